@@ -0,0 +1,221 @@
+use chrono::{Local, Utc};
+use log::{debug, warn};
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::recording;
+use crate::table::format_table;
+use crate::stop_monitor::{
+    ApiError, DisplayRow, LineStop, MonitoredVehicleJourney, extract_monitored_vehicle_journeys,
+    get_stop_monitor_responses, group_by_line_stop,
+};
+
+pub type DisplayLines = HashMap<DisplayRow, Vec<MonitoredVehicleJourney>>;
+
+// Everything needed to fetch arrivals, cloned into the background tasks
+#[derive(Clone)]
+pub struct FetchSettings {
+    pub rapid_line_to_parent_map: HashMap<String, String>,
+    pub stops_to_monitor: Vec<String>,
+    pub agency: String,
+    pub api_key: String,
+    // arrivals sooner than this many minutes at a stop are dropped
+    pub min_lead_minutes: HashMap<String, i64>,
+    // append every response to this recording, for `replay`
+    pub record: Option<PathBuf>,
+}
+
+impl FetchSettings {
+    pub fn new(config: &Config) -> Self {
+        FetchSettings {
+            rapid_line_to_parent_map: config.rapid_line_to_parent_line_map().clone(),
+            stops_to_monitor: config.stops_to_monitor().clone(),
+            agency: config.agency().to_string(),
+            // commands that fetch have already checked there's a key
+            api_key: config.api_key().unwrap_or_default().to_string(),
+            min_lead_minutes: config.min_lead_minutes().clone(),
+            record: None,
+        }
+    }
+}
+
+pub async fn fetch_display_lines(
+    client: &Client,
+    settings: &FetchSettings,
+) -> Result<DisplayLines, ApiError> {
+    let responses = get_stop_monitor_responses(
+        client,
+        &settings.stops_to_monitor,
+        &settings.agency,
+        &settings.api_key,
+    )
+    .await?;
+    if let Some(path) = &settings.record {
+        if let Err(e) = recording::append(path, Utc::now(), &responses) {
+            warn!("Unable to record responses to {:?}: {}", path, e);
+        }
+    }
+    let monitored_vehicle_journeys = responses
+        .into_iter()
+        .flat_map(|(_, stop_monitor_data)| extract_monitored_vehicle_journeys(stop_monitor_data))
+        .collect();
+    let mut stops = group_by_line_stop(monitored_vehicle_journeys);
+    drop_uncatchable(&mut stops, &settings.min_lead_minutes);
+    debug!("Received lines to display");
+    Ok(get_display_lines(stops, &settings.rapid_line_to_parent_map, true))
+}
+
+// Drop arrivals too soon to get to the stop for, they'd only take up the
+// slots of ones that can be caught
+pub fn drop_uncatchable(
+    stops: &mut HashMap<LineStop, Vec<MonitoredVehicleJourney>>,
+    min_lead_minutes: &HashMap<String, i64>,
+) {
+    if min_lead_minutes.is_empty() {
+        return;
+    }
+    for journeys in stops.values_mut() {
+        journeys.retain(|mvj| {
+            match (min_lead_minutes.get(mvj.stop_point_ref()), mvj.time_to_arrival()) {
+                (Some(min_lead), Some(tta)) => tta >= *min_lead,
+                _ => true,
+            }
+        });
+    }
+    stops.retain(|_, journeys| !journeys.is_empty());
+}
+
+pub fn get_display_lines(
+    stops: HashMap<LineStop, Vec<MonitoredVehicleJourney>>,
+    rapid_line_to_parent_map: &HashMap<String, String>,
+    use_line_to_parent_map: bool,
+) -> DisplayLines {
+    const DEFAULT_TIME_TO_ARRIVAL: i64 = 999;
+    let mut display: DisplayLines = HashMap::new();
+
+    for (line_stop, value) in stops.into_iter() {
+        let parent_line = if use_line_to_parent_map {
+            match rapid_line_to_parent_map.get(line_stop.line_ref.as_str()) {
+                Some(parent_line) => parent_line.clone(),
+                None => line_stop.line_ref.clone(),
+            }
+        } else {
+            line_stop.line_ref.clone()
+        };
+        // Keep each direction of a line on its own row
+        let row = DisplayRow::new(&parent_line, &line_stop.direction());
+
+        // Add new time to arrivals or create a new entry in display lines
+        for mvj in value {
+            if mvj.time_to_arrival().is_some() {
+                display.entry(row.clone()).or_default().push(mvj);
+            }
+        }
+    }
+
+    // Sort the values by time to arrival
+    for value in display.values_mut() {
+        value.sort_by_key(|a| a.time_to_arrival().unwrap_or(DEFAULT_TIME_TO_ARRIVAL));
+    }
+
+    display
+}
+
+// One arrival as printed by `fetch`
+#[derive(Serialize, Debug)]
+pub struct Arrival {
+    // line of the row it's shown on, the parent line for rapid lines
+    pub line: String,
+    pub line_ref: String,
+    pub direction: String,
+    pub destination: String,
+    pub stop: String,
+    pub stop_code: String,
+    pub minutes: i64,
+    // RFC 3339
+    pub expected: String,
+    pub has_location: bool,
+}
+
+// Arrivals in the order the board shows them, by row then soonest first
+pub fn arrivals(display_lines: &DisplayLines) -> Vec<Arrival> {
+    let mut rows: Vec<&DisplayRow> = display_lines.keys().collect();
+    rows.sort();
+    let mut arrivals = Vec::new();
+    for row in rows {
+        for mvj in &display_lines[row] {
+            let (minutes, expected) = match (mvj.time_to_arrival(), mvj.expected_arrival_time()) {
+                (Some(minutes), Some(expected)) => (minutes, expected),
+                _ => continue,
+            };
+            arrivals.push(Arrival {
+                line: row.line.clone(),
+                line_ref: mvj.line_ref.clone(),
+                direction: row.direction.clone(),
+                destination: mvj.destination_name().to_string(),
+                stop: mvj.stop_name().to_string(),
+                stop_code: mvj.stop_point_ref().to_string(),
+                minutes,
+                expected: expected.to_rfc3339(),
+                has_location: mvj.has_location(),
+            });
+        }
+    }
+    arrivals
+}
+
+// Arrival's fields, written out even when there are no arrivals
+const ARRIVAL_FIELDS: [&str; 9] = [
+    "line",
+    "line_ref",
+    "direction",
+    "destination",
+    "stop",
+    "stop_code",
+    "minutes",
+    "expected",
+    "has_location",
+];
+
+// Aligned columns for reading in a terminal, times in local time
+pub fn arrivals_table(arrivals: &[Arrival]) -> String {
+    let rows: Vec<Vec<String>> = arrivals
+        .iter()
+        .map(|a| {
+            let expected = chrono::DateTime::parse_from_rfc3339(&a.expected)
+                .map(|t| t.with_timezone(&Local).format("%H:%M:%S").to_string())
+                .unwrap_or_default();
+            let line = if a.line_ref == a.line {
+                a.line.clone()
+            } else {
+                format!("{} ({})", a.line, a.line_ref)
+            };
+            vec![
+                line,
+                a.direction.clone(),
+                a.destination.clone(),
+                format!("{} {}", a.stop_code, a.stop),
+                a.minutes.to_string(),
+                expected,
+                if a.has_location { "yes" } else { "no" }.to_string(),
+            ]
+        })
+        .collect();
+    format_table(
+        &["LINE", "DIR", "DESTINATION", "STOP", "MIN", "EXPECTED", "LOC"],
+        &rows,
+    )
+}
+
+pub fn arrivals_csv(arrivals: &[Arrival]) -> String {
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    // writing plain fields to memory can't fail
+    writer.write_record(ARRIVAL_FIELDS).expect("header written to CSV");
+    for arrival in arrivals {
+        writer.serialize(arrival).expect("arrival serializes to CSV");
+    }
+    String::from_utf8(writer.into_inner().expect("CSV written to memory")).expect("CSV is UTF-8")
+}
@@ -0,0 +1,320 @@
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use log::{debug, warn};
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::config::{AutoBrightnessConfig, LightSourceConfig};
+
+// How bright it is around the board, 0.0 for dark to 1.0 for full daylight.
+// None when there's no reading, the brightness then stays where it is.
+pub trait LightSource: Send {
+    fn light_level(&mut self, now: DateTime<Utc>) -> Option<f64>;
+}
+
+// Lux from a file holding a number, like the in_illuminance_input of an IIO
+// light sensor under /sys, or any file a script keeps up to date
+pub struct FileSensor {
+    path: PathBuf,
+    dark_lux: f64,
+    bright_lux: f64,
+}
+
+impl FileSensor {
+    pub fn new(path: PathBuf, dark_lux: f64, bright_lux: f64) -> Self {
+        FileSensor {
+            path,
+            dark_lux,
+            bright_lux,
+        }
+    }
+}
+
+impl LightSource for FileSensor {
+    fn light_level(&mut self, _now: DateTime<Utc>) -> Option<f64> {
+        let reading = match fs::read_to_string(&self.path) {
+            Ok(reading) => reading,
+            Err(e) => {
+                warn!("Unable to read light sensor {:?}: {}", self.path, e);
+                return None;
+            }
+        };
+        let lux: f64 = match reading.trim().parse() {
+            Ok(lux) => lux,
+            Err(_) => {
+                warn!("Light sensor {:?} reads `{}`, expected a number", self.path, reading.trim());
+                return None;
+            }
+        };
+        // eyes see light on a log scale, 10 to 100 lux is as big a change as
+        // 100 to 1000
+        let log = |lux: f64| (lux.max(0.0) + 1.0).log10();
+        let span = log(self.bright_lux) - log(self.dark_lux);
+        if span <= 0.0 {
+            return Some(1.0);
+        }
+        Some(((log(lux) - log(self.dark_lux)) / span).clamp(0.0, 1.0))
+    }
+}
+
+// Daylight worked out from the sun's height at a location, dark once the sun
+// is 6 degrees below the horizon and full once it's 10 degrees above
+pub struct SunPosition {
+    latitude: f64,
+    longitude: f64,
+}
+
+const DARK_ELEVATION: f64 = -6.0;
+const BRIGHT_ELEVATION: f64 = 10.0;
+
+impl SunPosition {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        SunPosition {
+            latitude,
+            longitude,
+        }
+    }
+
+    // Degrees above the horizon, from NOAA's general solar position equations
+    pub fn elevation(&self, now: DateTime<Utc>) -> f64 {
+        let hour = now.hour() as f64 + now.minute() as f64 / 60.0 + now.second() as f64 / 3600.0;
+        let gamma = 2.0 * PI / 365.0 * (now.ordinal() as f64 - 1.0 + (hour - 12.0) / 24.0);
+        let equation_of_time = 229.18
+            * (0.000075 + 0.001868 * gamma.cos()
+                - 0.032077 * gamma.sin()
+                - 0.014615 * (2.0 * gamma).cos()
+                - 0.040849 * (2.0 * gamma).sin());
+        let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+            - 0.006758 * (2.0 * gamma).cos()
+            + 0.000907 * (2.0 * gamma).sin()
+            - 0.002697 * (3.0 * gamma).cos()
+            + 0.00148 * (3.0 * gamma).sin();
+        // minutes past midnight in local solar time
+        let solar_time = hour * 60.0 + equation_of_time + 4.0 * self.longitude;
+        let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+        let latitude = self.latitude.to_radians();
+        let cos_zenith = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+        90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+    }
+}
+
+impl LightSource for SunPosition {
+    fn light_level(&mut self, now: DateTime<Utc>) -> Option<f64> {
+        let elevation = self.elevation(now);
+        Some(((elevation - DARK_ELEVATION) / (BRIGHT_ELEVATION - DARK_ELEVATION)).clamp(0.0, 1.0))
+    }
+}
+
+// Always the same, for trying settings out
+pub struct StaticLevel {
+    level: f64,
+}
+
+impl StaticLevel {
+    pub fn new(level: f64) -> Self {
+        StaticLevel { level }
+    }
+}
+
+impl LightSource for StaticLevel {
+    fn light_level(&mut self, _now: DateTime<Utc>) -> Option<f64> {
+        Some(self.level)
+    }
+}
+
+pub fn light_source(config: &LightSourceConfig) -> Box<dyn LightSource> {
+    match config {
+        LightSourceConfig::File {
+            path,
+            dark_lux,
+            bright_lux,
+        } => Box::new(FileSensor::new(path.clone(), *dark_lux, *bright_lux)),
+        LightSourceConfig::Sun {
+            latitude,
+            longitude,
+        } => Box::new(SunPosition::new(*latitude, *longitude)),
+        LightSourceConfig::Static { level } => Box::new(StaticLevel::new(*level)),
+    }
+}
+
+// Turns light levels into a brightness between min and max, easing towards
+// each new reading so the board doesn't jump when a cloud goes over
+pub struct BrightnessController {
+    source: Box<dyn LightSource>,
+    min: f64,
+    max: f64,
+    smoothing: Duration,
+    poll: Duration,
+    target: f64,
+    // None until the first reading, which is taken as is rather than eased
+    // into so the board doesn't start out at full brightness in the night
+    current: Option<f64>,
+    last_poll: Option<Instant>,
+    last_update: Instant,
+}
+
+impl BrightnessController {
+    pub fn new(config: &AutoBrightnessConfig, source: Box<dyn LightSource>) -> Self {
+        BrightnessController {
+            source,
+            min: config.min() as f64,
+            max: config.max() as f64,
+            smoothing: Duration::from_secs_f64(config.smoothing_secs()),
+            poll: Duration::from_secs_f64(config.poll_secs()),
+            target: config.max() as f64,
+            current: None,
+            last_poll: None,
+            last_update: Instant::now(),
+        }
+    }
+
+    // Start from another controller's brightness, so a reload doesn't jump
+    pub fn continue_from(mut self, previous: &BrightnessController) -> Self {
+        self.current = previous.current;
+        self
+    }
+
+    // Brightness percent for the frame being drawn now
+    pub fn update(&mut self, now: Instant) -> u8 {
+        if self.last_poll.is_none_or(|last| now.duration_since(last) >= self.poll) {
+            self.last_poll = Some(now);
+            if let Some(level) = self.source.light_level(Utc::now()) {
+                let target = self.min + (self.max - self.min) * level;
+                if (target - self.target).abs() >= 1.0 {
+                    debug!("Light level {:.2}, brightness heading to {:.0}%", level, target);
+                }
+                self.target = target;
+            }
+        }
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+        let current = match self.current {
+            Some(current) if !self.smoothing.is_zero() => {
+                // exponential easing, about two thirds of the way there after
+                // each smoothing period
+                let step = 1.0 - (-elapsed / self.smoothing.as_secs_f64()).exp();
+                current + (self.target - current) * step
+            }
+            _ => self.target,
+        };
+        self.current = Some(current);
+        current.round().clamp(0.0, 100.0) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_checker::ConfigChecker;
+    use crate::test_util::TempDir;
+    use chrono::TimeZone;
+    use std::sync::{Arc, Mutex};
+    use yaml_rust2::YamlLoader;
+
+    fn sensor(dir: &TempDir, reading: &str, dark_lux: f64, bright_lux: f64) -> FileSensor {
+        let path = dir.join("in_illuminance_input");
+        fs::write(&path, reading).unwrap();
+        FileSensor::new(path, dark_lux, bright_lux)
+    }
+
+    fn level(sensor: &mut FileSensor) -> Option<f64> {
+        sensor.light_level(Utc::now())
+    }
+
+    #[test]
+    fn lux_maps_to_level_on_a_log_scale() {
+        let dir = TempDir::new("lux_scale");
+        for (lux, expected) in [("10", 0.0), ("1000", 1.0), ("2", 0.0), ("50000", 1.0)] {
+            assert_eq!(level(&mut sensor(&dir, lux, 10.0, 1000.0)), Some(expected), "{} lux", lux);
+        }
+        // halfway on a log scale is nowhere near halfway in lux
+        let middle = level(&mut sensor(&dir, " 100\n", 10.0, 1000.0)).unwrap();
+        let expected = (101f64.log10() - 11f64.log10()) / (1001f64.log10() - 11f64.log10());
+        assert!((middle - expected).abs() < 1e-9, "{} != {}", middle, expected);
+        assert!((0.45..0.55).contains(&middle));
+    }
+
+    #[test]
+    fn bright_lux_not_above_dark_lux_is_full_light() {
+        let dir = TempDir::new("lux_full_light");
+        assert_eq!(level(&mut sensor(&dir, "0", 500.0, 500.0)), Some(1.0));
+        assert_eq!(level(&mut sensor(&dir, "0", 1000.0, 10.0)), Some(1.0));
+    }
+
+    #[test]
+    fn unreadable_sensor_has_no_level() {
+        let dir = TempDir::new("lux_unreadable");
+        assert_eq!(level(&mut sensor(&dir, "bright", 10.0, 1000.0)), None);
+        assert_eq!(level(&mut sensor(&dir, "", 10.0, 1000.0)), None);
+        let mut missing = FileSensor::new(PathBuf::from("/nonexistent/in_illuminance_input"), 10.0, 1000.0);
+        assert_eq!(level(&mut missing), None);
+    }
+
+    #[test]
+    fn sun_elevation_matches_noaa() {
+        // NOAA's calculator puts the June solstice sun at 73.44 degrees at
+        // solar noon in Boulder, and sunrise (-0.83 degrees, allowing for
+        // refraction) in San Francisco at 05:48 PDT
+        let boulder = SunPosition::new(40.0, -105.0);
+        let noon = boulder.elevation(Utc.with_ymd_and_hms(2010, 6, 21, 19, 2, 0).unwrap());
+        assert!((noon - 73.44).abs() < 0.1, "{}", noon);
+        let san_francisco = SunPosition::new(37.7749, -122.4194);
+        let sunrise = san_francisco.elevation(Utc.with_ymd_and_hms(2026, 6, 21, 12, 48, 0).unwrap());
+        assert!((sunrise + 0.83).abs() < 0.25, "{}", sunrise);
+        let midnight = san_francisco.elevation(Utc.with_ymd_and_hms(2026, 6, 21, 8, 0, 0).unwrap());
+        assert!(midnight < DARK_ELEVATION, "{}", midnight);
+    }
+
+    // A light source the test can change the level of
+    #[derive(Clone)]
+    struct Dial(Arc<Mutex<Option<f64>>>);
+
+    impl LightSource for Dial {
+        fn light_level(&mut self, _now: DateTime<Utc>) -> Option<f64> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn controller(level: f64) -> (BrightnessController, Dial) {
+        let source = "source: static\nmin: 10\nmax: 90\nsmoothing_secs: 10\npoll_secs: 1\n";
+        let config = &YamlLoader::load_from_str(source).unwrap()[0];
+        let config = AutoBrightnessConfig::new(config, &mut ConfigChecker::new(source)).unwrap();
+        let dial = Dial(Arc::new(Mutex::new(Some(level))));
+        (BrightnessController::new(&config, Box::new(dial.clone())), dial)
+    }
+
+    #[test]
+    fn first_reading_is_not_eased() {
+        let (mut dark, _) = controller(0.0);
+        assert_eq!(dark.update(Instant::now()), 10);
+        let (mut half, _) = controller(0.5);
+        assert_eq!(half.update(Instant::now()), 50);
+    }
+
+    #[test]
+    fn eases_exponentially_towards_the_target() {
+        let (mut brightness, dial) = controller(0.0);
+        let start = Instant::now();
+        assert_eq!(brightness.update(start), 10);
+        *dial.0.lock().unwrap() = Some(1.0);
+        // 90 - 80e^(-t/10) on the way from 10 to 90
+        for (secs, expected) in [(1, 18), (11, 63), (21, 80), (61, 90)] {
+            assert_eq!(brightness.update(start + Duration::from_secs(secs)), expected, "after {}s", secs);
+        }
+    }
+
+    #[test]
+    fn keeps_heading_to_the_last_target_without_a_reading() {
+        let (mut brightness, dial) = controller(1.0);
+        let start = Instant::now();
+        assert_eq!(brightness.update(start), 90);
+        *dial.0.lock().unwrap() = None;
+        assert_eq!(brightness.update(start + Duration::from_secs(30)), 90);
+        // and new readings wait for the next poll
+        *dial.0.lock().unwrap() = Some(0.0);
+        assert_eq!(brightness.update(start + Duration::from_millis(30_500)), 90);
+        assert!(brightness.update(start + Duration::from_secs(32)) < 90);
+    }
+}
@@ -0,0 +1,862 @@
+use chrono::{
+    Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use log::{debug, warn};
+use reqwest::Client;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// Where events are read from
+#[derive(Clone, Debug, PartialEq)]
+pub enum CalendarSource {
+    File(PathBuf),
+    // fetched every refresh and kept in `cache`, which is read at startup and
+    // whenever the URL can't be reached
+    Url { url: String, cache: PathBuf },
+}
+
+// Where the agenda goes on the board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CalendarSlot {
+    // on the bottom row as a message
+    Ticker,
+    // in place of the clock
+    Header,
+}
+
+// When an event starts, in local time. Times with a TZID are taken to be in
+// the board's own time zone, with a warning when the TZID names another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EventStart {
+    AllDay(NaiveDate),
+    At(NaiveDateTime),
+}
+
+impl EventStart {
+    fn date(&self) -> NaiveDate {
+        match self {
+            EventStart::AllDay(date) => *date,
+            EventStart::At(at) => at.date(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+// The parts of an RRULE home calendars use: FREQ, INTERVAL, COUNT, UNTIL,
+// BYDAY (with an ordinal for monthly and yearly rules, e.g. -1FR), BYMONTHDAY
+// and BYMONTH
+#[derive(Clone, Debug)]
+struct RecurrenceRule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    // in local time like the start, a date takes in the whole day
+    until: Option<EventStart>,
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CalendarEvent {
+    pub summary: String,
+    start: EventStart,
+    // whole days for all day events
+    duration: Duration,
+    rule: Option<RecurrenceRule>,
+    // occurrences left out, by the day they'd have started
+    exdates: HashSet<NaiveDate>,
+    uid: Option<String>,
+    // set when this event replaces one occurrence of a recurring event
+    recurrence_id: Option<NaiveDate>,
+    // TZID of the start, if it had one
+    time_zone: Option<String>,
+}
+
+// One time an event happens
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occurrence {
+    pub summary: String,
+    pub date: NaiveDate,
+    // None for all day events
+    pub time: Option<NaiveTime>,
+    pub end: NaiveDateTime,
+}
+
+// A property's name, its parameters and its value
+type ContentLine = (String, Vec<(String, String)>, String);
+
+// Unfolded content lines of an ICS file
+fn content_lines(source: &str) -> Vec<ContentLine> {
+    let mut unfolded: Vec<String> = Vec::new();
+    for line in source.lines() {
+        // a line starting with a space or tab carries on the one before
+        match (line.strip_prefix([' ', '\t']), unfolded.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => unfolded.push(line.to_string()),
+        }
+    }
+    unfolded
+        .iter()
+        .filter_map(|line| {
+            // the value starts at the first colon outside quoted parameters
+            let mut in_quotes = false;
+            let colon = line.char_indices().find_map(|(i, c)| {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                }
+                (c == ':' && !in_quotes).then_some(i)
+            })?;
+            let mut parts = line[..colon].split(';');
+            let name = parts.next()?.trim().to_uppercase();
+            let params = parts
+                .filter_map(|param| param.split_once('='))
+                .map(|(key, value)| (key.to_uppercase(), value.trim_matches('"').to_string()))
+                .collect();
+            Some((name, params, line[colon + 1..].to_string()))
+        })
+        .collect()
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push(' '),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+// 20261019, 20261019T143000 (floating, local) or 20261019T213000Z (UTC)
+fn parse_start(value: &str, params: &[(String, String)]) -> Option<EventStart> {
+    let value = value.trim();
+    let is_date = params.iter().any(|(key, value)| key == "VALUE" && value == "DATE");
+    if is_date || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(EventStart::AllDay);
+    }
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let at = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some(EventStart::At(if utc {
+        Utc.from_utc_datetime(&at).with_timezone(&Local).naive_local()
+    } else {
+        at
+    }))
+}
+
+// P1D, PT1H30M, P1W
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().trim_start_matches('+');
+    let value = value.strip_prefix('P')?;
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                duration += match unit {
+                    'W' => Duration::weeks(n),
+                    'D' => Duration::days(n),
+                    'H' => Duration::hours(n),
+                    'M' => Duration::minutes(n),
+                    'S' => Duration::seconds(n),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(duration)
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    use Weekday::*;
+    Some(match day {
+        "MO" => Mon,
+        "TU" => Tue,
+        "WE" => Wed,
+        "TH" => Thu,
+        "FR" => Fri,
+        "SA" => Sat,
+        "SU" => Sun,
+        _ => return None,
+    })
+}
+
+fn parse_rule(value: &str) -> Option<RecurrenceRule> {
+    let mut rule = RecurrenceRule {
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+        by_month_day: Vec::new(),
+        by_month: Vec::new(),
+    };
+    let mut frequency = None;
+    for part in value.split(';') {
+        let (key, value) = part.split_once('=')?;
+        let list = || value.split(',').map(str::trim);
+        match key.trim().to_uppercase().as_str() {
+            "FREQ" => {
+                frequency = Some(match value.trim().to_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return None,
+                })
+            }
+            "INTERVAL" => rule.interval = value.trim().parse().ok().filter(|n| *n > 0)?,
+            "COUNT" => rule.count = Some(value.trim().parse().ok()?),
+            "UNTIL" => rule.until = Some(parse_start(value, &[])?),
+            "BYDAY" => {
+                for day in list() {
+                    if !day.is_ascii() {
+                        return None;
+                    }
+                    let split = day.len().checked_sub(2)?;
+                    let ordinal = match &day[..split] {
+                        "" => None,
+                        ordinal => Some(ordinal.trim_start_matches('+').parse().ok()?),
+                    };
+                    rule.by_day.push((ordinal, parse_weekday(&day[split..].to_uppercase())?));
+                }
+            }
+            "BYMONTHDAY" => {
+                for day in list() {
+                    rule.by_month_day.push(day.parse().ok()?);
+                }
+            }
+            "BYMONTH" => {
+                for month in list() {
+                    rule.by_month.push(month.parse().ok()?);
+                }
+            }
+            // WKST and the like make no difference to the rules above
+            _ => {}
+        }
+    }
+    rule.frequency = frequency?;
+    Some(rule)
+}
+
+fn days_in_month(date: NaiveDate) -> u32 {
+    let first = date.with_day(1).unwrap_or(date);
+    let next = first.checked_add_months(Months::new(1)).unwrap_or(first);
+    (next - first).num_days() as u32
+}
+
+fn months_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    (to.year() as i64 - from.year() as i64) * 12 + to.month() as i64 - from.month() as i64
+}
+
+impl RecurrenceRule {
+    // Day of the month matches BYMONTHDAY or BYDAY, or the day the event
+    // started on when there are neither
+    fn matches_day_of_month(&self, start: NaiveDate, day: NaiveDate) -> bool {
+        if !self.by_month_day.is_empty() {
+            let last = days_in_month(day) as i32;
+            return self.by_month_day.iter().any(|d| {
+                let d = if *d < 0 { last + d + 1 } else { *d };
+                d == day.day() as i32
+            });
+        }
+        if !self.by_day.is_empty() {
+            let nth = (day.day() as i32 - 1) / 7 + 1;
+            let nth_last = (days_in_month(day) as i32 - day.day() as i32) / 7 + 1;
+            return self.by_day.iter().any(|(ordinal, weekday)| {
+                *weekday == day.weekday()
+                    && match ordinal {
+                        None => true,
+                        Some(n) if *n > 0 => nth == *n,
+                        Some(n) => nth_last == -n,
+                    }
+            });
+        }
+        day.day() == start.day()
+    }
+
+    // Whether the rule puts an occurrence on this day, not counting COUNT
+    fn matches(&self, start: EventStart, day: NaiveDate) -> bool {
+        // UNTIL is compared with the occurrence's start, not just its day, so
+        // a UTC UNTIL doesn't gain or lose the last occurrence near midnight
+        let past_until = match (self.until, start) {
+            (None, _) => false,
+            (Some(EventStart::AllDay(until)), _) => day > until,
+            (Some(EventStart::At(until)), EventStart::At(at)) => day.and_time(at.time()) > until,
+            (Some(EventStart::At(until)), EventStart::AllDay(_)) => day > until.date(),
+        };
+        let start = start.date();
+        if day < start || past_until {
+            return false;
+        }
+        if !self.by_month.is_empty() && !self.by_month.contains(&day.month()) {
+            return false;
+        }
+        let interval = self.interval as i64;
+        match self.frequency {
+            Frequency::Daily => {
+                (day - start).num_days() % interval == 0
+                    && (self.by_day.is_empty() || self.by_day.iter().any(|(_, d)| *d == day.weekday()))
+            }
+            Frequency::Weekly => {
+                let week = |date: NaiveDate| date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                let weeks = (week(day) - week(start)).num_days() / 7;
+                weeks % interval == 0
+                    && if self.by_day.is_empty() {
+                        day.weekday() == start.weekday()
+                    } else {
+                        self.by_day.iter().any(|(_, d)| *d == day.weekday())
+                    }
+            }
+            Frequency::Monthly => {
+                months_between(start, day) % interval == 0 && self.matches_day_of_month(start, day)
+            }
+            Frequency::Yearly => {
+                (day.year() - start.year()) as i64 % interval == 0
+                    && (!self.by_month.is_empty() || day.month() == start.month())
+                    && self.matches_day_of_month(start, day)
+            }
+        }
+    }
+}
+
+// Events in an ICS file. Cancelled events and ones that can't be read are left
+// out, the rest of the file is still used.
+pub fn parse_ics(source: &str) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+    let mut event: Option<CalendarEvent> = None;
+    // kept aside until the end of the event, the start could come after them
+    let mut start: Option<EventStart> = None;
+    let mut end: Option<EventStart> = None;
+    let mut cancelled = false;
+    for (name, params, value) in content_lines(source) {
+        match (name.as_str(), &mut event) {
+            ("BEGIN", None) if value.trim().eq_ignore_ascii_case("VEVENT") => {
+                event = Some(CalendarEvent {
+                    summary: String::new(),
+                    start: EventStart::AllDay(NaiveDate::default()),
+                    duration: Duration::zero(),
+                    rule: None,
+                    exdates: HashSet::new(),
+                    uid: None,
+                    recurrence_id: None,
+                    time_zone: None,
+                });
+                start = None;
+                end = None;
+                cancelled = false;
+            }
+            ("END", Some(_)) if value.trim().eq_ignore_ascii_case("VEVENT") => {
+                let mut finished = match event.take() {
+                    Some(finished) => finished,
+                    None => continue,
+                };
+                finished.start = match start {
+                    Some(start) if !cancelled => start,
+                    _ => continue,
+                };
+                finished.duration = match (finished.start, end) {
+                    (EventStart::AllDay(start), Some(end)) => (end.date() - start).max(Duration::days(1)),
+                    (EventStart::At(start), Some(EventStart::At(end))) => (end - start).max(Duration::zero()),
+                    (EventStart::AllDay(_), None) if finished.duration.is_zero() => Duration::days(1),
+                    _ => finished.duration,
+                };
+                events.push(finished);
+            }
+            ("SUMMARY", Some(event)) => event.summary = unescape(value.trim()),
+            ("UID", Some(event)) => event.uid = Some(value.trim().to_string()),
+            ("DTSTART", Some(event)) => {
+                start = parse_start(&value, &params);
+                event.time_zone = params.iter().find(|(key, _)| key == "TZID").map(|(_, zone)| zone.clone());
+                if start.is_none() {
+                    warn!("Skipping calendar event with a start of `{}`", value);
+                }
+            }
+            ("DTEND", Some(_)) => end = parse_start(&value, &params),
+            ("DURATION", Some(event)) => event.duration = parse_duration(&value).unwrap_or_default(),
+            ("RRULE", Some(event)) => {
+                event.rule = parse_rule(&value);
+                if event.rule.is_none() {
+                    warn!("Ignoring calendar rule `{}`, only the first occurrence is shown", value);
+                }
+            }
+            ("EXDATE", Some(event)) => {
+                for exdate in value.split(',') {
+                    if let Some(exdate) = parse_start(exdate, &params) {
+                        event.exdates.insert(exdate.date());
+                    }
+                }
+            }
+            ("RECURRENCE-ID", Some(event)) => {
+                event.recurrence_id = parse_start(&value, &params).map(|id| id.date())
+            }
+            ("STATUS", Some(_)) => cancelled = value.trim().eq_ignore_ascii_case("CANCELLED"),
+            _ => {}
+        }
+    }
+    for zone in other_time_zones(&events, local_time_zone().as_deref()) {
+        warn!("Calendar times in {} are shown as if they were local time", zone);
+    }
+    events
+}
+
+// The board's own time zone by name, from TZ or where /etc/localtime points
+fn local_time_zone() -> Option<String> {
+    if let Ok(zone) = std::env::var("TZ") {
+        return Some(zone.trim_start_matches(':').to_string());
+    }
+    let link = fs::read_link("/etc/localtime").ok()?;
+    Some(link.to_str()?.split_once("zoneinfo/")?.1.to_string())
+}
+
+// TZIDs the events start in other than the board's own, all of them when the
+// board's isn't known
+fn other_time_zones<'a>(events: &'a [CalendarEvent], local: Option<&str>) -> BTreeSet<&'a str> {
+    events
+        .iter()
+        .filter_map(|event| event.time_zone.as_deref())
+        .filter(|zone| Some(*zone) != local)
+        .collect()
+}
+
+// Every occurrence that's on at some point between `from` and `to`, both
+// whole days, in order
+pub fn occurrences(events: &[CalendarEvent], from: NaiveDate, to: NaiveDate) -> Vec<Occurrence> {
+    // occurrences moved or changed by an event of their own
+    let replaced: HashSet<(&str, NaiveDate)> = events
+        .iter()
+        .filter_map(|event| Some((event.uid.as_deref()?, event.recurrence_id?)))
+        .collect();
+    let mut found = Vec::new();
+    for event in events {
+        let start = event.start.date();
+        let mut add = |day: NaiveDate| {
+            let (date_time, time) = match event.start {
+                EventStart::AllDay(_) => (day.and_time(NaiveTime::MIN), None),
+                EventStart::At(at) => (day.and_time(at.time()), Some(at.time())),
+            };
+            let end = date_time + event.duration;
+            let last_day = match time {
+                // all day events end at midnight at the start of the day after
+                None => end.date().pred_opt().unwrap_or(day),
+                Some(_) if end > date_time => (end - Duration::seconds(1)).date(),
+                Some(_) => day,
+            };
+            if day > to || last_day < from || event.exdates.contains(&day) {
+                return;
+            }
+            // one that started before the window shows from its first day, as
+            // ongoing rather than at its start time
+            found.push(Occurrence {
+                summary: event.summary.clone(),
+                date: day.max(from),
+                time: time.filter(|_| day >= from),
+                end,
+            });
+        };
+        let rule = match (&event.rule, event.recurrence_id) {
+            (Some(rule), None) => rule,
+            _ => {
+                add(start);
+                continue;
+            }
+        };
+        // walked a day at a time, from the start when there's a COUNT to keep
+        // track of, otherwise from just before the window
+        let mut count = 0;
+        let mut day = match rule.count {
+            Some(_) => start,
+            None => start.max(from - Duration::days(event.duration.num_days() + 1)),
+        };
+        while day <= to && rule.count.is_none_or(|max| count < max) {
+            if rule.matches(event.start, day) {
+                count += 1;
+                let replaced = event.uid.as_deref().is_some_and(|uid| replaced.contains(&(uid, day)));
+                if !replaced {
+                    add(day);
+                }
+            }
+            day = match day.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+    found.sort_by(|a, b| (a.date, a.time, &a.summary).cmp(&(b.date, b.time, &b.summary)));
+    found.dedup();
+    found
+}
+
+// One line for the board: "Today: Trash day, 14:30 Dentist | Tomorrow: Book
+// club". Events that have ended are left off.
+pub fn agenda(occurrences: &[Occurrence], now: NaiveDateTime) -> Option<String> {
+    let today = now.date();
+    let mut days: Vec<(NaiveDate, Vec<String>)> = Vec::new();
+    for occurrence in occurrences {
+        if occurrence.end <= now {
+            continue;
+        }
+        let text = match occurrence.time {
+            Some(time) => format!("{} {}", time.format("%H:%M"), occurrence.summary),
+            None => occurrence.summary.clone(),
+        };
+        match days.last_mut() {
+            Some((date, texts)) if *date == occurrence.date => texts.push(text),
+            _ => days.push((occurrence.date, vec![text])),
+        }
+    }
+    let parts: Vec<String> = days
+        .into_iter()
+        .map(|(date, texts)| {
+            let day = match (date - today).num_days() {
+                0 => "Today".to_string(),
+                1 => "Tomorrow".to_string(),
+                2..=6 => date.format("%a").to_string(),
+                _ => date.format("%b %-d").to_string(),
+            };
+            format!("{}: {}", day, texts.join(", "))
+        })
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" | "))
+}
+
+// Name for the cached copy of a URL, readable enough to tell which is which
+pub fn cache_file_name(url: &str) -> String {
+    let name: String = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(100)
+        .collect();
+    format!("{}.ics", name.trim_end_matches("_ics"))
+}
+
+// Events from every source, with URLs fetched again once they're due
+pub struct Calendar {
+    sources: Vec<CalendarSource>,
+    refresh: std::time::Duration,
+    // events by source, kept when a fetch fails
+    events: Vec<Vec<CalendarEvent>>,
+    fetched: Vec<Option<Instant>>,
+}
+
+impl Calendar {
+    pub fn new(sources: Vec<CalendarSource>, refresh: std::time::Duration) -> Self {
+        let n = sources.len();
+        Calendar {
+            sources,
+            refresh,
+            events: vec![Vec::new(); n],
+            fetched: vec![None; n],
+        }
+    }
+
+    // Read the files again and fetch the URLs that are due. A URL that can't
+    // be fetched falls back to its cache.
+    pub async fn refresh(&mut self, client: &Client) {
+        for i in 0..self.sources.len() {
+            let source = self.sources[i].clone();
+            match source {
+                CalendarSource::File(path) => {
+                    if let Some(events) = read_ics(&path) {
+                        self.events[i] = events;
+                    }
+                }
+                CalendarSource::Url { url, cache } => {
+                    if self.fetched[i].is_some_and(|fetched| fetched.elapsed() < self.refresh) {
+                        continue;
+                    }
+                    match fetch_ics(client, &url).await {
+                        Ok(source) => {
+                            self.events[i] = parse_ics(&source);
+                            debug!("Fetched {} calendar events from {}", self.events[i].len(), url);
+                            if let Err(e) = write_cache(&cache, &source) {
+                                warn!("Unable to cache calendar {} in {}: {}", url, cache.display(), e);
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Unable to fetch calendar {}: {}", url, e.without_url());
+                            // only worth reading when there's nothing better
+                            if self.fetched[i].is_none() {
+                                if let Some(events) = read_ics(&cache) {
+                                    self.events[i] = events;
+                                }
+                            }
+                        }
+                    }
+                    self.fetched[i] = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    // Today's events and those in the next `days` days
+    pub fn agenda(&self, now: NaiveDateTime, days: u32) -> Option<String> {
+        let events: Vec<CalendarEvent> = self.events.iter().flatten().cloned().collect();
+        let today = now.date();
+        agenda(&occurrences(&events, today, today + Duration::days(days as i64)), now)
+    }
+}
+
+fn read_ics(path: &Path) -> Option<Vec<CalendarEvent>> {
+    match fs::read_to_string(path) {
+        Ok(source) => Some(parse_ics(&source)),
+        Err(e) => {
+            warn!("Unable to read calendar {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+async fn fetch_ics(client: &Client, url: &str) -> Result<String, reqwest::Error> {
+    client.get(url).send().await?.error_for_status()?.text().await
+}
+
+fn write_cache(cache: &Path, source: &str) -> std::io::Result<()> {
+    if let Some(dir) = cache.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(cache, source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    // Events given as their lines between BEGIN:VEVENT and END:VEVENT
+    fn calendar(events: &[&str]) -> Vec<CalendarEvent> {
+        let mut source = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n".to_string();
+        for event in events {
+            let lines = event.trim().replace('\n', "\r\n");
+            source.push_str(&format!("BEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\n", lines));
+        }
+        source.push_str("END:VCALENDAR\r\n");
+        parse_ics(&source)
+    }
+
+    fn dates(events: &[&str], from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        occurrences(&calendar(events), from, to).iter().map(|o| o.date).collect()
+    }
+
+    #[test]
+    fn weekly_by_day_stops_after_count() {
+        // 2026-10-06 is a Tuesday
+        let event = "SUMMARY:Swim\nDTSTART:20261006T070000\nRRULE:FREQ=WEEKLY;BYDAY=TU,TH;COUNT=5";
+        assert_eq!(
+            dates(&[event], date(2026, 10, 1), date(2026, 11, 30)),
+            [date(2026, 10, 6), date(2026, 10, 8), date(2026, 10, 13), date(2026, 10, 15), date(2026, 10, 20)]
+        );
+        // the count is from the first occurrence, not the window
+        assert_eq!(
+            dates(&[event], date(2026, 10, 14), date(2026, 11, 30)),
+            [date(2026, 10, 15), date(2026, 10, 20)]
+        );
+    }
+
+    #[test]
+    fn interval_skips_weeks_and_days() {
+        let fortnightly = "SUMMARY:Recycling\nDTSTART;VALUE=DATE:20261005\nRRULE:FREQ=WEEKLY;INTERVAL=2";
+        assert_eq!(
+            dates(&[fortnightly], date(2026, 10, 1), date(2026, 11, 10)),
+            [date(2026, 10, 5), date(2026, 10, 19), date(2026, 11, 2)]
+        );
+        let every_third_day = "SUMMARY:Water plants\nDTSTART;VALUE=DATE:20261001\nRRULE:FREQ=DAILY;INTERVAL=3";
+        assert_eq!(
+            dates(&[every_third_day], date(2026, 10, 5), date(2026, 10, 12)),
+            [date(2026, 10, 7), date(2026, 10, 10)]
+        );
+    }
+
+    #[test]
+    fn until_is_the_last_day() {
+        let event = "SUMMARY:Visit\nDTSTART;VALUE=DATE:20261007\nRRULE:FREQ=DAILY;UNTIL=20261010";
+        assert_eq!(
+            dates(&[event], date(2026, 10, 1), date(2026, 10, 31)),
+            [date(2026, 10, 7), date(2026, 10, 8), date(2026, 10, 9), date(2026, 10, 10)]
+        );
+    }
+
+    #[test]
+    fn utc_until_is_compared_with_the_start_time() {
+        // worked out from local times so the rule means the same in any zone
+        let utc = |at: NaiveDateTime| {
+            Local.from_local_datetime(&at).unwrap().with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string()
+        };
+        let event = |until: NaiveDateTime| {
+            format!("SUMMARY:Class\nDTSTART:20261007T190000\nRRULE:FREQ=DAILY;UNTIL={}", utc(until))
+        };
+        // an hour before the last day's class leaves that class out
+        let before = event(date(2026, 10, 10).and_time(time(18, 0)));
+        assert_eq!(
+            dates(&[&before], date(2026, 10, 1), date(2026, 10, 31)),
+            [date(2026, 10, 7), date(2026, 10, 8), date(2026, 10, 9)]
+        );
+        let at = event(date(2026, 10, 10).and_time(time(19, 0)));
+        assert_eq!(
+            dates(&[&at], date(2026, 10, 1), date(2026, 10, 31)),
+            [date(2026, 10, 7), date(2026, 10, 8), date(2026, 10, 9), date(2026, 10, 10)]
+        );
+    }
+
+    #[test]
+    fn monthly_by_day_with_ordinals() {
+        let last_friday = "SUMMARY:Game night\nDTSTART:20261030T190000\nRRULE:FREQ=MONTHLY;BYDAY=-1FR";
+        assert_eq!(
+            dates(&[last_friday], date(2026, 10, 1), date(2026, 12, 31)),
+            [date(2026, 10, 30), date(2026, 11, 27), date(2026, 12, 25)]
+        );
+        let second_tuesday =
+            "SUMMARY:Board meeting\nDTSTART:20261013T180000\nRRULE:FREQ=MONTHLY;BYDAY=2TU;COUNT=2";
+        assert_eq!(
+            dates(&[second_tuesday], date(2026, 10, 1), date(2027, 1, 31)),
+            [date(2026, 10, 13), date(2026, 11, 10)]
+        );
+        let yearly = "SUMMARY:Birthday\nDTSTART;VALUE=DATE:20200229\nRRULE:FREQ=YEARLY";
+        assert_eq!(dates(&[yearly], date(2026, 1, 1), date(2028, 12, 31)), [date(2028, 2, 29)]);
+    }
+
+    #[test]
+    fn all_day_events_have_no_time_and_end_at_midnight() {
+        let events = [
+            "SUMMARY:Trash day\nDTSTART;VALUE=DATE:20261019",
+            "SUMMARY:Camping\nDTSTART;VALUE=DATE:20261017\nDTEND;VALUE=DATE:20261021",
+            "SUMMARY:Dentist\nDTSTART:20261019T143000\nDTEND:20261019T153000",
+            "SUMMARY:Call\nDTSTART:20261019T090000\nDURATION:PT45M",
+        ];
+        let found = occurrences(&calendar(&events), date(2026, 10, 19), date(2026, 10, 19));
+        let midnight = date(2026, 10, 20).and_time(NaiveTime::MIN);
+        assert_eq!(
+            found,
+            [
+                // started before the window, so shown as ongoing from its first day
+                Occurrence {
+                    summary: "Camping".to_string(),
+                    date: date(2026, 10, 19),
+                    time: None,
+                    end: date(2026, 10, 21).and_time(NaiveTime::MIN),
+                },
+                Occurrence {
+                    summary: "Trash day".to_string(),
+                    date: date(2026, 10, 19),
+                    time: None,
+                    end: midnight,
+                },
+                Occurrence {
+                    summary: "Call".to_string(),
+                    date: date(2026, 10, 19),
+                    time: Some(time(9, 0)),
+                    end: date(2026, 10, 19).and_time(time(9, 45)),
+                },
+                Occurrence {
+                    summary: "Dentist".to_string(),
+                    date: date(2026, 10, 19),
+                    time: Some(time(14, 30)),
+                    end: date(2026, 10, 19).and_time(time(15, 30)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn utc_times_move_to_local_and_tzid_times_stay_as_written() {
+        let events = [
+            "SUMMARY:Standup\nDTSTART:20261019T163000Z\nDTEND:20261019T164500Z",
+            "SUMMARY:Piano\nDTSTART;TZID=America/Los_Angeles:20261019T170000",
+        ];
+        let found = occurrences(&calendar(&events), date(2026, 10, 18), date(2026, 10, 20));
+        let local = Utc
+            .from_utc_datetime(&date(2026, 10, 19).and_time(time(16, 30)))
+            .with_timezone(&Local)
+            .naive_local();
+        let standup = found.iter().find(|o| o.summary == "Standup").unwrap();
+        assert_eq!((standup.date, standup.time), (local.date(), Some(local.time())));
+        let piano = found.iter().find(|o| o.summary == "Piano").unwrap();
+        assert_eq!((piano.date, piano.time), (date(2026, 10, 19), Some(time(17, 0))));
+    }
+
+    #[test]
+    fn names_time_zones_other_than_the_boards() {
+        let events = calendar(&[
+            "SUMMARY:Piano\nDTSTART;TZID=America/Los_Angeles:20261019T170000",
+            "SUMMARY:Call home\nDTSTART;TZID=Europe/London:20261020T090000",
+            "SUMMARY:Standup\nDTSTART:20261019T163000Z",
+        ]);
+        assert_eq!(other_time_zones(&events, Some("America/Los_Angeles")), BTreeSet::from(["Europe/London"]));
+        assert_eq!(other_time_zones(&events, None), BTreeSet::from(["America/Los_Angeles", "Europe/London"]));
+    }
+
+    #[test]
+    fn exdates_and_replaced_occurrences() {
+        let events = [
+            "UID:book-club\nSUMMARY:Book club\nDTSTART:20261005T190000\nRRULE:FREQ=WEEKLY\n\
+             EXDATE:20261012T190000",
+            "UID:book-club\nRECURRENCE-ID:20261019T190000\nSUMMARY:Book club (moved)\nDTSTART:20261020T190000",
+            "SUMMARY:Cancelled\nDTSTART:20261014T120000\nSTATUS:CANCELLED",
+        ];
+        let found = occurrences(&calendar(&events), date(2026, 10, 5), date(2026, 10, 26));
+        let found: Vec<(NaiveDate, &str)> = found.iter().map(|o| (o.date, o.summary.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (date(2026, 10, 5), "Book club"),
+                (date(2026, 10, 20), "Book club (moved)"),
+                (date(2026, 10, 26), "Book club"),
+            ]
+        );
+    }
+
+    #[test]
+    fn folded_and_escaped_summaries() {
+        let events = calendar(&["SUMMARY:Book club\\, upstairs\n  at Sam's\nDTSTART;VALUE=DATE:20261019"]);
+        assert_eq!(events[0].summary, "Book club, upstairs at Sam's");
+    }
+
+    #[test]
+    fn agenda_leaves_off_ended_events() {
+        let events = [
+            "SUMMARY:Trash day\nDTSTART;VALUE=DATE:20261019",
+            "SUMMARY:Call\nDTSTART:20261019T090000\nDURATION:PT45M",
+            "SUMMARY:Dentist\nDTSTART:20261019T143000\nDTEND:20261019T153000",
+            "SUMMARY:Book club\nDTSTART:20261020T190000",
+            "SUMMARY:Game night\nDTSTART:20261023T190000",
+        ];
+        let found = occurrences(&calendar(&events), date(2026, 10, 19), date(2026, 10, 25));
+        assert_eq!(
+            agenda(&found, date(2026, 10, 19).and_time(time(10, 0))).as_deref(),
+            Some("Today: Trash day, 14:30 Dentist | Tomorrow: 19:00 Book club | Fri: 19:00 Game night")
+        );
+        assert_eq!(agenda(&found[..0], date(2026, 10, 19).and_time(time(10, 0))), None);
+    }
+}
@@ -1,5 +1,854 @@
-use std::collections::HashMap;
-use yaml_rust2::Yaml;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+use yaml_rust2::yaml::Hash;
+use rpi_led_matrix::LedColor;
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::calendar::{self, CalendarSlot, CalendarSource};
+use crate::config_checker::{ConfigChecker, ConfigError, ConfigIssue, child_path, scalar_string};
+use crate::display_board::panel_grid_size;
+use crate::frame::Rect;
+use crate::messages::{self, Message, MessageEvent};
+use crate::schedule::{self, Schedule, ScheduleRule};
+use crate::theme::{self, COLOR_KEYS, DEFAULT_THEME, Theme};
+use crate::weather::{self, TemperatureUnit};
+
+// What to draw between the line ref and the arrival times
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DestinationMode {
+    None,
+    Arrow,
+    Text,
+    // full destination name scrolling through the column
+    Scroll,
+}
+
+#[derive(Clone)]
+pub struct DestinationColumnConfig {
+    mode: DestinationMode,
+    chars: usize,
+    direction_arrows: HashMap<String, String>,
+    abbreviations: HashMap<String, String>,
+}
+
+impl DestinationColumnConfig {
+    // Every key is optional so configs written before this column existed keep working
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "display_board_config.destination_column";
+        checker.section(config, PATH);
+        checker.check_keys(config, PATH, &["mode", "chars", "direction_arrows", "abbreviations"]);
+        let mode = match checker
+            .optional_choice(
+                &config["mode"],
+                &child_path(PATH, "mode"),
+                "none",
+                &["none", "arrow", "text", "scroll"],
+            )
+            .as_str()
+        {
+            "arrow" => DestinationMode::Arrow,
+            "text" => DestinationMode::Text,
+            "scroll" => DestinationMode::Scroll,
+            _ => DestinationMode::None,
+        };
+        let mut direction_arrows: HashMap<String, String> = HashMap::from([
+            ("IB".to_string(), "\u{2190}".to_string()),
+            ("OB".to_string(), "\u{2192}".to_string()),
+        ]);
+        direction_arrows.extend(
+            checker.string_map(&config["direction_arrows"], &child_path(PATH, "direction_arrows")),
+        );
+        Self {
+            mode,
+            chars: checker.optional_integer(&config["chars"], &child_path(PATH, "chars"), 3, 1..=16)
+                as usize,
+            direction_arrows,
+            abbreviations: checker
+                .string_map(&config["abbreviations"], &child_path(PATH, "abbreviations")),
+        }
+    }
+    pub fn mode(&self) -> DestinationMode {
+        self.mode
+    }
+    // Width of the column in characters
+    pub fn chars(&self) -> usize {
+        match self.mode {
+            DestinationMode::None => 0,
+            DestinationMode::Arrow => 1,
+            DestinationMode::Text | DestinationMode::Scroll => self.chars,
+        }
+    }
+    pub fn direction_arrows(&self) -> &HashMap<String, String> {
+        &self.direction_arrows
+    }
+    pub fn abbreviations(&self) -> &HashMap<String, String> {
+        &self.abbreviations
+    }
+}
+
+// Arrival time colors for stops with a walk time
+#[derive(Clone)]
+pub struct UrgencyConfig {
+    enabled: bool,
+    leave_now_mins: i64,
+}
+
+impl UrgencyConfig {
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "display_board_config.urgency";
+        checker.section(config, PATH);
+        checker.check_keys(config, PATH, &["enabled", "leave_now_mins"]);
+        Self {
+            enabled: checker.optional_bool(&config["enabled"], &child_path(PATH, "enabled"), true),
+            leave_now_mins: checker.optional_integer(
+                &config["leave_now_mins"],
+                &child_path(PATH, "leave_now_mins"),
+                2,
+                0..=60,
+            ),
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    // Minutes past the walk time an arrival still counts as "leave now"
+    pub fn leave_now_mins(&self) -> i64 {
+        self.leave_now_mins
+    }
+}
+
+// What the board shows while it shuts down
+#[derive(Clone)]
+pub struct ShutdownConfig {
+    text: Option<String>,
+    hold_secs: u64,
+}
+
+impl ShutdownConfig {
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "display_board_config.shutdown";
+        checker.section(config, PATH);
+        checker.check_keys(config, PATH, &["text", "hold_secs"]);
+        let text_path = child_path(PATH, "text");
+        Self {
+            text: if ConfigChecker::is_missing(&config["text"]) {
+                None
+            } else {
+                checker.string(&config["text"], &text_path)
+            },
+            hold_secs: checker.optional_integer(
+                &config["hold_secs"],
+                &child_path(PATH, "hold_secs"),
+                0,
+                0..=3600,
+            ) as u64,
+        }
+    }
+    // Shown on the way out, the panel is left blank without it. Lines are
+    // split on newlines.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+    // Seconds the shutdown frame stays up before the matrix is released, the
+    // panel goes dark once the process exits
+    pub fn hold_secs(&self) -> u64 {
+        self.hold_secs
+    }
+}
+
+// Local HTTP server for checking on and controlling the board
+pub struct HttpApiConfig {
+    enabled: bool,
+    bind: String,
+}
+
+impl HttpApiConfig {
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "http_api";
+        checker.section(config, PATH);
+        checker.check_keys(config, PATH, &["enabled", "bind"]);
+        let bind_path = child_path(PATH, "bind");
+        let bind = checker.optional_string(&config["bind"], &bind_path, "127.0.0.1:8080");
+        if bind.parse::<std::net::SocketAddr>().is_err() {
+            checker.error(&bind_path, format!("`{}` isn't an address, expected e.g. 0.0.0.0:8080", bind));
+        }
+        Self {
+            enabled: checker.optional_bool(&config["enabled"], &child_path(PATH, "enabled"), false),
+            bind,
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    // Address and port to listen on, only this machine by default
+    pub fn bind(&self) -> &str {
+        &self.bind
+    }
+}
+
+// MQTT broker to publish arrivals and status to, and take commands from
+#[derive(Clone, PartialEq)]
+pub struct MqttConfig {
+    enabled: bool,
+    host: String,
+    port: u16,
+    username: Option<String>,
+    password: Option<String>,
+    client_id: String,
+    topic_prefix: String,
+    discovery: bool,
+    discovery_prefix: String,
+    publish_secs: u64,
+}
+
+impl MqttConfig {
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "mqtt";
+        checker.section(config, PATH);
+        checker.check_keys(
+            config,
+            PATH,
+            &[
+                "enabled",
+                "host",
+                "port",
+                "username",
+                "password",
+                "client_id",
+                "topic_prefix",
+                "discovery",
+                "discovery_prefix",
+                "publish_secs",
+            ],
+        );
+        let optional = |key: &str, checker: &mut ConfigChecker| {
+            if ConfigChecker::is_missing(&config[key]) {
+                None
+            } else {
+                checker.string(&config[key], &child_path(PATH, key))
+            }
+        };
+        let username = optional("username", checker);
+        let password = optional("password", checker);
+        let topic_path = child_path(PATH, "topic_prefix");
+        let topic_prefix = checker.optional_string(&config["topic_prefix"], &topic_path, "transit_rust");
+        if topic_prefix.is_empty() || topic_prefix.contains(['+', '#']) {
+            checker.error(&topic_path, "expected a topic without wildcards (+ or #)");
+        }
+        Self {
+            enabled: checker.optional_bool(&config["enabled"], &child_path(PATH, "enabled"), false),
+            host: checker.optional_string(&config["host"], &child_path(PATH, "host"), "localhost"),
+            port: checker.optional_integer(&config["port"], &child_path(PATH, "port"), 1883, 1..=65535)
+                as u16,
+            username,
+            password,
+            client_id: checker.optional_string(
+                &config["client_id"],
+                &child_path(PATH, "client_id"),
+                "transit_rust",
+            ),
+            topic_prefix: topic_prefix.trim_end_matches('/').to_string(),
+            discovery: checker.optional_bool(&config["discovery"], &child_path(PATH, "discovery"), true),
+            discovery_prefix: checker.optional_string(
+                &config["discovery_prefix"],
+                &child_path(PATH, "discovery_prefix"),
+                "homeassistant",
+            ),
+            publish_secs: checker.optional_integer(
+                &config["publish_secs"],
+                &child_path(PATH, "publish_secs"),
+                10,
+                1..=3600,
+            ) as u64,
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+    // Also names the board in Home Assistant, so unique per board
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+    // Every topic the board publishes or listens on starts with this
+    pub fn topic_prefix(&self) -> &str {
+        &self.topic_prefix
+    }
+    // Publish Home Assistant discovery configs
+    pub fn discovery(&self) -> bool {
+        self.discovery
+    }
+    pub fn discovery_prefix(&self) -> &str {
+        &self.discovery_prefix
+    }
+    // How often arrivals and status are checked, only changes are published
+    pub fn publish_secs(&self) -> u64 {
+        self.publish_secs
+    }
+}
+
+// Messages shown on the board without anyone having to post them: dated
+// events from the config and whatever is in the message file
+#[derive(Clone)]
+pub struct MessagesConfig {
+    file: Option<PathBuf>,
+    events: Vec<MessageEvent>,
+}
+
+impl MessagesConfig {
+    //   file: /run/transit_rust/messages
+    //   events:
+    //     - { text: "YOUR BIRTHDAY!", date: "02-02", priority: 10 }
+    //     - { text: "Trash day", days: tue, from: "18:00", color: green }
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "messages";
+        checker.section(config, PATH);
+        checker.check_keys(config, PATH, &["file", "events"]);
+        let file = (!ConfigChecker::is_missing(&config["file"]))
+            .then(|| checker.string(&config["file"], &child_path(PATH, "file")))
+            .flatten()
+            .map(PathBuf::from);
+        let events_path = child_path(PATH, "events");
+        let items = match &config["events"] {
+            Yaml::Array(items) => items.as_slice(),
+            events if ConfigChecker::is_missing(events) => &[],
+            _ => {
+                checker.error(&events_path, "expected a list of messages");
+                &[]
+            }
+        };
+        let events = items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| message_event(item, &format!("{}[{}]", events_path, i), checker))
+            .collect();
+        Self { file, events }
+    }
+    // File or FIFO to read messages from
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+    pub fn events(&self) -> &[MessageEvent] {
+        &self.events
+    }
+}
+
+// Today's and upcoming events from ICS files and URLs
+#[derive(Clone)]
+pub struct CalendarConfig {
+    sources: Vec<CalendarSource>,
+    lookahead_days: u32,
+    refresh_mins: u64,
+    slot: CalendarSlot,
+    priority: i32,
+    color: Option<LedColor>,
+}
+
+impl CalendarConfig {
+    //   sources:
+    //     - /home/pi/birthdays.ics
+    //     - { url: "https://example.com/trash.ics", cache: ./cache/trash.ics }
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "calendar";
+        checker.section(config, PATH);
+        checker.check_keys(
+            config,
+            PATH,
+            &["sources", "lookahead_days", "refresh_mins", "slot", "priority", "color", "cache_dir"],
+        );
+        let cache_dir = checker.optional_string(&config["cache_dir"], &child_path(PATH, "cache_dir"), "./cache");
+        let sources_path = child_path(PATH, "sources");
+        let items = match &config["sources"] {
+            Yaml::Array(items) => items.as_slice(),
+            sources if ConfigChecker::is_missing(sources) => &[],
+            _ => {
+                checker.error(&sources_path, "expected a list of files and URLs");
+                &[]
+            }
+        };
+        let mut sources = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{}[{}]", sources_path, i);
+            if let Some(file) = scalar_string(item) {
+                sources.push(CalendarSource::File(PathBuf::from(file)));
+                continue;
+            }
+            if item.as_hash().is_none() {
+                checker.error(&path, "expected a file, or a mapping with `path` or `url`");
+                continue;
+            }
+            checker.check_keys(item, &path, &["path", "url", "cache"]);
+            match (&item["path"], &item["url"]) {
+                (file, url) if ConfigChecker::is_missing(url) => {
+                    if let Some(file) = checker.string(file, &child_path(&path, "path")) {
+                        sources.push(CalendarSource::File(PathBuf::from(file)));
+                    }
+                }
+                (file, url) if ConfigChecker::is_missing(file) => {
+                    let url = match checker.string(url, &child_path(&path, "url")) {
+                        Some(url) => url,
+                        None => continue,
+                    };
+                    if !url.starts_with("http://") && !url.starts_with("https://") {
+                        checker.error(&child_path(&path, "url"), "expected an http:// or https:// URL");
+                    }
+                    let default_cache = Path::new(&cache_dir).join(calendar::cache_file_name(&url));
+                    let cache = checker.optional_string(
+                        &item["cache"],
+                        &child_path(&path, "cache"),
+                        &default_cache.to_string_lossy(),
+                    );
+                    sources.push(CalendarSource::Url {
+                        url,
+                        cache: PathBuf::from(cache),
+                    });
+                }
+                _ => checker.error(&path, "expected one of `path` or `url`, not both"),
+            }
+        }
+        let slot = match checker
+            .optional_choice(&config["slot"], &child_path(PATH, "slot"), "ticker", &["ticker", "header"])
+            .as_str()
+        {
+            "header" => CalendarSlot::Header,
+            _ => CalendarSlot::Ticker,
+        };
+        Self {
+            sources,
+            lookahead_days: checker.optional_integer(
+                &config["lookahead_days"],
+                &child_path(PATH, "lookahead_days"),
+                3,
+                0..=60,
+            ) as u32,
+            refresh_mins: checker.optional_integer(
+                &config["refresh_mins"],
+                &child_path(PATH, "refresh_mins"),
+                60,
+                1..=1440,
+            ) as u64,
+            slot,
+            priority: checker.optional_integer(
+                &config["priority"],
+                &child_path(PATH, "priority"),
+                0,
+                i32::MIN as i64..=i32::MAX as i64,
+            ) as i32,
+            color: (!ConfigChecker::is_missing(&config["color"]))
+                .then(|| color(&config["color"], &child_path(PATH, "color"), checker))
+                .flatten(),
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        !self.sources.is_empty()
+    }
+    pub fn sources(&self) -> &[CalendarSource] {
+        &self.sources
+    }
+    // Days after today to show events for, 0 for only today's
+    pub fn lookahead_days(&self) -> u32 {
+        self.lookahead_days
+    }
+    // How often URLs are fetched again, files are read every minute
+    pub fn refresh_mins(&self) -> u64 {
+        self.refresh_mins
+    }
+    pub fn slot(&self) -> CalendarSlot {
+        self.slot
+    }
+    // Priority of the agenda among the messages, in the ticker slot
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+    pub fn color(&self) -> Option<LedColor> {
+        self.color
+    }
+}
+
+// Where the weather comes from
+#[derive(Clone, Debug)]
+pub enum WeatherProviderConfig {
+    OpenMeteo {
+        url: String,
+        latitude: f64,
+        longitude: f64,
+        rain_hours: u32,
+    },
+    Nws {
+        url: String,
+        latitude: f64,
+        longitude: f64,
+        rain_hours: u32,
+    },
+    // a JSON file with the forecast in it, or a saved Open-Meteo or NWS response
+    File { path: PathBuf, rain_hours: u32 },
+}
+
+// Current temperature, conditions and chance of rain in a corner of the board
+#[derive(Clone, Debug)]
+pub struct WeatherConfig {
+    provider: WeatherProviderConfig,
+    unit: TemperatureUnit,
+    refresh_mins: u64,
+    stale_mins: u64,
+    cache: PathBuf,
+    region: Rect,
+}
+
+impl WeatherConfig {
+    // None when the section is missing, there's no weather on the board then
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Option<Self> {
+        const PATH: &str = "weather";
+        if ConfigChecker::is_missing(config) || !checker.section(config, PATH) {
+            return None;
+        }
+        checker.check_keys(
+            config,
+            PATH,
+            &[
+                "provider",
+                "latitude",
+                "longitude",
+                "url",
+                "file",
+                "rain_hours",
+                "units",
+                "refresh_mins",
+                "stale_mins",
+                "cache",
+                "x",
+                "y",
+                "width",
+                "height",
+            ],
+        );
+        let path = |key: &str| child_path(PATH, key);
+        let provider = checker.optional_choice(
+            &config["provider"],
+            &path("provider"),
+            "open_meteo",
+            &["open_meteo", "nws", "file"],
+        );
+        let rain_hours = checker.optional_integer(&config["rain_hours"], &path("rain_hours"), 3, 1..=48) as u32;
+        let provider = match provider.as_str() {
+            "file" => WeatherProviderConfig::File {
+                path: PathBuf::from(checker.string(&config["file"], &path("file")).unwrap_or_default()),
+                rain_hours,
+            },
+            provider => {
+                for key in ["latitude", "longitude"] {
+                    if ConfigChecker::is_missing(&config[key]) {
+                        checker.error(&path(key), format!("required for the {} provider", provider));
+                    }
+                }
+                let default_url = if provider == "nws" { weather::NWS_URL } else { weather::OPEN_METEO_URL };
+                let url = checker.optional_string(&config["url"], &path("url"), default_url);
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    checker.error(&path("url"), "expected an http:// or https:// URL");
+                }
+                let latitude = checker.optional_float(&config["latitude"], &path("latitude"), 0.0, -90.0..=90.0);
+                let longitude =
+                    checker.optional_float(&config["longitude"], &path("longitude"), 0.0, -180.0..=180.0);
+                if provider == "nws" {
+                    WeatherProviderConfig::Nws {
+                        url,
+                        latitude,
+                        longitude,
+                        rain_hours,
+                    }
+                } else {
+                    WeatherProviderConfig::OpenMeteo {
+                        url,
+                        latitude,
+                        longitude,
+                        rain_hours,
+                    }
+                }
+            }
+        };
+        let unit = match checker
+            .optional_choice(&config["units"], &path("units"), "fahrenheit", &["fahrenheit", "celsius"])
+            .as_str()
+        {
+            "celsius" => TemperatureUnit::Celsius,
+            _ => TemperatureUnit::Fahrenheit,
+        };
+        // the panel goes where it's told, over anything else drawn there
+        for key in ["x", "y"] {
+            if ConfigChecker::is_missing(&config[key]) {
+                checker.error(&path(key), "required to place the weather on the board");
+            }
+        }
+        let region = Rect::new(
+            checker.optional_integer(&config["x"], &path("x"), 0, 0..=4096) as i32,
+            checker.optional_integer(&config["y"], &path("y"), 0, 0..=4096) as i32,
+            checker.optional_integer(&config["width"], &path("width"), 44, 1..=4096) as i32,
+            checker.optional_integer(&config["height"], &path("height"), weather::ICON_SIZE as i64, 1..=4096)
+                as i32,
+        );
+        Some(Self {
+            provider,
+            unit,
+            refresh_mins: checker.optional_integer(&config["refresh_mins"], &path("refresh_mins"), 15, 1..=1440)
+                as u64,
+            stale_mins: checker.optional_integer(&config["stale_mins"], &path("stale_mins"), 120, 1..=10080)
+                as u64,
+            cache: PathBuf::from(checker.optional_string(
+                &config["cache"],
+                &path("cache"),
+                "./cache/weather.json",
+            )),
+            region,
+        })
+    }
+    pub fn provider(&self) -> &WeatherProviderConfig {
+        &self.provider
+    }
+    pub fn unit(&self) -> TemperatureUnit {
+        self.unit
+    }
+    // How often the provider is asked for the weather
+    pub fn refresh_mins(&self) -> u64 {
+        self.refresh_mins
+    }
+    // The panel is left off once the last forecast is older than this
+    pub fn stale_mins(&self) -> u64 {
+        self.stale_mins
+    }
+    // File the last forecast is kept in between restarts
+    pub fn cache(&self) -> &Path {
+        &self.cache
+    }
+    // Area of the board the panel is drawn in, cleared first
+    pub fn region(&self) -> Rect {
+        self.region
+    }
+}
+
+fn message_event(item: &Yaml, path: &str, checker: &mut ConfigChecker) -> Option<MessageEvent> {
+    if item.as_hash().is_none() {
+        checker.error(path, "expected a mapping of keys to values");
+        return None;
+    }
+    checker.check_keys(
+        item,
+        path,
+        &["text", "date", "days", "from", "to", "priority", "color", "scroll"],
+    );
+    let key_path = |key: &str| child_path(path, key);
+    let optional = |key: &str| !ConfigChecker::is_missing(&item[key]);
+    let text = checker.string(&item["text"], &key_path("text")).unwrap_or_default();
+    if optional("text") && text.trim().is_empty() {
+        checker.error(&key_path("text"), "expected the text to show");
+    }
+    let mut message = Message::new(text.trim());
+    message.priority = checker.optional_integer(
+        &item["priority"],
+        &key_path("priority"),
+        0,
+        i32::MIN as i64..=i32::MAX as i64,
+    ) as i32;
+    if optional("color") {
+        message.color = color(&item["color"], &key_path("color"), checker);
+    }
+    message.scroll = checker.optional_bool(&item["scroll"], &key_path("scroll"), true);
+
+    let date = if optional("date") {
+        let value = scalar_string(&item["date"]).unwrap_or_default();
+        let date = messages::parse_date(&value);
+        if date.is_none() {
+            checker.error(
+                &key_path("date"),
+                format!("`{}` isn't a date, expected MM-DD for every year or YYYY-MM-DD", value),
+            );
+        }
+        date
+    } else {
+        None
+    };
+    let days_path = key_path("days");
+    let day_names = match &item["days"] {
+        Yaml::Array(_) => checker.string_list(&item["days"], &days_path),
+        days => checker
+            .optional_string(days, &days_path, "daily")
+            .split(',')
+            .map(String::from)
+            .collect(),
+    };
+    let mut days = HashSet::new();
+    for day in day_names {
+        match schedule::parse_days(&day) {
+            Some(parsed) => days.extend(parsed),
+            None => checker.error(
+                &days_path,
+                format!("`{}` isn't a day, expected mon..sun, weekdays, weekends or daily", day),
+            ),
+        }
+    }
+    let mut time = |key: &str| {
+        if !optional(key) {
+            return None;
+        }
+        let value = checker.string(&item[key], &key_path(key))?;
+        let time = schedule::parse_time(&value);
+        if time.is_none() {
+            checker.error(&key_path(key), format!("`{}` isn't a time, expected HH:MM", value));
+        }
+        time
+    };
+    let from = time("from");
+    let to = time("to");
+    Some(MessageEvent {
+        message,
+        date,
+        days,
+        from,
+        to,
+    })
+}
+
+// Where the auto brightness reads how light it is
+#[derive(Clone, Debug)]
+pub enum LightSourceConfig {
+    // lux from a sensor file, mapped between dark_lux and bright_lux
+    File {
+        path: PathBuf,
+        dark_lux: f64,
+        bright_lux: f64,
+    },
+    // daylight from the sun's position
+    Sun { latitude: f64, longitude: f64 },
+    // a fixed light level from 0 to 1
+    Static { level: f64 },
+}
+
+#[derive(Clone, Debug)]
+pub struct AutoBrightnessConfig {
+    source: LightSourceConfig,
+    min: u8,
+    max: u8,
+    smoothing_secs: f64,
+    poll_secs: f64,
+}
+
+impl AutoBrightnessConfig {
+    // None when the section is missing, the board then stays at full brightness
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Option<Self> {
+        const PATH: &str = "display_board_config.auto_brightness";
+        if ConfigChecker::is_missing(config) || !checker.section(config, PATH) {
+            return None;
+        }
+        checker.check_keys(
+            config,
+            PATH,
+            &[
+                "source",
+                "file",
+                "dark_lux",
+                "bright_lux",
+                "latitude",
+                "longitude",
+                "level",
+                "min",
+                "max",
+                "smoothing_secs",
+                "poll_secs",
+            ],
+        );
+        let path = |key: &str| child_path(PATH, key);
+        let source =
+            checker.optional_choice(&config["source"], &path("source"), "sun", &["file", "sun", "static"]);
+        let source = match source.as_str() {
+            "file" => LightSourceConfig::File {
+                path: PathBuf::from(checker.string(&config["file"], &path("file")).unwrap_or_default()),
+                dark_lux: checker.optional_float(
+                    &config["dark_lux"],
+                    &path("dark_lux"),
+                    1.0,
+                    0.0..=100000.0,
+                ),
+                bright_lux: checker.optional_float(
+                    &config["bright_lux"],
+                    &path("bright_lux"),
+                    1000.0,
+                    0.0..=200000.0,
+                ),
+            },
+            "static" => LightSourceConfig::Static {
+                level: checker.optional_float(&config["level"], &path("level"), 1.0, 0.0..=1.0),
+            },
+            _ => {
+                for key in ["latitude", "longitude"] {
+                    if ConfigChecker::is_missing(&config[key]) {
+                        checker.error(&path(key), "required for the sun source");
+                    }
+                }
+                LightSourceConfig::Sun {
+                    latitude: checker.optional_float(
+                        &config["latitude"],
+                        &path("latitude"),
+                        0.0,
+                        -90.0..=90.0,
+                    ),
+                    longitude: checker.optional_float(
+                        &config["longitude"],
+                        &path("longitude"),
+                        0.0,
+                        -180.0..=180.0,
+                    ),
+                }
+            }
+        };
+        let min = checker.optional_integer(&config["min"], &path("min"), 10, 0..=100) as u8;
+        let max = checker.optional_integer(&config["max"], &path("max"), 100, 0..=100) as u8;
+        if min > max {
+            checker.error(&path("min"), format!("{} is more than max ({})", min, max));
+        }
+        Some(Self {
+            source,
+            min,
+            max,
+            smoothing_secs: checker.optional_float(
+                &config["smoothing_secs"],
+                &path("smoothing_secs"),
+                5.0,
+                0.0..=600.0,
+            ),
+            poll_secs: checker.optional_float(&config["poll_secs"], &path("poll_secs"), 2.0, 0.1..=3600.0),
+        })
+    }
+    pub fn source(&self) -> &LightSourceConfig {
+        &self.source
+    }
+    // Brightness percent in the dark
+    pub fn min(&self) -> u8 {
+        self.min
+    }
+    // Brightness percent in full light
+    pub fn max(&self) -> u8 {
+        self.max
+    }
+    // Roughly how long the board takes to settle at a new brightness
+    pub fn smoothing_secs(&self) -> f64 {
+        self.smoothing_secs
+    }
+    // Seconds between light level readings
+    pub fn poll_secs(&self) -> f64 {
+        self.poll_secs
+    }
+}
 
 pub struct DisplayBoardConfig {
     font_file: String,
@@ -7,28 +856,206 @@ pub struct DisplayBoardConfig {
     cols: u32,
     chained: u32,
     line_ref_to_display_position: HashMap<String, (i32, i32)>,
+    destination_column: DestinationColumnConfig,
+    frame_rate: u32,
+    marquee_speed: f32,
+    page_dwell_secs: u64,
+    urgency: UrgencyConfig,
+    leave_in: bool,
+    auto_brightness: Option<AutoBrightnessConfig>,
+    theme: String,
+    themes: HashMap<String, Theme>,
+    shutdown: ShutdownConfig,
+}
+
+// Cell for each line, positions have to land on the grid of cells the panel
+// has room for
+fn display_positions(
+    config: &Yaml,
+    path: &str,
+    (grid_cols, grid_rows): (i32, i32),
+    checker: &mut ConfigChecker,
+) -> HashMap<String, (i32, i32)> {
+    let mut line_ref_to_display_position = HashMap::new();
+    if !checker.section(config, path) {
+        return line_ref_to_display_position;
+    }
+    for (k, v) in config.as_hash().into_iter().flatten() {
+        let line_ref = scalar_string(k).unwrap_or_default();
+        let position_path = child_path(path, &line_ref);
+        if v.as_hash().is_none()
+            || ConfigChecker::is_missing(&v["x"])
+            || ConfigChecker::is_missing(&v["y"])
+        {
+            checker.error(&position_path, "expected `x` and `y`");
+            continue;
+        }
+        checker.check_keys(v, &position_path, &["x", "y"]);
+        let x_path = child_path(&position_path, "x");
+        let y_path = child_path(&position_path, "y");
+        let x = checker.optional_integer(&v["x"], &x_path, 0, 0..=(grid_cols as i64 - 1));
+        let y = checker.optional_integer(&v["y"], &y_path, 0, 0..=(grid_rows as i64 - 1));
+        line_ref_to_display_position.insert(line_ref, (x as i32, y as i32));
+    }
+    line_ref_to_display_position
+}
+
+// Yaml reads an unquoted #ff8800 as a comment, so quote hex colors
+fn color(config: &Yaml, path: &str, checker: &mut ConfigChecker) -> Option<LedColor> {
+    let value = scalar_string(config).unwrap_or_default();
+    let color = theme::parse_color(&value);
+    if color.is_none() {
+        checker.error(
+            path,
+            format!("`{}` isn't a color, expected \"#rrggbb\" or a name like orange", value),
+        );
+    }
+    color
+}
+
+// The presets plus the themes from the config. Each theme starts from `base`,
+// a preset or a theme defined above it, and changes some of its colors.
+fn themes(config: &Yaml, checker: &mut ConfigChecker) -> HashMap<String, Theme> {
+    const PATH: &str = "display_board_config.themes";
+    let mut themes = theme::preset_themes();
+    if !checker.section(config, PATH) {
+        return themes;
+    }
+    for (k, v) in config.as_hash().into_iter().flatten() {
+        let name = scalar_string(k).unwrap_or_default();
+        let theme_path = child_path(PATH, &name);
+        if !checker.section(v, &theme_path) {
+            continue;
+        }
+        checker.check_keys(v, &theme_path, &["base", "colors", "line_colors"]);
+        let base_path = child_path(&theme_path, "base");
+        let base = checker.optional_string(&v["base"], &base_path, DEFAULT_THEME);
+        let mut theme = match themes.get(&base) {
+            Some(theme) => theme.clone(),
+            None => {
+                checker.error(&base_path, format!("no preset or earlier theme named `{}`", base));
+                Theme::default()
+            }
+        };
+
+        let colors_path = child_path(&theme_path, "colors");
+        if checker.section(&v["colors"], &colors_path) {
+            checker.check_keys(&v["colors"], &colors_path, &COLOR_KEYS);
+            for (key, value) in v["colors"].as_hash().into_iter().flatten() {
+                let key = scalar_string(key).unwrap_or_default();
+                let color_path = child_path(&colors_path, &key);
+                if let (Some(slot), Some(color)) =
+                    (theme.color_mut(&key), color(value, &color_path, checker))
+                {
+                    *slot = color;
+                }
+            }
+        }
+
+        let line_colors_path = child_path(&theme_path, "line_colors");
+        if checker.section(&v["line_colors"], &line_colors_path) {
+            for (line, value) in v["line_colors"].as_hash().into_iter().flatten() {
+                let line = scalar_string(line).unwrap_or_default();
+                if let Some(color) = color(value, &child_path(&line_colors_path, &line), checker) {
+                    theme.line_colors.insert(line, color);
+                }
+            }
+        }
+        themes.insert(name, theme);
+    }
+    themes
 }
+
 impl DisplayBoardConfig {
-    pub fn new(config: &Yaml) -> Self {
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "display_board_config";
+        checker.section(config, PATH);
+        checker.check_keys(
+            config,
+            PATH,
+            &[
+                "font_file",
+                "rows",
+                "cols",
+                "chained",
+                "line_ref_to_display_position",
+                "destination_column",
+                "frame_rate",
+                "marquee_speed",
+                "page_dwell_secs",
+                "urgency",
+                "leave_in",
+                "auto_brightness",
+                "theme",
+                "themes",
+                "shutdown",
+            ],
+        );
+        let font_file =
+            checker.optional_string(&config["font_file"], &child_path(PATH, "font_file"), "./4x6.bdf");
+        if !Path::new(&font_file).exists() {
+            checker.error(
+                &child_path(PATH, "font_file"),
+                format!("font file `{}` doesn't exist", font_file),
+            );
+        }
+        let rows = checker.optional_integer(&config["rows"], &child_path(PATH, "rows"), 32, 8..=64) as u32;
+        let cols = checker.optional_integer(&config["cols"], &child_path(PATH, "cols"), 64, 16..=256) as u32;
+        let chained =
+            checker.optional_integer(&config["chained"], &child_path(PATH, "chained"), 2, 1..=16) as u32;
+
+        let grid = panel_grid_size((cols * chained) as i32, rows as i32);
+        let line_ref_to_display_position = display_positions(
+            &config["line_ref_to_display_position"],
+            &child_path(PATH, "line_ref_to_display_position"),
+            grid,
+            checker,
+        );
+
+        let themes = themes(&config["themes"], checker);
+        let theme_path = child_path(PATH, "theme");
+        let theme = checker.optional_string(&config["theme"], &theme_path, DEFAULT_THEME);
+        if !themes.contains_key(&theme) {
+            let mut names: Vec<&String> = themes.keys().collect();
+            names.sort();
+            let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+            checker.error(
+                &theme_path,
+                format!("no theme named `{}`, expected one of {}", theme, names.join(", ")),
+            );
+        }
+
         Self {
-            font_file: config["font_file"].as_str().unwrap().to_string(),
-            rows: config["rows"].as_i64().unwrap() as u32,
-            cols: config["cols"].as_i64().unwrap() as u32,
-            chained: config["chained"].as_i64().unwrap() as u32,
-            line_ref_to_display_position: config["line_ref_to_display_position"]
-                .as_hash()
-                .unwrap()
-                .iter()
-                .map(|(k, v)| {
-                    (
-                        k.as_str().unwrap().to_string(),
-                        (
-                            v["x"].as_i64().unwrap() as i32,
-                            v["y"].as_i64().unwrap() as i32,
-                        ),
-                    )
-                })
-                .collect(),
+            font_file,
+            rows,
+            cols,
+            chained,
+            line_ref_to_display_position,
+            destination_column: DestinationColumnConfig::new(&config["destination_column"], checker),
+            frame_rate: checker.optional_integer(
+                &config["frame_rate"],
+                &child_path(PATH, "frame_rate"),
+                30,
+                1..=120,
+            ) as u32,
+            marquee_speed: checker.optional_float(
+                &config["marquee_speed"],
+                &child_path(PATH, "marquee_speed"),
+                15.0,
+                0.0..=500.0,
+            ) as f32,
+            page_dwell_secs: checker.optional_integer(
+                &config["page_dwell_secs"],
+                &child_path(PATH, "page_dwell_secs"),
+                8,
+                1..=3600,
+            ) as u64,
+            urgency: UrgencyConfig::new(&config["urgency"], checker),
+            leave_in: checker.optional_bool(&config["leave_in"], &child_path(PATH, "leave_in"), false),
+            auto_brightness: AutoBrightnessConfig::new(&config["auto_brightness"], checker),
+            theme,
+            themes,
+            shutdown: ShutdownConfig::new(&config["shutdown"], checker),
         }
     }
     pub fn font_file(&self) -> &str {
@@ -46,37 +1073,522 @@ impl DisplayBoardConfig {
     pub fn line_ref_to_display_position(&self) -> &HashMap<String, (i32, i32)> {
         &self.line_ref_to_display_position
     }
+    pub fn destination_column(&self) -> &DestinationColumnConfig {
+        &self.destination_column
+    }
+    // Frames drawn per second
+    pub fn frame_rate(&self) -> u32 {
+        self.frame_rate
+    }
+    // Scrolling text speed in pixels per second
+    pub fn marquee_speed(&self) -> f32 {
+        self.marquee_speed
+    }
+    // Seconds each page is shown when rows don't fit on one screen
+    pub fn page_dwell_secs(&self) -> u64 {
+        self.page_dwell_secs
+    }
+    pub fn urgency(&self) -> &UrgencyConfig {
+        &self.urgency
+    }
+    // Show minutes until it's time to leave instead of minutes to arrival at
+    // stops with a walk time
+    pub fn leave_in(&self) -> bool {
+        self.leave_in
+    }
+    pub fn auto_brightness(&self) -> Option<&AutoBrightnessConfig> {
+        self.auto_brightness.as_ref()
+    }
+    // Name of the theme to start with
+    pub fn theme(&self) -> &str {
+        &self.theme
+    }
+    // Presets and configured themes by name
+    pub fn themes(&self) -> &HashMap<String, Theme> {
+        &self.themes
+    }
+    pub fn shutdown(&self) -> &ShutdownConfig {
+        &self.shutdown
+    }
+}
+
+pub struct ServiceAlertsConfig {
+    enabled: bool,
+    refresh_secs: u64,
+    ticker: bool,
+}
+
+impl ServiceAlertsConfig {
+    // Off unless configured, alerts cost an extra request against the API quota
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        const PATH: &str = "service_alerts";
+        checker.section(config, PATH);
+        checker.check_keys(config, PATH, &["enabled", "refresh_secs", "ticker"]);
+        Self {
+            enabled: checker.optional_bool(&config["enabled"], &child_path(PATH, "enabled"), false),
+            refresh_secs: checker.optional_integer(
+                &config["refresh_secs"],
+                &child_path(PATH, "refresh_secs"),
+                300,
+                30..=86400,
+            ) as u64,
+            ticker: checker.optional_bool(&config["ticker"], &child_path(PATH, "ticker"), true),
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    // Seconds between alert fetches
+    pub fn refresh_secs(&self) -> u64 {
+        self.refresh_secs
+    }
+    // Scroll alert headers along the bottom row
+    pub fn ticker(&self) -> bool {
+        self.ticker
+    }
+}
+
+// Prefix of environment variables that override config keys
+const ENV_PREFIX: &str = "TRANSIT_";
+// Legacy way of passing the 511 API key, not a config key
+const TOKEN_ENV: &str = "TRANSIT_TOKEN";
+
+// A single config value set from outside the file, applied on top of the YAML
+#[derive(Debug, Clone)]
+pub struct ConfigOverride {
+    // dotted path to the key, e.g. display_board_config.rows
+    path: String,
+    value: String,
+    // where the override came from, for error messages
+    origin: String,
+}
+
+impl ConfigOverride {
+    // `--set path=value` from the command line
+    pub fn parse_cli(arg: &str) -> Result<Self, String> {
+        match arg.split_once('=') {
+            Some((path, value)) if !path.trim().is_empty() => Ok(ConfigOverride {
+                path: path.trim().to_string(),
+                value: value.to_string(),
+                origin: format!("--set {}", arg),
+            }),
+            _ => Err(format!("expected key=value, e.g. agency=SF, got `{}`", arg)),
+        }
+    }
+
+    // TRANSIT_* variables, nested keys are separated by a double underscore,
+    // e.g. TRANSIT_DISPLAY_BOARD_CONFIG__ROWS=16
+    pub fn from_env() -> Vec<Self> {
+        let mut overrides: Vec<Self> = env::vars()
+            .filter(|(name, _)| name != TOKEN_ENV)
+            .filter_map(|(name, value)| {
+                let key = name.strip_prefix(ENV_PREFIX)?;
+                let path = key
+                    .split("__")
+                    .map(|part| part.to_lowercase())
+                    .collect::<Vec<_>>()
+                    .join(".");
+                Some(ConfigOverride {
+                    path,
+                    value,
+                    origin: name,
+                })
+            })
+            .collect();
+        // keep the order stable when two variables name the same key
+        overrides.sort_by(|a, b| a.origin.cmp(&b.origin));
+        overrides
+    }
+
+    // Lists and mappings are read as YAML, e.g. TRANSIT_STOPS_TO_MONITOR='[15551, 15552]'.
+    // Anything else stays a string so a stop code like 01234 keeps its leading
+    // zero, keys that want a number or boolean convert it when they're read.
+    fn yaml_value(&self) -> Yaml {
+        let document = YamlLoader::load_from_str(&self.value)
+            .ok()
+            .and_then(|documents| documents.into_iter().next());
+        match document {
+            Some(value @ (Yaml::Array(_) | Yaml::Hash(_) | Yaml::Null)) if !self.value.trim().is_empty() => value,
+            _ => Yaml::String(self.value.clone()),
+        }
+    }
+
+    fn apply(&self, config: &mut Yaml, checker: &mut ConfigChecker) {
+        let keys: Vec<&str> = self.path.split('.').collect();
+        let mut node = config;
+        for (i, key) in keys.iter().enumerate() {
+            if ConfigChecker::is_missing(node) {
+                *node = Yaml::Hash(Hash::new());
+            }
+            let hash = match node {
+                Yaml::Hash(hash) => hash,
+                _ => {
+                    checker.set_origin(&keys[..i].join("."), &self.origin);
+                    checker.error(&keys[..i].join("."), "can't set a key inside a value that isn't a mapping");
+                    return;
+                }
+            };
+            // the file might have the key as a number, e.g. `22:`
+            let existing = hash
+                .keys()
+                .find(|k| scalar_string(k).as_deref() == Some(*key))
+                .cloned()
+                .unwrap_or_else(|| Yaml::String(key.to_string()));
+            node = hash.entry(existing).or_insert(Yaml::Null);
+            if i == keys.len() - 1 {
+                *node = self.yaml_value();
+            }
+        }
+        checker.set_origin(&self.path, &self.origin);
+    }
+}
+
+// Stop codes and, for stops that have them, minutes to walk there and the
+// fewest minutes away an arrival can be and still be shown.
+type StopsToMonitor = (Vec<String>, HashMap<String, i64>, HashMap<String, i64>);
+
+// Each stop is either a code on its own or a mapping with the code and its
+// times: `- "15551"` or `- { stop: "15551", walk_mins: 6, min_lead_mins: 6 }`
+fn stops_to_monitor(config: &Yaml, checker: &mut ConfigChecker) -> StopsToMonitor {
+    const PATH: &str = "stops_to_monitor";
+    let mut stops = Vec::new();
+    let mut walk_minutes = HashMap::new();
+    let mut min_lead_minutes = HashMap::new();
+    if ConfigChecker::is_missing(config) {
+        return (stops, walk_minutes, min_lead_minutes);
+    }
+    let items = match config.as_vec() {
+        Some(items) => items,
+        None => {
+            checker.error(PATH, "expected a list");
+            return (stops, walk_minutes, min_lead_minutes);
+        }
+    };
+    for (i, item) in items.iter().enumerate() {
+        let item_path = format!("{}[{}]", PATH, i);
+        if item.as_hash().is_none() {
+            stops.extend(checker.string(item, &item_path));
+            continue;
+        }
+        checker.check_keys(item, &item_path, &["stop", "walk_mins", "min_lead_mins"]);
+        let stop = match checker.string(&item["stop"], &child_path(&item_path, "stop")) {
+            Some(stop) => stop,
+            None => continue,
+        };
+        let walk_mins = if ConfigChecker::is_missing(&item["walk_mins"]) {
+            None
+        } else {
+            let walk_path = child_path(&item_path, "walk_mins");
+            Some(checker.optional_integer(&item["walk_mins"], &walk_path, 0, 0..=120))
+        };
+        if let Some(walk_mins) = walk_mins {
+            walk_minutes.insert(stop.clone(), walk_mins);
+        }
+        let min_lead_mins = if ConfigChecker::is_missing(&item["min_lead_mins"]) {
+            // no making an arrival sooner than the walk there
+            walk_mins
+        } else {
+            let lead_path = child_path(&item_path, "min_lead_mins");
+            Some(checker.optional_integer(&item["min_lead_mins"], &lead_path, 0, 0..=120))
+        };
+        if let Some(min_lead_mins) = min_lead_mins {
+            min_lead_minutes.insert(stop.clone(), min_lead_mins);
+        }
+        stops.push(stop);
+    }
+    (stops, walk_minutes, min_lead_minutes)
+}
+
+// Time-of-day rules, applied in order:
+//   - { name: night, from: "22:00", to: "06:30", brightness: 20 }
+//   - { name: commute, days: weekdays, to: "10:00", lines: ["J", "24"] }
+fn schedule(
+    config: &Yaml,
+    display_board_config: &DisplayBoardConfig,
+    checker: &mut ConfigChecker,
+) -> Schedule {
+    const PATH: &str = "schedule";
+    let mut schedule = Schedule::default();
+    if ConfigChecker::is_missing(config) {
+        return schedule;
+    }
+    let items = match config.as_vec() {
+        Some(items) => items,
+        None => {
+            checker.error(PATH, "expected a list of rules");
+            return schedule;
+        }
+    };
+    let grid = panel_grid_size(
+        (display_board_config.cols() * display_board_config.chained()) as i32,
+        display_board_config.rows() as i32,
+    );
+    for (i, item) in items.iter().enumerate() {
+        let rule_path = format!("{}[{}]", PATH, i);
+        if item.as_hash().is_none() {
+            checker.error(&rule_path, "expected a mapping of keys to values");
+            continue;
+        }
+        checker.check_keys(
+            item,
+            &rule_path,
+            &[
+                "name",
+                "days",
+                "from",
+                "to",
+                "brightness",
+                "blank",
+                "lines",
+                "theme",
+                "line_ref_to_display_position",
+            ],
+        );
+
+        let days_path = child_path(&rule_path, "days");
+        let day_names = match &item["days"] {
+            Yaml::Array(_) => checker.string_list(&item["days"], &days_path),
+            days => checker
+                .optional_string(days, &days_path, "daily")
+                .split(',')
+                .map(String::from)
+                .collect(),
+        };
+        let mut days = HashSet::new();
+        for day in day_names {
+            match schedule::parse_days(&day) {
+                Some(parsed) => days.extend(parsed),
+                None => checker.error(
+                    &days_path,
+                    format!("`{}` isn't a day, expected mon..sun, weekdays, weekends or daily", day),
+                ),
+            }
+        }
+
+        let time = |key: &str, checker: &mut ConfigChecker| {
+            let path = child_path(&rule_path, key);
+            if ConfigChecker::is_missing(&item[key]) {
+                return None;
+            }
+            let value = checker.string(&item[key], &path)?;
+            let time = schedule::parse_time(&value);
+            if time.is_none() {
+                checker.error(&path, format!("`{}` isn't a time, expected HH:MM", value));
+            }
+            time
+        };
+        let from = time("from", checker);
+        let to = time("to", checker);
+
+        let key_path = |key: &str| child_path(&rule_path, key);
+        let optional = |key: &str| !ConfigChecker::is_missing(&item[key]);
+        let theme = optional("theme")
+            .then(|| checker.optional_string(&item["theme"], &key_path("theme"), ""));
+        if let Some(theme) = &theme {
+            if !display_board_config.themes().contains_key(theme) {
+                checker.error(&key_path("theme"), format!("no theme named `{}`", theme));
+            }
+        }
+        let name = checker.optional_string(&item["name"], &key_path("name"), &format!("rule {}", i + 1));
+        let brightness = optional("brightness").then(|| {
+            checker.optional_integer(&item["brightness"], &key_path("brightness"), 100, 0..=100) as u8
+        });
+        let blank = optional("blank")
+            .then(|| checker.optional_bool(&item["blank"], &key_path("blank"), false));
+        let lines = optional("lines")
+            .then(|| checker.string_list(&item["lines"], &key_path("lines")).into_iter().collect());
+        let positions_key = "line_ref_to_display_position";
+        let line_ref_to_display_position = optional(positions_key)
+            .then(|| display_positions(&item[positions_key], &key_path(positions_key), grid, checker));
+        schedule.rules.push(ScheduleRule {
+            name,
+            days,
+            from,
+            to,
+            brightness,
+            blank,
+            lines,
+            theme,
+            line_ref_to_display_position,
+        });
+    }
+    schedule
+}
+
+// Where the 511 API key comes from, in order of preference: a file (so it
+// isn't in the environment), the config itself, then TRANSIT_TOKEN
+fn load_api_key(config: &Yaml, checker: &mut ConfigChecker) -> Option<String> {
+    let api_key_file = checker.optional_string(&config["api_key_file"], "api_key_file", "");
+    let api_key = checker.optional_string(&config["api_key"], "api_key", "");
+    if !api_key_file.is_empty() {
+        if !api_key.is_empty() {
+            checker.warn("api_key", "ignored, `api_key_file` is set");
+        }
+        return match fs::read_to_string(&api_key_file) {
+            Ok(key) if !key.trim().is_empty() => Some(key.trim().to_string()),
+            Ok(_) => {
+                checker.error("api_key_file", format!("`{}` is empty", api_key_file));
+                None
+            }
+            Err(e) => {
+                checker.error(
+                    "api_key_file",
+                    format!("unable to read `{}`: {}", api_key_file, e),
+                );
+                None
+            }
+        };
+    }
+    if !api_key.is_empty() {
+        return Some(api_key);
+    }
+    // only needed by commands that talk to 511, they ask for it with api_key()
+    env::var(TOKEN_ENV)
+        .ok()
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
 }
 
 pub struct Config {
+    api_key: Option<String>,
     display_board_config: DisplayBoardConfig,
     rapid_line_to_parent_line_map: HashMap<String, String>,
     stops_to_monitor: Vec<String>,
+    walk_minutes: HashMap<String, i64>,
+    min_lead_minutes: HashMap<String, i64>,
+    agency: String,
+    service_alerts: ServiceAlertsConfig,
+    schedule: Schedule,
+    http_api: HttpApiConfig,
+    mqtt: MqttConfig,
+    messages: MessagesConfig,
+    calendar: CalendarConfig,
+    weather: Option<WeatherConfig>,
+    hash: String,
 }
 
 impl Config {
-    pub fn new(config: &Yaml) -> Self {
+    // Reads and validates a config file with overrides applied in order,
+    // returning it along with any warnings
+    pub fn load(
+        path: &Path,
+        overrides: &[ConfigOverride],
+    ) -> Result<(Self, Vec<ConfigIssue>), ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(source) => Self::parse(&source, overrides),
+            Err(e) => Err(ConfigError {
+                errors: vec![ConfigIssue {
+                    path: String::new(),
+                    line: None,
+                    origin: None,
+                    message: format!("unable to read {}: {}", path.display(), e),
+                }],
+                warnings: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn parse(
+        source: &str,
+        overrides: &[ConfigOverride],
+    ) -> Result<(Self, Vec<ConfigIssue>), ConfigError> {
+        let mut checker = ConfigChecker::new(source);
+        let documents = match YamlLoader::load_from_str(source) {
+            Ok(documents) => documents,
+            Err(e) => {
+                return Err(ConfigError {
+                    errors: vec![ConfigIssue {
+                        path: String::new(),
+                        line: Some(e.marker().line()),
+                        origin: None,
+                        message: format!("invalid YAML: {}", e.info()),
+                    }],
+                    warnings: Vec::new(),
+                });
+            }
+        };
+        let mut config = documents.into_iter().next().unwrap_or(Yaml::Null);
+        // an empty file is fine when everything is set from outside it
+        if ConfigChecker::is_missing(&config) {
+            config = Yaml::Hash(Hash::new());
+        }
+        if !matches!(config, Yaml::Hash(_)) {
+            checker.error("", "expected a mapping of keys to values");
+            let (errors, warnings) = checker.finish();
+            return Err(ConfigError { errors, warnings });
+        }
+        for config_override in overrides {
+            config_override.apply(&mut config, &mut checker);
+        }
+        let mut config = Self::new(&config, &mut checker);
+        config.hash = config_hash(source, overrides);
+        let (errors, warnings) = checker.finish();
+        if errors.is_empty() {
+            Ok((config, warnings))
+        } else {
+            Err(ConfigError { errors, warnings })
+        }
+    }
+
+    pub fn new(config: &Yaml, checker: &mut ConfigChecker) -> Self {
+        checker.check_keys(
+            config,
+            "",
+            &[
+                "api_key",
+                "api_key_file",
+                "display_board_config",
+                "rapid_line_to_parent_line_map",
+                "stops_to_monitor",
+                "agency",
+                "service_alerts",
+                "schedule",
+                "http_api",
+                "mqtt",
+                "messages",
+                "calendar",
+                "weather",
+            ],
+        );
+        let (stops_to_monitor, walk_minutes, min_lead_minutes) =
+            stops_to_monitor(&config["stops_to_monitor"], checker);
+        if ConfigChecker::is_missing(&config["stops_to_monitor"])
+            || config["stops_to_monitor"].as_vec().is_some_and(|v| v.is_empty())
+        {
+            checker.error("stops_to_monitor", "at least one stop code is required");
+        }
+        let display_board_config = DisplayBoardConfig::new(&config["display_board_config"], checker);
         Self {
-            display_board_config: DisplayBoardConfig::new(&config["display_board_config"]),
-            rapid_line_to_parent_line_map: config["rapid_line_to_parent_line_map"]
-                .as_hash()
-                .unwrap()
-                .iter()
-                .map(|(k, v)| {
-                    (
-                        k.as_str().unwrap().to_string(),
-                        v.as_str().unwrap().to_string(),
-                    )
-                })
-                .collect(),
-            stops_to_monitor: config["stops_to_monitor"]
-                .as_vec()
-                .unwrap()
-                .iter()
-                .map(|x| x.as_str().unwrap().to_string())
-                .collect(),
+            api_key: load_api_key(config, checker),
+            schedule: schedule(&config["schedule"], &display_board_config, checker),
+            http_api: HttpApiConfig::new(&config["http_api"], checker),
+            mqtt: MqttConfig::new(&config["mqtt"], checker),
+            messages: MessagesConfig::new(&config["messages"], checker),
+            calendar: CalendarConfig::new(&config["calendar"], checker),
+            weather: WeatherConfig::new(&config["weather"], checker),
+            hash: String::new(),
+            display_board_config,
+            rapid_line_to_parent_line_map: checker.string_map(
+                &config["rapid_line_to_parent_line_map"],
+                "rapid_line_to_parent_line_map",
+            ),
+            stops_to_monitor,
+            walk_minutes,
+            min_lead_minutes,
+            agency: checker.optional_string(&config["agency"], "agency", "SF"),
+            service_alerts: ServiceAlertsConfig::new(&config["service_alerts"], checker),
         }
     }
+    // Only commands that fetch from 511 need a key, so a missing one is an
+    // error for them rather than for the config
+    pub fn api_key(&self) -> Result<&str, String> {
+        self.api_key.as_deref().ok_or_else(|| {
+            format!(
+                "no 511 API key, set `api_key_file`, `api_key` or the {} environment variable",
+                TOKEN_ENV
+            )
+        })
+    }
     pub fn display_board_config(&self) -> &DisplayBoardConfig {
         &self.display_board_config
     }
@@ -86,4 +1598,58 @@ impl Config {
     pub fn stops_to_monitor(&self) -> &Vec<String> {
         &self.stops_to_monitor
     }
+    // Minutes it takes to walk to each stop that has a walk time set
+    pub fn walk_minutes(&self) -> &HashMap<String, i64> {
+        &self.walk_minutes
+    }
+    // Arrivals sooner than this at a stop are left off, there's no making them
+    pub fn min_lead_minutes(&self) -> &HashMap<String, i64> {
+        &self.min_lead_minutes
+    }
+    // 511 operator id the stops belong to
+    pub fn agency(&self) -> &str {
+        &self.agency
+    }
+    pub fn service_alerts(&self) -> &ServiceAlertsConfig {
+        &self.service_alerts
+    }
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+    pub fn http_api(&self) -> &HttpApiConfig {
+        &self.http_api
+    }
+    pub fn mqtt(&self) -> &MqttConfig {
+        &self.mqtt
+    }
+    pub fn messages(&self) -> &MessagesConfig {
+        &self.messages
+    }
+    pub fn calendar(&self) -> &CalendarConfig {
+        &self.calendar
+    }
+    pub fn weather(&self) -> Option<&WeatherConfig> {
+        self.weather.as_ref()
+    }
+    // Changes whenever the file or overrides do, to tell which config a board
+    // is running
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+}
+
+// FNV-1a of the file and overrides, only needs to tell configs apart
+fn config_hash(source: &str, overrides: &[ConfigOverride]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut add = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    add(source.as_bytes());
+    for config_override in overrides {
+        add(format!("\n{}={}", config_override.path, config_override.value).as_bytes());
+    }
+    format!("{:016x}", hash)
 }
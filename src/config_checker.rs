@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use yaml_rust2::parser::{MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+use yaml_rust2::{Event, Yaml};
+
+// A problem found in the config, path is dotted e.g. display_board_config.rows
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub path: String,
+    pub line: Option<usize>,
+    // set when the value came from an override rather than the file,
+    // e.g. TRANSIT_AGENCY or --set agency=AC
+    pub origin: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "<root>" } else { &self.path };
+        match (&self.origin, self.line) {
+            (Some(origin), _) => write!(f, "{}, {}: {}", origin, path, self.message),
+            (None, Some(line)) => write!(f, "line {}, {}: {}", line, path, self.message),
+            (None, None) => write!(f, "{}: {}", path, self.message),
+        }
+    }
+}
+
+// Returned when a config can't be used. Warnings are kept as they often explain
+// the error (a misspelt key shows up as unknown and as missing).
+#[derive(Debug)]
+pub struct ConfigError {
+    pub errors: Vec<ConfigIssue>,
+    pub warnings: Vec<ConfigIssue>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "invalid config:")?;
+        for error in &self.errors {
+            writeln!(f, "  error: {}", error)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// Scalars that can stand in for a string, so `22:` and `"22":` are the same key
+pub fn scalar_string(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+enum Frame {
+    Mapping { path: String, key: Option<String> },
+    Sequence { path: String, index: usize },
+}
+
+// Line each key/item starts on, keyed by path. yaml_rust2's Yaml drops
+// positions, so they're collected from the parser events.
+#[derive(Default)]
+struct LineIndex {
+    lines: HashMap<String, usize>,
+    stack: Vec<Frame>,
+}
+
+impl LineIndex {
+    // Path of the node that starts now, moving the parent on to its next child
+    fn next_node_path(&mut self, mark: Marker) -> Option<String> {
+        match self.stack.last_mut() {
+            None => Some(String::new()),
+            Some(Frame::Mapping { path, key }) => key.take().map(|key| child_path(path, &key)),
+            Some(Frame::Sequence { path, index }) => {
+                let item_path = format!("{}[{}]", path, index);
+                *index += 1;
+                self.lines.entry(item_path.clone()).or_insert(mark.line());
+                Some(item_path)
+            }
+        }
+    }
+}
+
+impl MarkedEventReceiver for LineIndex {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Mapping { path, key }) = self.stack.last_mut() {
+                    if key.is_none() {
+                        // a key, remember it for the value that follows
+                        self.lines.entry(child_path(path, &value)).or_insert(mark.line());
+                        *key = Some(value);
+                        return;
+                    }
+                }
+                self.next_node_path(mark);
+            }
+            Event::Alias(_) => {
+                self.next_node_path(mark);
+            }
+            Event::MappingStart(..) => {
+                let path = self.next_node_path(mark).unwrap_or_default();
+                self.stack.push(Frame::Mapping { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.next_node_path(mark).unwrap_or_default();
+                self.stack.push(Frame::Sequence { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            _ => (),
+        }
+    }
+}
+
+// Reads values out of the config while collecting everything wrong with it,
+// so one run reports every problem instead of panicking on the first
+pub struct ConfigChecker {
+    lines: HashMap<String, usize>,
+    origins: HashMap<String, String>,
+    errors: Vec<ConfigIssue>,
+    warnings: Vec<ConfigIssue>,
+}
+
+impl ConfigChecker {
+    pub fn new(source: &str) -> Self {
+        let mut index = LineIndex::default();
+        // syntax errors are reported by YamlLoader, the index is best effort
+        let _ = Parser::new_from_str(source).load(&mut index, false);
+        ConfigChecker {
+            lines: index.lines,
+            origins: HashMap::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // Record that the value at path was overridden, issues under it point at
+    // origin instead of a line in the file
+    pub fn set_origin(&mut self, path: &str, origin: &str) {
+        self.origins.insert(path.to_string(), origin.to_string());
+    }
+
+    // Line of path, or of its closest parent for keys that are missing
+    pub fn line(&self, path: &str) -> Option<usize> {
+        closest(&self.lines, path).copied()
+    }
+
+    fn issue(&self, path: &str, message: String) -> ConfigIssue {
+        ConfigIssue {
+            path: path.to_string(),
+            line: self.line(path),
+            origin: closest(&self.origins, path).cloned(),
+            message,
+        }
+    }
+
+    pub fn error(&mut self, path: &str, message: impl Into<String>) {
+        let issue = self.issue(path, message.into());
+        self.errors.push(issue);
+    }
+
+    pub fn warn(&mut self, path: &str, message: impl Into<String>) {
+        let issue = self.issue(path, message.into());
+        self.warnings.push(issue);
+    }
+
+    // Overridden scalars arrive as strings, read them the way YAML would for
+    // keys that want a number or boolean
+    fn typed(&self, config: &Yaml, path: &str) -> Yaml {
+        match config {
+            Yaml::String(s) if closest(&self.origins, path).is_some() => Yaml::from_str(s.trim()),
+            _ => config.clone(),
+        }
+    }
+
+    pub fn finish(self) -> (Vec<ConfigIssue>, Vec<ConfigIssue>) {
+        (self.errors, self.warnings)
+    }
+
+    // Warn about keys we don't know, with a suggestion when it looks like a typo
+    pub fn check_keys(&mut self, config: &Yaml, path: &str, known: &[&str]) {
+        let hash = match config.as_hash() {
+            Some(hash) => hash,
+            None => return,
+        };
+        for key in hash.keys() {
+            let key = scalar_string(key).unwrap_or_default();
+            if known.contains(&key.as_str()) {
+                continue;
+            }
+            let message = match known.iter().min_by_key(|k| edit_distance(k, &key)) {
+                Some(suggestion) if edit_distance(suggestion, &key) <= 2 => {
+                    format!("unknown key, did you mean `{}`?", suggestion)
+                }
+                _ => format!("unknown key, expected one of: {}", known.join(", ")),
+            };
+            self.warn(&child_path(path, &key), message);
+        }
+    }
+
+    // Fails when the section isn't a mapping, missing sections are fine
+    pub fn section(&mut self, config: &Yaml, path: &str) -> bool {
+        match config {
+            Yaml::Hash(_) | Yaml::BadValue | Yaml::Null => true,
+            _ => {
+                self.error(path, "expected a mapping of keys to values");
+                false
+            }
+        }
+    }
+
+    pub fn is_missing(config: &Yaml) -> bool {
+        matches!(config, Yaml::BadValue | Yaml::Null)
+    }
+
+    pub fn string(&mut self, config: &Yaml, path: &str) -> Option<String> {
+        if Self::is_missing(config) {
+            self.error(path, "required key is missing");
+            return None;
+        }
+        let value = scalar_string(config);
+        if value.is_none() {
+            self.error(path, "expected a string");
+        }
+        value
+    }
+
+    pub fn optional_string(&mut self, config: &Yaml, path: &str, default: &str) -> String {
+        if Self::is_missing(config) {
+            return default.to_string();
+        }
+        self.string(config, path).unwrap_or_else(|| default.to_string())
+    }
+
+    pub fn optional_integer(
+        &mut self,
+        config: &Yaml,
+        path: &str,
+        default: i64,
+        range: RangeInclusive<i64>,
+    ) -> i64 {
+        if Self::is_missing(config) {
+            return default;
+        }
+        match self.typed(config, path).as_i64() {
+            Some(value) if range.contains(&value) => value,
+            Some(value) => {
+                self.error(
+                    path,
+                    format!(
+                        "{} is out of range, expected {} to {}",
+                        value,
+                        range.start(),
+                        range.end()
+                    ),
+                );
+                default
+            }
+            None => {
+                self.error(path, "expected a whole number");
+                default
+            }
+        }
+    }
+
+    pub fn optional_float(
+        &mut self,
+        config: &Yaml,
+        path: &str,
+        default: f64,
+        range: RangeInclusive<f64>,
+    ) -> f64 {
+        if Self::is_missing(config) {
+            return default;
+        }
+        let value = match self.typed(config, path) {
+            Yaml::Integer(i) => Some(i as f64),
+            config => config.as_f64(),
+        };
+        match value {
+            Some(value) if range.contains(&value) => value,
+            Some(value) => {
+                self.error(
+                    path,
+                    format!(
+                        "{} is out of range, expected {} to {}",
+                        value,
+                        range.start(),
+                        range.end()
+                    ),
+                );
+                default
+            }
+            None => {
+                self.error(path, "expected a number");
+                default
+            }
+        }
+    }
+
+    pub fn optional_bool(&mut self, config: &Yaml, path: &str, default: bool) -> bool {
+        if Self::is_missing(config) {
+            return default;
+        }
+        match self.typed(config, path).as_bool() {
+            Some(value) => value,
+            None => {
+                self.error(path, "expected true or false");
+                default
+            }
+        }
+    }
+
+    // Value must be one of choices
+    pub fn optional_choice(
+        &mut self,
+        config: &Yaml,
+        path: &str,
+        default: &str,
+        choices: &[&str],
+    ) -> String {
+        let value = self.optional_string(config, path, default);
+        if !choices.contains(&value.as_str()) {
+            self.error(
+                path,
+                format!("`{}` is not one of: {}", value, choices.join(", ")),
+            );
+            return default.to_string();
+        }
+        value
+    }
+
+    // Mapping of string to string, empty when missing
+    pub fn string_map(&mut self, config: &Yaml, path: &str) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        if !self.section(config, path) {
+            return map;
+        }
+        for (k, v) in config.as_hash().into_iter().flatten() {
+            let key = scalar_string(k).unwrap_or_default();
+            let key_path = child_path(path, &key);
+            if let Some(value) = self.string(v, &key_path) {
+                map.insert(key, value);
+            }
+        }
+        map
+    }
+
+    // Sequence of strings, empty when missing
+    pub fn string_list(&mut self, config: &Yaml, path: &str) -> Vec<String> {
+        if Self::is_missing(config) {
+            return Vec::new();
+        }
+        let items = match config.as_vec() {
+            Some(items) => items,
+            None => {
+                self.error(path, "expected a list");
+                return Vec::new();
+            }
+        };
+        items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| self.string(item, &format!("{}[{}]", path, i)))
+            .collect()
+    }
+}
+
+// Value for path or the closest of its parents
+fn closest<'a, T>(map: &'a HashMap<String, T>, path: &str) -> Option<&'a T> {
+    let mut path = path;
+    loop {
+        if let Some(value) = map.get(path) {
+            return Some(value);
+        }
+        match path.rfind(['.', '[']) {
+            Some(i) => path = &path[..i],
+            None => return map.get(""),
+        }
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust2::YamlLoader;
+
+    const CONFIG: &str = "agency: SF
+display_board_config:
+  rows: 32
+  walk_minutes:
+    N: 5
+stops:
+  - \"13915\"
+  - \"13916\"
+";
+
+    fn issue_for<'a>(issues: &'a [ConfigIssue], path: &str) -> &'a ConfigIssue {
+        issues.iter().find(|i| i.path == path).unwrap()
+    }
+
+    #[test]
+    fn lines_for_nested_keys_and_sequence_items() {
+        let checker = ConfigChecker::new(CONFIG);
+        assert_eq!(checker.line("agency"), Some(1));
+        assert_eq!(checker.line("display_board_config.rows"), Some(3));
+        assert_eq!(checker.line("display_board_config.walk_minutes.N"), Some(5));
+        assert_eq!(checker.line("stops[1]"), Some(8));
+    }
+
+    #[test]
+    fn missing_keys_point_at_their_closest_parent() {
+        let checker = ConfigChecker::new(CONFIG);
+        assert_eq!(checker.line("display_board_config.cols"), Some(2));
+        assert_eq!(checker.line("display_board_config.walk_minutes.J"), Some(4));
+        assert_eq!(checker.line("stops[5]"), Some(6));
+        // the root has no line of its own
+        assert_eq!(checker.line("api_key"), None);
+    }
+
+    #[test]
+    fn issues_carry_their_line() {
+        let mut checker = ConfigChecker::new(CONFIG);
+        checker.error("display_board_config.rows", "too many");
+        checker.error("", "expected a mapping of keys to values");
+        let (errors, _) = checker.finish();
+        assert_eq!(
+            issue_for(&errors, "display_board_config.rows").to_string(),
+            "line 3, display_board_config.rows: too many"
+        );
+        assert_eq!(issue_for(&errors, "").to_string(), "<root>: expected a mapping of keys to values");
+    }
+
+    #[test]
+    fn overridden_issues_name_their_origin() {
+        let mut checker = ConfigChecker::new(CONFIG);
+        checker.set_origin("display_board_config", "--set display_board_config={rows: 99}");
+        checker.error("display_board_config.rows", "too many");
+        checker.error("agency", "unknown agency");
+        let (errors, _) = checker.finish();
+        assert_eq!(
+            issue_for(&errors, "display_board_config.rows").to_string(),
+            "--set display_board_config={rows: 99}, display_board_config.rows: too many"
+        );
+        assert_eq!(issue_for(&errors, "agency").to_string(), "line 1, agency: unknown agency");
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("stops", "stops"), 0);
+        assert_eq!(edit_distance("stops", "stop"), 1);
+        assert_eq!(edit_distance("agency", "agnecy"), 2);
+        assert_eq!(edit_distance("rows", "cols"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn check_keys_suggests_close_matches() {
+        let source = "agency: SF\nstopz: []\nbrightness_level: 3\n";
+        let config = &YamlLoader::load_from_str(source).unwrap()[0];
+        let mut checker = ConfigChecker::new(source);
+        checker.check_keys(config, "", &["agency", "stops", "api_key"]);
+        let (errors, warnings) = checker.finish();
+        assert!(errors.is_empty());
+        assert_eq!(warnings.len(), 2);
+        let typo = issue_for(&warnings, "stopz");
+        assert_eq!(typo.line, Some(2));
+        assert_eq!(typo.message, "unknown key, did you mean `stops`?");
+        assert_eq!(
+            issue_for(&warnings, "brightness_level").message,
+            "unknown key, expected one of: agency, stops, api_key"
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use log::{debug, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+// Sends on reload_tx whenever the config file is written, created or moved
+// into place. The directory is watched rather than the file itself because
+// most editors save by writing a new file and renaming it over the old one,
+// which would leave a watch on the file pointing at the deleted copy.
+// The watcher stops when the returned value is dropped.
+pub fn watch_config(
+    config_path: &Path,
+    reload_tx: mpsc::UnboundedSender<()>,
+) -> notify::Result<RecommendedWatcher> {
+    let file_name = config_path.file_name().map(|f| f.to_os_string());
+    let dir = match config_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            if !matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_)
+            ) {
+                return;
+            }
+            if event
+                .paths
+                .iter()
+                .any(|p| p.file_name().map(|f| f.to_os_string()) == file_name)
+            {
+                debug!("config file changed: {:?}", event.kind);
+                let _ = reload_tx.send(());
+            }
+        }
+        Err(e) => warn!("config watch error: {:?}", e),
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
@@ -1,58 +1,32 @@
-use chrono::{DateTime, Datelike, Local};
-use std::collections::HashMap;
+use chrono::{DateTime, Local, Utc};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::time::Instant;
 use std::{thread, time};
 
-use crate::stop_monitor::MonitoredVehicleJourney;
-use log::debug;
-use rpi_led_matrix::{LedColor, LedFont, LedMatrix, LedMatrixOptions, LedCanvas};
-
-// CONSTANTS for display
-
-// top line purplihs
-const TOP_LINE_COLOR: LedColor = LedColor {
-    red: 0,
-    green: 0,
-    blue: 150,
+use crate::calendar::CalendarSlot;
+use crate::config::{
+    DestinationColumnConfig, DestinationMode, DisplayBoardConfig, ShutdownConfig, UrgencyConfig, WeatherConfig,
 };
+use crate::font::BdfFont;
+use crate::frame::{FrameBuffer, Rect};
+use crate::marquee::Marquee;
+use crate::messages::{Message, Messages, ShownMessage};
+use crate::schedule::ScheduleState;
+use crate::service_alerts::ServiceAlert;
+use crate::stop_monitor::{DisplayRow, MonitoredVehicleJourney, Occupancy};
+use crate::theme::Theme;
+use crate::weather::{self, IconPixel, WeatherReport};
+use log::{debug, trace, warn};
+use rpi_led_matrix::{LedCanvas, LedColor, LedMatrix, LedMatrixOptions};
 
-// Line name color
-const LINE_NAME_COLOR: LedColor = LedColor {
-    red: 255,
-    green: 255,
-    blue: 20,
-};
-
-// Time to arrival color
-const TTA_COLOR: LedColor = LedColor {
-    red: 255,
-    green: 140,
-    blue: 0,
-};
-// Used for most text on teh board
-const STANDARD_COLOR: LedColor = LedColor {
-    red: 0,
-    green: 127,
-    blue: 255,
-};
-
-// legacy: used to distinguish rapid line times when we aggregate line time
-const RAPID_LINE_COLOR: LedColor = LedColor {
-    red: 255,
-    green: 0,
-    blue: 0,
-};
-
-// COlor of the dot used to indicate that there is no locaiton
-const NO_LOC_COLOR: LedColor = LedColor {
-    red: 255,
-    green: 0,
-    blue: 0,
-};
+// CONSTANTS for display
 
 // Number of column used for Line Refs (e.g. 9, 22, 14R)
 const LINE_REF_N_CHARS: usize = 4;
 const LINE_REF_BUFFER_COLS: i32 = 6;
+// Number of led pixel width to provide after the destination column
+const DESTINATION_BUFFER_COLS: i32 = 2;
 // Number of chars to use for time to arrivals
 const TTA_N_CHARS: usize = 2;
 // Number of led pixel width to provvide for TTA
@@ -63,23 +37,96 @@ const COL_WIDTH: i32 = 64;
 
 const FONT_HEIGHT: i32 = 6;
 
+// Number of (col, row) cells a panel of this size has room for below the header
+pub fn panel_grid_size(width: i32, height: i32) -> (i32, i32) {
+    let cols = (width / COL_WIDTH).max(1);
+    // last row whose text still fits on the panel
+    let rows = (height - 1) / (FONT_HEIGHT + 1) - 1;
+    (cols, rows.max(1))
+}
+
 pub struct DisplayBoard {
-    pub display_lines: Option<HashMap<String, Vec<MonitoredVehicleJourney>>>,
+    pub display_lines: Option<HashMap<DisplayRow, Vec<MonitoredVehicleJourney>>>,
     pub last_successful_request_time: Option<DateTime<Local>>,
     pub last_request_successful: bool,
-    pub led_matrix: LedMatrix,
-    pub led_canvas: LedCanvas,
-    pub font: LedFont,
+    // why the last fetch failed, None once one succeeds
+    pub last_fetch_error: Option<String>,
+    // None for a headless board
+    pub led_matrix: Option<LedMatrix>,
+    // offscreen canvas, swapped onto the matrix by present()
+    pub led_canvas: Option<LedCanvas>,
+    pub frame: FrameBuffer,
+    pub font: BdfFont,
     pub display_position_map: HashMap<String, (i32, i32)>,
+    pub destination_column: DestinationColumnConfig,
+    // scroll speed in pixels per second
+    pub marquee_speed: f32,
+    destination_marquees: HashMap<DisplayRow, Marquee>,
+    // how long each page is shown when there are more rows than cells
+    pub page_dwell: time::Duration,
+    started: Instant,
+    pub service_alerts: Vec<ServiceAlert>,
+    pub show_alert_ticker: bool,
+    // Some while there are alerts or a message to scroll along the bottom row
+    alert_ticker: Option<Marquee>,
+    // shown on the bottom row in place of the alerts
+    pub messages: Messages,
+    // message the ticker was last set to, None while it shows alerts
+    shown_message: Option<Message>,
+    // shown in the status area instead of the last update time while set
+    pub status_error: Option<String>,
+    status_marquee: Option<Marquee>,
+    // today's and upcoming events when the calendar goes in place of the clock
+    header_agenda: Option<Message>,
+    agenda_marquee: Option<Marquee>,
+    // where the weather goes, None when there's no weather on the board
+    weather: Option<WeatherConfig>,
+    pub weather_report: Option<WeatherReport>,
+    // minutes to walk to a stop, by stop code, for coloring arrival times
+    pub walk_minutes: HashMap<String, i64>,
+    urgency: UrgencyConfig,
+    // show minutes until it's time to leave rather than until arrival
+    leave_in: bool,
+    // colors in use, switchable with set_theme
+    pub theme: Theme,
+    theme_name: String,
+    themes: HashMap<String, Theme>,
+    // theme named in the config, a reload only switches theme when it changes
+    configured_theme: String,
+    // percents, from the schedule and the auto brightness, applied together
+    // when the frame is copied to the matrix
+    pub brightness: u8,
+    pub ambient_brightness: u8,
+    // set through the HTTP API, a fixed brightness or page in place of the
+    // schedule and auto brightness or the paging
+    pub brightness_override: Option<u8>,
+    pub page_override: Option<usize>,
+    // switched off through the HTTP API or MQTT, blank like the schedule's blank
+    switched_off: bool,
+    // (page, pages) last drawn
+    shown_page: (usize, usize),
+    // set by the schedule: nothing drawn while blank, only rows for these
+    // lines, a different layout or theme
+    blank: bool,
+    line_filter: Option<HashSet<String>>,
+    schedule_positions: Option<HashMap<String, (i32, i32)>>,
+    schedule_theme: Option<String>,
+    // names of the schedule rules in effect
+    schedule_rules: Vec<String>,
+    shutdown: ShutdownConfig,
 }
 
 pub struct RGBDisplayLine {
+    row: DisplayRow,
     line: Vec<LineString>,
 }
 
 impl RGBDisplayLine {
-    pub fn new() -> Self {
-        RGBDisplayLine { line: Vec::new() }
+    pub fn new(row: DisplayRow) -> Self {
+        RGBDisplayLine {
+            row,
+            line: Vec::new(),
+        }
     }
 }
 
@@ -88,64 +135,311 @@ pub struct LineString {
     color: LedColor,
     has_loc: bool,
     is_line_ref: bool,
+    is_destination: bool,
+    occupancy: Occupancy,
+}
+
+impl Default for LineString {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LineString {
     pub fn new() -> Self {
         LineString {
             string: "".to_string(),
-            color: STANDARD_COLOR,
+            color: Theme::default().text,
             has_loc: false,
             is_line_ref: false,
+            is_destination: false,
+            occupancy: Occupancy::Unknown,
         }
     }
 }
 
 impl DisplayBoard {
-    pub fn new(
-        rows: u32,
-        cols: u32,
-        chained: u32,
-        font_file: &Path,
-        display_position_map: &HashMap<String, (i32, i32)>,
-    ) -> Result<Self, &'static str> {
+    // Board driving the LED matrix
+    pub fn new(config: &DisplayBoardConfig) -> Result<Self, &'static str> {
         let mut options = LedMatrixOptions::new();
-        debug!("Setting rows to {}", rows);
-        debug!("Setting cols to {}", cols);
-        options.set_rows(rows);
-        options.set_cols(cols);
-        debug!("Setting chain length to {}", chained);
-        options.set_chain_length(2);
+        debug!("Setting rows to {}", config.rows());
+        debug!("Setting cols to {}", config.cols());
+        options.set_rows(config.rows());
+        options.set_cols(config.cols());
+        debug!("Setting chain length to {}", config.chained());
+        options.set_chain_length(config.chained());
         options.set_hardware_mapping("adafruit-hat");
 
         let led_matrix = LedMatrix::new(Some(options), None)?;
         debug!("creating canvas");
-        let mut led_canvas = led_matrix.canvas();
-        debug!("loading font from {:?}", font_file);
-        let font = LedFont::new(font_file)?;
+        let led_canvas = led_matrix.offscreen_canvas();
+        let mut d = Self::headless(config)?;
+        d.led_matrix = Some(led_matrix);
+        d.led_canvas = Some(led_canvas);
+        Ok(d)
+    }
+
+    // Board that only draws into its frame, for rendering without a matrix
+    pub fn headless(config: &DisplayBoardConfig) -> Result<Self, &'static str> {
+        debug!("loading font from {:?}", config.font_file());
+        let font = BdfFont::new(Path::new(config.font_file()))?;
 
-        let mut d = DisplayBoard {
+        let d = DisplayBoard {
             display_lines: None,
             last_successful_request_time: None,
             last_request_successful: false,
-            led_matrix: led_matrix,
-            led_canvas: led_canvas,
-            font: font,
-            display_position_map: display_position_map.clone(),
+            last_fetch_error: None,
+            led_matrix: None,
+            led_canvas: None,
+            frame: FrameBuffer::new(
+                (config.cols() * config.chained()) as i32,
+                config.rows() as i32,
+            ),
+            font,
+            display_position_map: config.line_ref_to_display_position().clone(),
+            destination_column: config.destination_column().clone(),
+            marquee_speed: config.marquee_speed(),
+            destination_marquees: HashMap::new(),
+            page_dwell: time::Duration::from_secs(config.page_dwell_secs()),
+            started: Instant::now(),
+            service_alerts: Vec::new(),
+            show_alert_ticker: false,
+            alert_ticker: None,
+            messages: Messages::default(),
+            shown_message: None,
+            status_error: None,
+            status_marquee: None,
+            header_agenda: None,
+            agenda_marquee: None,
+            weather: None,
+            weather_report: None,
+            walk_minutes: HashMap::new(),
+            urgency: config.urgency().clone(),
+            leave_in: config.leave_in(),
+            theme: config.themes()[config.theme()].clone(),
+            theme_name: config.theme().to_string(),
+            themes: config.themes().clone(),
+            configured_theme: config.theme().to_string(),
+            brightness: 100,
+            ambient_brightness: 100,
+            brightness_override: None,
+            page_override: None,
+            switched_off: false,
+            shown_page: (0, 0),
+            blank: false,
+            line_filter: None,
+            schedule_positions: None,
+            schedule_theme: None,
+            schedule_rules: Vec::new(),
+            shutdown: config.shutdown().clone(),
         };
         Ok(d)
     }
 
+    // Apply a reloaded config to the running board. The panel size is fixed
+    // once the matrix is initialized so changing it still needs a restart.
+    pub fn apply_config(&mut self, config: &DisplayBoardConfig) -> Result<(), &'static str> {
+        let width = (config.cols() * config.chained()) as i32;
+        if width != self.frame.width() || config.rows() as i32 != self.frame.height() {
+            warn!("panel size changes (rows, cols, chained) need a restart to take effect");
+        }
+        self.font = BdfFont::new(Path::new(config.font_file()))?;
+        self.display_position_map = config.line_ref_to_display_position().clone();
+        self.destination_column = config.destination_column().clone();
+        self.marquee_speed = config.marquee_speed();
+        for marquee in self.destination_marquees.values_mut() {
+            marquee.set_speed(self.marquee_speed);
+        }
+        if let Some(ticker) = self.alert_ticker.as_mut() {
+            ticker.set_speed(self.marquee_speed);
+        }
+        self.page_dwell = time::Duration::from_secs(config.page_dwell_secs());
+        self.urgency = config.urgency().clone();
+        self.leave_in = config.leave_in();
+        self.shutdown = config.shutdown().clone();
+        self.themes = config.themes().clone();
+        // keep a theme switched to at runtime (or by the schedule) unless the
+        // config picks another
+        let theme = if config.theme() != self.configured_theme || !self.themes.contains_key(&self.theme_name) {
+            config.theme().to_string()
+        } else {
+            self.theme_name.clone()
+        };
+        self.configured_theme = config.theme().to_string();
+        self.set_theme(&theme)?;
+        Ok(())
+    }
+
+    // Take on what the schedule asks for right now
+    pub fn apply_schedule(&mut self, state: &ScheduleState) {
+        self.brightness = state.brightness;
+        if state.blank && !self.is_blank() {
+            self.forget_arrivals();
+        }
+        self.blank = state.blank;
+        self.line_filter = state.lines.clone();
+        self.schedule_positions = state.line_ref_to_display_position.clone();
+        if state.theme != self.schedule_theme {
+            let theme = state.theme.clone().unwrap_or_else(|| self.configured_theme.clone());
+            if self.set_theme(&theme).is_err() {
+                warn!("schedule asks for theme {}, which doesn't exist", theme);
+            }
+            self.schedule_theme = state.theme.clone();
+        }
+        self.schedule_rules = state.active.clone();
+    }
+
+    pub fn schedule_rules(&self) -> &[String] {
+        &self.schedule_rules
+    }
+
+    // Percent the board is lit at, the schedule scaling the auto brightness
+    // unless there's an override
+    pub fn effective_brightness(&self) -> u8 {
+        if let Some(brightness) = self.brightness_override {
+            return brightness;
+        }
+        (self.brightness as u32 * self.ambient_brightness as u32 / 100) as u8
+    }
+
+    // Blank for the schedule or switched off
+    pub fn is_blank(&self) -> bool {
+        self.blank || self.switched_off
+    }
+
+    pub fn is_on(&self) -> bool {
+        !self.switched_off
+    }
+
+    pub fn set_on(&mut self, on: bool) {
+        if !on && !self.is_blank() {
+            self.forget_arrivals();
+        }
+        self.switched_off = !on;
+    }
+
+    // whatever was showing will be stale by the time the board wakes
+    fn forget_arrivals(&mut self) {
+        self.display_lines = None;
+        self.last_successful_request_time = None;
+    }
+
+    // (page, pages) last drawn, page counting from 0
+    pub fn shown_page(&self) -> (usize, usize) {
+        self.shown_page
+    }
+
+    // Post a message for the bottom row, for a while or until cleared. It
+    // replaces the last one posted.
+    pub fn set_message(&mut self, message: Message, duration: Option<time::Duration>) {
+        self.messages.post(message, duration);
+    }
+
+    pub fn clear_message(&mut self) {
+        self.messages.clear_posted();
+    }
+
+    // Message that's due right now, from whichever source
+    pub fn message(&self) -> Option<ShownMessage<'_>> {
+        self.messages.current(Local::now(), Instant::now())
+    }
+
+    // Events from the calendar, on the bottom row like any other message or in
+    // place of the clock
+    pub fn show_agenda(&mut self, agenda: Option<(CalendarSlot, Message)>) {
+        let (ticker, header) = match agenda {
+            Some((CalendarSlot::Ticker, message)) => (Some(message), None),
+            Some((CalendarSlot::Header, message)) => (None, Some(message)),
+            None => (None, None),
+        };
+        self.messages.set_calendar(ticker);
+        self.header_agenda = header;
+    }
+
+    // Draw the weather where the config puts it, or stop drawing it
+    pub fn set_weather(&mut self, config: Option<WeatherConfig>) {
+        if config.is_none() {
+            self.weather_report = None;
+        }
+        self.weather = config;
+    }
+
+    pub fn theme_name(&self) -> &str {
+        &self.theme_name
+    }
+
+    // Theme names, sorted
+    pub fn theme_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.themes.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn set_theme(&mut self, name: &str) -> Result<(), &'static str> {
+        let theme = self.themes.get(name).ok_or("no theme with that name")?;
+        self.theme = theme.clone();
+        self.theme_name = name.to_string();
+        Ok(())
+    }
+
+    // Switch to the theme after the current one, by name
+    pub fn next_theme(&mut self) {
+        let names = self.theme_names();
+        let next = names
+            .iter()
+            .position(|n| *n == self.theme_name)
+            .map(|i| (i + 1) % names.len())
+            .unwrap_or(0);
+        if let Some(name) = names.get(next) {
+            let _ = self.set_theme(&name.clone());
+        }
+    }
+
+    // Put up the configured shutdown frame, blank unless there's text for it,
+    // and return how long it should stay up
+    pub fn show_shutdown_frame(&mut self) -> time::Duration {
+        self.frame.clear();
+        if let Some(text) = self.shutdown.text() {
+            let lines: Vec<&str> = text.lines().collect();
+            let line_height = FONT_HEIGHT + 1;
+            // centered both ways, y is the bottom row of each line
+            let top = (self.frame.height() - line_height * lines.len() as i32) / 2;
+            for (i, line) in lines.iter().enumerate() {
+                let x = (self.frame.width() - self.font.text_width(line)) / 2;
+                let y = top + line_height * (i as i32 + 1) - 1;
+                self.frame.draw_text(&self.font, line, x.max(0), y, &self.theme.text);
+            }
+        }
+        self.present();
+        time::Duration::from_secs(self.shutdown.hold_secs())
+    }
+
+    // Blank the panel and let go of the matrix, which stops it being driven
+    pub fn release(&mut self) {
+        self.frame.clear();
+        self.present();
+        self.led_canvas = None;
+        self.led_matrix = None;
+    }
+
+    // Copy the frame onto the offscreen canvas and swap it onto the matrix
+    pub fn present(&mut self) {
+        if let (Some(led_matrix), Some(mut canvas)) = (&self.led_matrix, self.led_canvas.take()) {
+            self.frame.copy_to(&mut canvas, self.effective_brightness());
+            self.led_canvas = Some(led_matrix.swap(canvas));
+        }
+    }
+
     pub fn test_write(&mut self) {
-        // let mut canvas = self.led_matrix.offscreen_canvas();
+        self.frame.clear();
 
         // Your vertical line
         for y in 10..=17 {
-            self.led_canvas.set(10, y, &STANDARD_COLOR);
+            self.frame.set(10, y, &self.theme.text);
         }
 
         // Get font metrics if available
-        let (height, width) = self.led_canvas.canvas_size();
+        let (height, width) = (self.frame.height(), self.frame.width());
         debug!("Canvas dimensions: {}x{}", width, height);
 
         // Try different Y positions - BDF fonts often have baseline issues
@@ -154,64 +448,36 @@ impl DisplayBoard {
             (0, 8, "Y=8"),
             (0, 16, "Y=16"),
             (0, 24, "Y=24"),
-            (0, height as i32 - 1, "Bottom"),
+            (0, height - 1, "Bottom"),
         ];
 
         for (x, y, label) in test_positions {
             debug!("Drawing '{}' at ({}, {})", label, x, y);
-            self.led_canvas.draw_text(&self.font, label, x, y, &STANDARD_COLOR, 0, false);
+            self.frame.draw_text(&self.font, label, x, y, &self.theme.text);
         }
-        self.led_canvas.draw_text(&self.font, "Hello", 2, 2, &STANDARD_COLOR, 0, false);
+        self.frame.draw_text(&self.font, "Hello", 2, 2, &self.theme.text);
 
-        // canvas = self.led_matrix.swap(canvas);
+        self.present();
     }
 
     pub fn test_color(&mut self, red: u8, green: u8, blue: u8) {
-        // let mut canvas = self.led_matrix.offscreen_canvas();
-        let text = format!("r {red}, g: {green}, b: {blue}");
-        let color = LedColor {
-            red: red,
-            green: green,
-            blue: blue,
-        };
-        self.led_canvas.draw_text(&self.font, &format!("red {red}"), 2, 12, &color, 0, false);
-        self.led_canvas.draw_text(
-            &self.font,
-            &format!("green: {green}"),
-            2,
-            18,
-            &color,
-            0,
-            false,
-        );
-        self.led_canvas.draw_text(
-            &self.font,
-            &format!("blue: {blue}"),
-            2,
-            24,
-            &color,
-            0,
-            false,
-        );
+        self.frame.clear();
+        let color = LedColor { red, green, blue };
+        self.frame.draw_text(&self.font, &format!("red {red}"), 2, 12, &color);
+        self.frame.draw_text(&self.font, &format!("green: {green}"), 2, 18, &color);
+        self.frame.draw_text(&self.font, &format!("blue: {blue}"), 2, 24, &color);
         for y in 10..22 {
-            self.led_canvas.draw_line(80, y, 96, y, &color);
+            self.frame.draw_line(80, y, 96, y, &color);
         }
-        // canvas = self.led_matrix.swap(canvas);
+        self.present();
     }
 
     pub fn test_text_colors(&mut self) {
-        // let mut canvas = self.led_matrix.offscreen_canvas();
-        
         // Clear the canvas first
-        self.led_canvas.clear();
+        self.frame.clear();
 
-        //hardcode font for testing
-        let font = LedFont::new(
-            Path::new("./4x6.bdf")
-        ).unwrap();
-        
         // Test different colors with simple text
-        let colors = vec![
+        let colors = [
             ("RED", LedColor { red: 255, green: 0, blue: 0 }),
             ("GREEN", LedColor { red: 0, green: 255, blue: 0 }),
             ("BLUE", LedColor { red: 0, green: 0, blue: 255 }),
@@ -220,20 +486,20 @@ impl DisplayBoard {
             ("MAGENTA", LedColor { red: 255, green: 0, blue: 255 }),
             ("WHITE", LedColor { red: 255, green: 255, blue: 255 }),
         ];
-        
+
         for (i, (text, color)) in colors.iter().enumerate() {
             let y_pos = 8 + (i as i32 * 2);
             debug!("Drawing '{}' in color {:?} at y={}", text, color, y_pos);
-            self.led_canvas.draw_text(&font, text, 2, y_pos, color, 0, false);
+            self.frame.draw_text(&self.font, text, 2, y_pos, color);
         }
-        
+
         // Also draw some colored lines for comparison
-        for i in 0..7 {
+        for (i, (_, color)) in colors.iter().enumerate() {
             let y_pos = 8 + (i as i32 * 2) + 4;
-            self.led_canvas.draw_line(80, y_pos, 120, y_pos, &colors[i].1);
+            self.frame.draw_line(80, y_pos, 120, y_pos, color);
         }
-        
-        // canvas = self.led_matrix.swap(canvas);
+
+        self.present();
     }
 
     pub fn test_colors(&mut self) {
@@ -247,123 +513,487 @@ impl DisplayBoard {
         }
     }
 
-    fn get_starting_position(&self, line_ref: &String) -> (i32, i32) {
+    fn get_starting_position(&self, cell: (i32, i32)) -> (i32, i32) {
         // Row Position is (font_height + 1) * (n_row + 1)
         //col Position is 2 for left and COL_WIDTH + 2 for right for some buffer
 
-        let (col, row) = self.display_position_map.get(line_ref).unwrap_or(&(1, 2));
-        let x = (COL_WIDTH * *col) + 2;
-        let y = (FONT_HEIGHT + 1) * (*row + 2);
-        debug!(
-            "line_ref='{}', col={}, row={}, calculated=({},{})",
-            line_ref, col, row, x, y
+        let (col, row) = cell;
+        let x = (COL_WIDTH * col) + 2;
+        let y = (FONT_HEIGHT + 1) * (row + 2);
+        trace!("col={}, row={}, calculated=({},{})", col, row, x, y);
+        (x, y)
+    }
+
+    // Number of (col, row) cells that fit below the header
+    fn grid_size(&self) -> (i32, i32) {
+        let (cols, rows) = panel_grid_size(self.frame.width(), self.frame.height());
+        // the ticker or message takes over the bottom row
+        if self.alert_ticker.is_some() {
+            return (cols, (rows - 1).max(1));
+        }
+        (cols, rows)
+    }
+
+    // Cell configured for a row, "22:IB" lets each direction of a line have
+    // its own cell while "22" covers both
+    fn configured_cell(&self, display_row: &DisplayRow) -> Option<(i32, i32)> {
+        let (cols, rows) = self.grid_size();
+        let positions = self.schedule_positions.as_ref().unwrap_or(&self.display_position_map);
+        display_row
+            .position_keys()
+            .iter()
+            .find_map(|key| positions.get(key))
+            .copied()
+            .filter(|(col, row)| *col >= 0 && *col < cols && *row >= 0 && *row < rows)
+    }
+
+    // Split rows into pages of (row, cell). Rows keep their configured cell when
+    // it's free, everything else fills the remaining cells top to bottom, left
+    // to right, spilling onto extra pages when the board is full.
+    pub fn layout_pages(&self, display_rows: &[DisplayRow]) -> Vec<Vec<(DisplayRow, (i32, i32))>> {
+        let (cols, rows) = self.grid_size();
+        let mut pages = Vec::new();
+        let mut remaining: Vec<DisplayRow> = display_rows.to_vec();
+
+        while !remaining.is_empty() {
+            let mut page: Vec<(DisplayRow, (i32, i32))> = Vec::new();
+            let mut unplaced = Vec::new();
+            for display_row in remaining {
+                match self.configured_cell(&display_row) {
+                    Some(cell) if !page.iter().any(|(_, c)| *c == cell) => {
+                        page.push((display_row, cell))
+                    }
+                    _ => unplaced.push(display_row),
+                }
+            }
+            let mut free_cells = (0..cols)
+                .flat_map(|col| (0..rows).map(move |row| (col, row)))
+                .filter(|cell| !page.iter().any(|(_, c)| c == cell))
+                .collect::<Vec<_>>()
+                .into_iter();
+            remaining = Vec::new();
+            for display_row in unplaced {
+                match free_cells.next() {
+                    Some(cell) => page.push((display_row, cell)),
+                    None => remaining.push(display_row),
+                }
+            }
+            pages.push(page);
+        }
+        pages
+    }
+
+    // Page to show right now, pages advance every page_dwell
+    fn current_page(&self, n_pages: usize, now: Instant) -> usize {
+        if let Some(page) = self.page_override {
+            return page.min(n_pages.saturating_sub(1));
+        }
+        if n_pages <= 1 || self.page_dwell.is_zero() {
+            return 0;
+        }
+        let elapsed = now.saturating_duration_since(self.started);
+        (elapsed.as_millis() / self.page_dwell.as_millis()) as usize % n_pages
+    }
+
+    // Lines with an active alert and the ticker text for them. Alerts that
+    // don't name a line were kept because they name one of our stops.
+    fn active_alerts(&self, display_rows: &[DisplayRow]) -> (HashSet<String>, Vec<String>) {
+        let now = Utc::now();
+        let mut alerted_lines = HashSet::new();
+        let mut headers = Vec::new();
+        for alert in self.service_alerts.iter().filter(|a| a.is_active(now)) {
+            let affected: Vec<&DisplayRow> =
+                display_rows.iter().filter(|r| alert.affects_line(&r.line)).collect();
+            if alert.lines.is_empty() || !affected.is_empty() {
+                alerted_lines.extend(affected.iter().map(|r| r.line.clone()));
+                if !headers.contains(&alert.header_text) {
+                    headers.push(alert.header_text.clone());
+                }
+            }
+        }
+        (alerted_lines, headers)
+    }
+
+    fn update_alert_ticker(&mut self, headers: &[String], now: Instant) {
+        self.shown_message = self
+            .messages
+            .current(Local::now(), now)
+            .map(|shown| shown.message.clone());
+        let text = match &self.shown_message {
+            Some(message) => message.text.clone(),
+            None if self.show_alert_ticker && !headers.is_empty() => headers.join(" | "),
+            None => {
+                self.alert_ticker = None;
+                return;
+            }
+        };
+        let bounds = Rect::new(
+            0,
+            self.frame.height() - FONT_HEIGHT,
+            self.frame.width(),
+            FONT_HEIGHT,
         );
-        return (x, y);
+        match self.alert_ticker.as_mut() {
+            Some(ticker) => ticker.set_text(&text),
+            None => self.alert_ticker = Some(Marquee::new(&text, bounds, self.marquee_speed)),
+        }
     }
 
-    pub fn write_times(&mut self) {
-        // let mut canvas = self.led_matrix.offscreen_canvas();
-        self.led_canvas.clear();
-        let mut curr_row = FONT_HEIGHT;
-        let mut curr_time = String::from("Now ");
-        let now = Local::now();
-        if now.month() == 2 && now.day() == 2 {
-            curr_time.push_str("YOUR BIRTHDAY!");
+    // Right half of the header when the board is two panels wide, otherwise the
+    // whole header row (the clock gives way to the error)
+    fn status_bounds(&self) -> Rect {
+        if self.frame.width() >= 2 * COL_WIDTH {
+            Rect::new(COL_WIDTH + 2, 0, self.frame.width() - COL_WIDTH - 2, FONT_HEIGHT)
+        } else {
+            Rect::new(0, 0, self.frame.width(), FONT_HEIGHT)
+        }
+    }
+
+    fn update_status_marquee(&mut self) {
+        let text = match &self.status_error {
+            Some(text) => text.clone(),
+            None => {
+                self.status_marquee = None;
+                return;
+            }
+        };
+        let bounds = self.status_bounds();
+        match self.status_marquee.as_mut() {
+            Some(marquee) => marquee.set_text(&text),
+            None => self.status_marquee = Some(Marquee::new(&text, bounds, self.marquee_speed)),
+        }
+    }
+
+    // Left half of the header when the board is two panels wide, where the
+    // clock goes
+    fn clock_bounds(&self) -> Rect {
+        Rect::new(2, 0, self.frame.width().min(COL_WIDTH) - 2, FONT_HEIGHT)
+    }
+
+    fn update_agenda_marquee(&mut self) {
+        let text = match &self.header_agenda {
+            Some(message) => message.text.clone(),
+            None => {
+                self.agenda_marquee = None;
+                return;
+            }
+        };
+        let bounds = self.clock_bounds();
+        match self.agenda_marquee.as_mut() {
+            Some(marquee) => marquee.set_text(&text),
+            None => self.agenda_marquee = Some(Marquee::new(&text, bounds, self.marquee_speed)),
+        }
+    }
+
+    // Small "!" in the gap after the line ref
+    fn draw_alert_indicator(&mut self, x: i32, curr_row: i32) {
+        let top = curr_row - FONT_HEIGHT + 1;
+        for y in [top, top + 1, top + 2, top + 4] {
+            self.frame.set(x, y, &self.theme.alert);
+        }
+    }
+
+    // Bar in the gap after a time to arrival, 1 pixel tall with seats free up
+    // to 3 when full
+    fn draw_occupancy(&mut self, x: i32, curr_row: i32, occupancy: Occupancy) {
+        let color = match occupancy {
+            Occupancy::Unknown => return,
+            Occupancy::SeatsAvailable => self.theme.occupancy_seats,
+            Occupancy::StandingAvailable => self.theme.occupancy_standing,
+            Occupancy::Full => self.theme.occupancy_full,
+        };
+        // bottom of the digits, above the descender row
+        let bottom = curr_row - 1;
+        for y in (bottom - occupancy.level() + 1)..=bottom {
+            self.frame.set(x, y, &color);
+        }
+    }
+
+    // One dot per page in the top right corner
+    fn draw_page_indicator(&mut self, n_pages: usize, page: usize) {
+        if n_pages <= 1 {
+            return;
+        }
+        for i in 0..n_pages {
+            let x = self.frame.width() - 1 - 2 * (n_pages - 1 - i) as i32;
+            let color = if i == page {
+                self.theme.page_indicator
+            } else {
+                self.theme.page_indicator_dim
+            };
+            self.frame.set(x, 0, &color);
+        }
+    }
+
+    // Short form of a destination that fits in the destination column
+    fn abbreviate_destination(&self, destination: &str) -> String {
+        let n_chars = self.destination_column.chars();
+        if let Some(abbreviation) = self.destination_column.abbreviations().get(destination) {
+            return abbreviation.chars().take(n_chars).collect();
+        }
+        let words: Vec<&str> = destination
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+        // Initials read better than a truncated first word for multi word names
+        // e.g. "Ocean Beach" -> "OB" rather than "Oce"
+        if words.len() > 1 {
+            words
+                .iter()
+                .filter_map(|w| w.chars().next())
+                .take(n_chars)
+                .collect::<String>()
+                .to_uppercase()
         } else {
-            curr_time.push_str(&now.format("%H:%M:%S").to_string());
+            destination.chars().take(n_chars).collect()
+        }
+    }
+
+    fn destination_string(&self, display_row: &DisplayRow, mvj: &MonitoredVehicleJourney) -> String {
+        match self.destination_column.mode() {
+            DestinationMode::None => String::new(),
+            DestinationMode::Arrow => self
+                .destination_column
+                .direction_arrows()
+                .get(&display_row.direction)
+                .cloned()
+                .unwrap_or_default(),
+            DestinationMode::Text => self.abbreviate_destination(mvj.destination_name()),
+            DestinationMode::Scroll => mvj.destination_name().to_string(),
+        }
+    }
+
+    pub fn write_times(&mut self) {
+        self.frame.clear();
+        if self.is_blank() {
+            return;
         }
-        debug!(
+        let frame_time = Instant::now();
+        let curr_row = FONT_HEIGHT;
+        let mut curr_time = String::from("Now ");
+        curr_time.push_str(&Local::now().format("%H:%M:%S").to_string());
+        trace!(
             "writing current time: {:?}, at position: {:?}",
             curr_time,
             (2, curr_row)
         );
-        let color = LedColor {
-            red: 255,
-            green: 255,
-            blue: 255,
-        };
-        self.led_canvas.draw_text(
-            &self.font, &curr_time, 2, // little bit of buffer
-            curr_row, &color, //&TOP_LINE_COLOR,
-            0, false,
-        );
+        self.update_status_marquee();
+        if let Some(status) = &self.status_marquee {
+            status.draw(&mut self.frame, &self.font, &self.theme.status_error, frame_time);
+        }
+        self.update_agenda_marquee();
+        // on a single panel the status takes the clock's place
+        if self.status_marquee.is_none() || self.status_bounds().x > 0 {
+            match (&self.agenda_marquee, &self.header_agenda) {
+                (Some(agenda), Some(message)) => {
+                    let color = message.color.unwrap_or(self.theme.clock);
+                    agenda.draw(&mut self.frame, &self.font, &color, frame_time);
+                }
+                _ => {
+                    self.frame.draw_text(
+                        &self.font, &curr_time, 2, // little bit of buffer
+                        curr_row, &self.theme.clock,
+                    );
+                }
+            }
+        }
 
-        if let Some(request_time) = self.last_successful_request_time {
+        if let (Some(request_time), None) = (self.last_successful_request_time, &self.status_marquee) {
             let mut last_updated = String::from("As of ");
             last_updated.push_str(&request_time.format("%H:%M:%S").to_string());
-            debug!(
+            trace!(
                 "writing last updated: {:?}, at position: {:?}",
                 last_updated,
                 (COL_WIDTH + 2, curr_row)
             );
-            self.led_canvas.draw_text(
+            self.frame.draw_text(
                 &self.font,
                 &last_updated,
                 COL_WIDTH + 2,
                 curr_row,
-                &TOP_LINE_COLOR,
-                0,
-                false,
+                &self.theme.updated,
             );
         }
 
         let lines_to_write = self.get_bus_styled_lines();
+        let display_rows: Vec<DisplayRow> = lines_to_write.iter().map(|l| l.row.clone()).collect();
+        let (alerted_lines, alert_headers) = self.active_alerts(&display_rows);
+        self.update_alert_ticker(&alert_headers, frame_time);
+        let pages = self.layout_pages(&display_rows);
+        let page = self.current_page(pages.len(), frame_time);
+        self.shown_page = (page, pages.len());
+        self.draw_page_indicator(pages.len(), page);
+        let page_cells: HashMap<DisplayRow, (i32, i32)> =
+            pages.into_iter().nth(page).unwrap_or_default().into_iter().collect();
 
-        for (index, line) in lines_to_write.iter().enumerate() {
-            let (mut col_pos, mut curr_row) = self.get_starting_position(&line.line[0].string);
-            debug!("starting position: {:?}, {:?}", col_pos, curr_row);
+        for line in lines_to_write.iter() {
+            let cell = match page_cells.get(&line.row) {
+                Some(cell) => *cell,
+                // on another page
+                None => continue,
+            };
+            let (mut col_pos, curr_row) = self.get_starting_position(cell);
+            trace!("starting position: {:?}, {:?}", col_pos, curr_row);
             for line_str in &line.line {
                 if line_str.is_line_ref {
-                    debug!(
+                    trace!(
                         "writing line: {:?} at position: {:?}",
                         line_str.string,
                         (col_pos, curr_row)
                     );
-                    self.led_canvas.draw_text(
+                    self.frame.draw_text(
                         &self.font,
                         &line_str.string,
                         col_pos,
                         curr_row,
                         &line_str.color,
-                        0,
-                        false,
                     );
+                    if alerted_lines.contains(&line.row.line) {
+                        self.draw_alert_indicator(col_pos + (LINE_REF_N_CHARS as i32) * 4 + 1, curr_row);
+                    }
                     // two spaces after the four spaces for the line ref
                     col_pos += (LINE_REF_N_CHARS as i32) * 4 + LINE_REF_BUFFER_COLS;
+                } else if line_str.is_destination {
+                    trace!(
+                        "writing destination: {:?} at position: {:?}",
+                        line_str.string,
+                        (col_pos, curr_row)
+                    );
+                    let column_width = (self.destination_column.chars() as i32) * 4;
+                    if self.destination_column.mode() == DestinationMode::Scroll {
+                        // full destination scrolls through the column
+                        let bounds =
+                            Rect::new(col_pos, curr_row - FONT_HEIGHT + 1, column_width, FONT_HEIGHT);
+                        let marquee = self
+                            .destination_marquees
+                            .entry(line.row.clone())
+                            .or_insert_with(|| Marquee::new(&line_str.string, bounds, self.marquee_speed));
+                        marquee.set_text(&line_str.string);
+                        marquee.set_bounds(bounds);
+                        marquee.draw(&mut self.frame, &self.font, &line_str.color, frame_time);
+                    } else {
+                        self.frame.draw_text(
+                            &self.font,
+                            &line_str.string,
+                            col_pos,
+                            curr_row,
+                            &line_str.color,
+                        );
+                    }
+                    col_pos += column_width + DESTINATION_BUFFER_COLS;
                 } else {
                     // buffer two spaces for chars (e.g. 10 = 10 or 4 = ' 4')
                     let to_write: String = line_str.string.chars().take(TTA_N_CHARS).collect();
                     let to_write = format!("{:>width$}", to_write, width = TTA_N_CHARS);
-                    debug!(
+                    trace!(
                         "to write: {:?} at position: {:?}",
                         to_write,
                         (col_pos, curr_row)
                     );
-                    self.led_canvas.draw_text(
+                    self.frame.draw_text(
                         &self.font,
                         &to_write,
                         col_pos,
                         curr_row,
                         &line_str.color,
-                        0,
-                        false,
                     );
                     col_pos += 4 * (TTA_N_CHARS as i32); //4 * 2
 
                     // write the dot if we don't have a loc
                     if !line_str.has_loc {
-                        self.led_canvas.set(
+                        self.frame.set(
                             col_pos - 1,                // deal with kearning
                             curr_row - FONT_HEIGHT + 1, //top pixel row for curr row
-                            &NO_LOC_COLOR,
+                            &self.theme.no_location,
                         );
                     }
+                    self.draw_occupancy(col_pos, curr_row, line_str.occupancy);
                     col_pos += 2;
                     col_pos += TTA_BUFFER_COLS;
                 }
             }
         }
-        // canvas = self.led_matrix.swap(canvas);
+        if let Some(ticker) = &self.alert_ticker {
+            match &self.shown_message {
+                Some(message) if !message.scroll => {
+                    // centered and cut off at the edges rather than scrolled
+                    let bounds = ticker.bounds();
+                    let x = bounds.x + ((bounds.width - self.font.text_width(&message.text)) / 2).max(0);
+                    let y = bounds.y + self.font.height() - 1;
+                    let color = message.color.unwrap_or(self.theme.text);
+                    self.frame.draw_text_clipped(&self.font, &message.text, x, y, &color, &bounds);
+                }
+                Some(message) => {
+                    let color = message.color.unwrap_or(self.theme.text);
+                    ticker.draw(&mut self.frame, &self.font, &color, frame_time);
+                }
+                None => ticker.draw(&mut self.frame, &self.font, &self.theme.alert, frame_time),
+            }
+        }
+        self.draw_weather();
+        // forget marquees for rows that are no longer on the board
+        self.destination_marquees
+            .retain(|row, _| lines_to_write.iter().any(|line| &line.row == row));
+    }
+
+    // Icon, temperature and chance of rain, left out once the forecast is too
+    // old to go by
+    fn draw_weather(&mut self) {
+        let (Some(config), Some(report)) = (&self.weather, &self.weather_report) else {
+            return;
+        };
+        if report.is_stale(Utc::now(), config.stale_mins()) {
+            return;
+        }
+        let region = config.region();
+        self.frame.clear_rect(&region);
+        let forecast = &report.forecast;
+        for (x, y, pixel) in weather::icon_pixels(forecast.conditions, forecast.night) {
+            let (x, y) = (region.x + x, region.y + y);
+            if region.contains(x, y) {
+                let color = match pixel {
+                    IconPixel::Sun => &self.theme.weather_sun,
+                    IconPixel::Cloud => &self.theme.weather_cloud,
+                    IconPixel::Rain => &self.theme.weather_rain,
+                };
+                self.frame.set(x, y, color);
+            }
+        }
+        // text sits on the bottom row of the icon
+        let y = region.y + weather::ICON_SIZE - 1;
+        let mut x = region.x + weather::ICON_SIZE + 2;
+        let temperature = config.unit().format(forecast.temperature);
+        x += self.frame.draw_text_clipped(&self.font, &temperature, x, y, &self.theme.weather, &region);
+        if let Some(chance) = forecast.rain_chance {
+            let chance = format!("{}%", chance);
+            self.frame
+                .draw_text_clipped(&self.font, &chance, x + 2, y, &self.theme.weather_rain, &region);
+        }
+    }
+
+    // Red when there's no longer time to walk to the stop, green when it's time
+    // to leave, the usual color when there's time to spare
+    fn tta_color(&self, mvj: &MonitoredVehicleJourney, tta: i64) -> LedColor {
+        let walk = match self.walk_minutes.get(mvj.stop_point_ref()) {
+            Some(walk) if self.urgency.enabled() => *walk,
+            _ => return self.theme.tta,
+        };
+        if tta < walk {
+            self.theme.tta_missed
+        } else if tta < walk + self.urgency.leave_now_mins() {
+            self.theme.tta_leave_now
+        } else {
+            self.theme.tta
+        }
+    }
+
+    // Minutes shown for an arrival, with leave_in the minutes left before
+    // having to set off for the stop
+    fn tta_string(&self, mvj: &MonitoredVehicleJourney, tta: i64) -> String {
+        match self.walk_minutes.get(mvj.stop_point_ref()) {
+            Some(walk) if self.leave_in => (tta - walk).max(0).to_string(),
+            _ => tta.to_string(),
+        }
     }
 
     // pub fn lines_to_write(self) -> Vec<String> {
@@ -378,14 +1008,18 @@ impl DisplayBoard {
 
         let mut sorted_keys = Vec::new();
         for key in display_lines.keys() {
+            // the schedule can limit the board to some lines
+            if let Some(lines) = &self.line_filter {
+                if !key.position_keys().iter().any(|k| lines.contains(k)) {
+                    continue;
+                }
+            }
             sorted_keys.push(key.clone());
         }
         sorted_keys.sort();
 
-        let longest_key = 4;
-
         for key in sorted_keys {
-            let mut this_line = RGBDisplayLine::new();
+            let mut this_line = RGBDisplayLine::new(key.clone());
             let first_mvj = &display_lines[&key][0];
             let line_ref = first_mvj.line_ref.clone();
             let line_ref: String = line_ref.chars().take(LINE_REF_N_CHARS).collect();
@@ -393,37 +1027,51 @@ impl DisplayBoard {
 
             this_line.line.push(LineString {
                 string: line_ref_padded,
-                color: LINE_NAME_COLOR,
+                color: self.theme.line_name_color(&key.line),
                 has_loc: false,
                 is_line_ref: true,
+                is_destination: false,
+                occupancy: Occupancy::Unknown,
             });
 
+            if self.destination_column.mode() != DestinationMode::None {
+                this_line.line.push(LineString {
+                    string: self.destination_string(&key, first_mvj),
+                    color: self.theme.destination,
+                    has_loc: false,
+                    is_line_ref: false,
+                    is_destination: true,
+                    occupancy: Occupancy::Unknown,
+                });
+            }
+
             for mvj in &display_lines[&key] {
-                match mvj.time_to_arrival() {
-                    Some(tta) => {
-                        // rapid lines the line ref isn't the same
-                        // as the key
-                        if mvj.has_location() {
-                            this_line.line.push(LineString {
-                                string: tta.to_string(),
-                                color: TTA_COLOR,
-                                has_loc: true,
-                                is_line_ref: false,
-                            })
-                        } else {
-                            this_line.line.push(LineString {
-                                string: tta.to_string(),
-                                color: TTA_COLOR,
-                                has_loc: false,
-                                is_line_ref: false,
-                            })
-                        }
+                if let Some(tta) = mvj.time_to_arrival() {
+                    // rapid lines the line ref isn't the same
+                    // as the key
+                    if mvj.has_location() {
+                        this_line.line.push(LineString {
+                            string: self.tta_string(mvj, tta),
+                            color: self.tta_color(mvj, tta),
+                            has_loc: true,
+                            is_line_ref: false,
+                            is_destination: false,
+                            occupancy: mvj.occupancy(),
+                        })
+                    } else {
+                        this_line.line.push(LineString {
+                            string: self.tta_string(mvj, tta),
+                            color: self.tta_color(mvj, tta),
+                            has_loc: false,
+                            is_line_ref: false,
+                            is_destination: false,
+                            occupancy: mvj.occupancy(),
+                        })
                     }
-                    None => (),
                 }
             }
             lines.push(this_line);
         }
-        return lines;
+        lines
     }
 }
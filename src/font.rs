@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Drawn in place of characters the font doesn't have
+const REPLACEMENT_CHAR: char = '\u{FFFD}';
+
+// A single bitmap glyph from a BDF font
+pub struct Glyph {
+    // pixels to advance after drawing the glyph
+    device_width: i32,
+    width: i32,
+    height: i32,
+    x_offset: i32,
+    y_offset: i32,
+    // one entry per row, bit (width - 1 - x) set when pixel x is lit
+    rows: Vec<u32>,
+}
+
+impl Glyph {
+    pub fn is_set(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width {
+            return false;
+        }
+        match self.rows.get(y as usize) {
+            Some(row) => (row >> (self.width - 1 - x)) & 1 == 1,
+            None => false,
+        }
+    }
+}
+
+// Minimal BDF font reader so text can be drawn into a frame buffer (and
+// clipped) rather than straight onto the matrix
+pub struct BdfFont {
+    height: i32,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl BdfFont {
+    pub fn new(font_file: &Path) -> Result<Self, &'static str> {
+        let contents = fs::read_to_string(font_file).map_err(|_| "unable to read font file")?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, &'static str> {
+        let mut height = 0;
+        let mut glyphs = HashMap::new();
+
+        let mut encoding: Option<u32> = None;
+        let mut device_width = 0;
+        let mut bbx = (0, 0, 0, 0);
+        let mut rows: Option<Vec<u32>> = None;
+
+        for line in contents.lines() {
+            let mut parts = line.split_whitespace();
+            let keyword = match parts.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let values: Vec<i32> = parts.filter_map(|v| v.parse().ok()).collect();
+            match keyword {
+                "FONTBOUNDINGBOX" if values.len() >= 2 => height = values[1],
+                "STARTCHAR" => {
+                    encoding = None;
+                    device_width = 0;
+                    bbx = (0, 0, 0, 0);
+                }
+                "ENCODING" => encoding = values.first().map(|e| *e as u32),
+                "DWIDTH" => device_width = *values.first().unwrap_or(&0),
+                "BBX" if values.len() == 4 => bbx = (values[0], values[1], values[2], values[3]),
+                "BITMAP" => rows = Some(Vec::new()),
+                "ENDCHAR" => {
+                    let bitmap = rows.take().ok_or("ENDCHAR without BITMAP in font file")?;
+                    if let Some(c) = encoding.and_then(char::from_u32) {
+                        glyphs.insert(
+                            c,
+                            Glyph {
+                                device_width,
+                                width: bbx.0,
+                                height: bbx.1,
+                                x_offset: bbx.2,
+                                y_offset: bbx.3,
+                                rows: bitmap,
+                            },
+                        );
+                    }
+                }
+                hex => {
+                    if let Some(bitmap) = rows.as_mut() {
+                        let bits = u32::from_str_radix(hex, 16)
+                            .map_err(|_| "invalid bitmap row in font file")?;
+                        // rows are padded out to whole bytes, drop the padding
+                        let padding = hex.len() as i32 * 4 - bbx.0;
+                        bitmap.push(bits >> padding.max(0));
+                    }
+                }
+            }
+        }
+
+        if glyphs.is_empty() {
+            return Err("no glyphs found in font file");
+        }
+        Ok(BdfFont { height, glyphs })
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&REPLACEMENT_CHAR))
+    }
+
+    // Width in pixels of text drawn with this font
+    pub fn text_width(&self, text: &str) -> i32 {
+        text.chars()
+            .filter_map(|c| self.glyph(c))
+            .map(|g| g.device_width)
+            .sum()
+    }
+
+    // Calls set_pixel for every lit pixel of text. Like the led matrix library,
+    // y is the bottom pixel row of the text. Returns the width drawn.
+    pub fn render<F: FnMut(i32, i32)>(&self, text: &str, x: i32, y: i32, mut set_pixel: F) -> i32 {
+        let mut x_pos = x;
+        for c in text.chars() {
+            let glyph = match self.glyph(c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let top = y - glyph.height - glyph.y_offset;
+            for gy in 0..glyph.height {
+                for gx in 0..glyph.width {
+                    if glyph.is_set(gx, gy) {
+                        set_pixel(x_pos + glyph.x_offset + gx, top + gy);
+                    }
+                }
+            }
+            x_pos += glyph.device_width;
+        }
+        x_pos - x
+    }
+}
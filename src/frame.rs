@@ -0,0 +1,195 @@
+use crate::font::BdfFont;
+use rpi_led_matrix::{LedCanvas, LedColor};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const BLACK: LedColor = LedColor {
+    red: 0,
+    green: 0,
+    blue: 0,
+};
+
+// An area of the board in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+// Software copy of the board. Everything is drawn here first and copied onto
+// the matrix in one go, which lets us clip text and avoid flicker.
+pub struct FrameBuffer {
+    width: i32,
+    height: i32,
+    pixels: Vec<LedColor>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: i32, height: i32) -> Self {
+        FrameBuffer {
+            width,
+            height,
+            pixels: vec![BLACK; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.fill(BLACK);
+    }
+
+    pub fn clear_rect(&mut self, rect: &Rect) {
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                self.set(x, y, &BLACK);
+            }
+        }
+    }
+
+    // Pixels off the board are ignored
+    pub fn set(&mut self, x: i32, y: i32, color: &LedColor) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        self.pixels[(y * self.width + x) as usize] = *color;
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<LedColor> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[(y * self.width + x) as usize])
+    }
+
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: &LedColor) {
+        // Bresenham
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.set(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    // y is the bottom pixel row of the text, same as LedCanvas::draw_text
+    pub fn draw_text(&mut self, font: &BdfFont, text: &str, x: i32, y: i32, color: &LedColor) -> i32 {
+        let bounds = self.bounds();
+        self.draw_text_clipped(font, text, x, y, color, &bounds)
+    }
+
+    // Draws text, dropping any pixel outside of clip
+    pub fn draw_text_clipped(
+        &mut self,
+        font: &BdfFont,
+        text: &str,
+        x: i32,
+        y: i32,
+        color: &LedColor,
+        clip: &Rect,
+    ) -> i32 {
+        font.render(text, x, y, |px, py| {
+            if clip.contains(px, py) {
+                self.set(px, py, color);
+            }
+        })
+    }
+
+    // Copy the frame onto a matrix canvas
+    // Brightness is a percentage, the frame itself is left as drawn
+    pub fn copy_to(&self, canvas: &mut LedCanvas, brightness: u8) {
+        let scale = |c: u8| (c as u32 * brightness.min(100) as u32 / 100) as u8;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel = &self.pixels[(y * self.width + x) as usize];
+                let color = LedColor {
+                    red: scale(pixel.red),
+                    green: scale(pixel.green),
+                    blue: scale(pixel.blue),
+                };
+                canvas.set(x, y, &color);
+            }
+        }
+    }
+
+    // Save the frame as a PNG, each LED drawn as a scale x scale block with a
+    // dark gap around it once there's room, so it looks like the panel
+    pub fn write_png(&self, path: &Path, scale: u32) -> io::Result<()> {
+        self.encode_png(BufWriter::new(File::create(path)?), scale)
+    }
+
+    // The same PNG in memory
+    pub fn png_bytes(&self, scale: u32) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.encode_png(&mut bytes, scale)?;
+        Ok(bytes)
+    }
+
+    fn encode_png<W: Write>(&self, out: W, scale: u32) -> io::Result<()> {
+        let scale = scale.max(1) as usize;
+        let gap = usize::from(scale >= 4);
+        let (width, height) = (self.width as usize * scale, self.height as usize * scale);
+        let mut data = vec![0u8; width * height * 3];
+        for y in 0..height {
+            for x in 0..width {
+                if x % scale < gap || y % scale < gap {
+                    continue;
+                }
+                let color = &self.pixels[(y / scale) * self.width as usize + x / scale];
+                let i = (y * width + x) * 3;
+                data[i] = color.red;
+                data[i + 1] = color.green;
+                data[i + 2] = color.blue;
+            }
+        }
+
+        let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&data).map_err(io::Error::other)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,399 @@
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, info};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::arrivals::arrivals;
+use crate::display_board::DisplayBoard;
+use crate::messages::{Message, message_from_fields};
+use crate::metrics::metrics;
+
+// Longest message accepted in a request body
+const MAX_BODY_BYTES: usize = 4096;
+// Longest a request waits for the render loop to answer
+const BOARD_REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_FRAME_SCALE: u32 = 16;
+
+// What a request, over HTTP or MQTT, asks of the board. Neither touches the
+// board itself, the render loop answers these between frames.
+#[derive(Debug)]
+pub enum ApiCommand {
+    Status,
+    Arrivals,
+    Frame { scale: u32 },
+    // None goes back to the schedule and auto brightness
+    SetBrightness(Option<u8>),
+    // None goes back to paging on its own, pages count from 0
+    SetPage(Option<usize>),
+    // None clears the message
+    SetMessage(Option<(Message, Option<Duration>)>),
+    SetTheme(String),
+    // switched off the board is blank and doesn't fetch
+    SetPower(bool),
+}
+
+pub struct ApiRequest {
+    pub command: ApiCommand,
+    pub reply: oneshot::Sender<ApiResponse>,
+}
+
+pub struct ApiResponse {
+    status: StatusCode,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl ApiResponse {
+    pub fn json(value: &Value) -> Self {
+        ApiResponse {
+            status: StatusCode::OK,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    pub fn text(content_type: &'static str, text: String) -> Self {
+        ApiResponse {
+            status: StatusCode::OK,
+            content_type,
+            body: text.into_bytes(),
+        }
+    }
+
+    pub fn png(bytes: Vec<u8>) -> Self {
+        ApiResponse {
+            status: StatusCode::OK,
+            content_type: "image/png",
+            body: bytes,
+        }
+    }
+
+    pub fn error(status: StatusCode, message: &str) -> Self {
+        ApiResponse {
+            status,
+            ..ApiResponse::json(&json!({ "error": message }))
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status.is_success()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    fn into_response(self) -> Response<Body> {
+        Response::builder()
+            .status(self.status)
+            .header("Content-Type", self.content_type)
+            .body(Body::from(self.body))
+            .unwrap_or_else(|_| Response::new(Body::empty()))
+    }
+}
+
+// About the running board, for /status
+pub struct RunInfo {
+    pub started: Instant,
+    pub config_path: PathBuf,
+    pub config_hash: String,
+}
+
+// Answer a command, called from the render loop
+pub fn respond(command: ApiCommand, board: &mut DisplayBoard, info: &RunInfo) -> ApiResponse {
+    match command {
+        ApiCommand::Status => ApiResponse::json(&status(board, info)),
+        ApiCommand::Arrivals => {
+            let arrivals = board.display_lines.as_ref().map(arrivals).unwrap_or_default();
+            ApiResponse::json(&json!(arrivals))
+        }
+        ApiCommand::Frame { scale } => match board.frame.png_bytes(scale) {
+            Ok(bytes) => ApiResponse::png(bytes),
+            Err(e) => ApiResponse::error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        },
+        ApiCommand::SetBrightness(brightness) => {
+            info!("Brightness override set to {:?}", brightness);
+            board.brightness_override = brightness;
+            ApiResponse::json(&brightness_status(board))
+        }
+        ApiCommand::SetPage(page) => {
+            board.page_override = page;
+            ApiResponse::json(&page_status(board))
+        }
+        ApiCommand::SetMessage(message) => {
+            match message {
+                Some((message, duration)) => {
+                    info!("Showing message `{}`", message.text);
+                    board.set_message(message, duration);
+                }
+                None => board.clear_message(),
+            }
+            ApiResponse::json(&message_status(board))
+        }
+        ApiCommand::SetPower(on) => {
+            info!("Board switched {}", if on { "on" } else { "off" });
+            board.set_on(on);
+            ApiResponse::json(&json!({ "on": board.is_on() }))
+        }
+        ApiCommand::SetTheme(name) => match board.set_theme(&name) {
+            Ok(()) => {
+                info!("Switched to theme {}", name);
+                ApiResponse::json(&json!({ "theme": board.theme_name() }))
+            }
+            Err(e) => ApiResponse::json(&json!({ "error": e, "themes": board.theme_names() }))
+                .with_status(StatusCode::NOT_FOUND),
+        },
+    }
+}
+
+fn status(board: &DisplayBoard, info: &RunInfo) -> Value {
+    json!({
+        "uptime_secs": info.started.elapsed().as_secs(),
+        "config_path": info.config_path,
+        "config_hash": info.config_hash,
+        "last_fetch": board.last_successful_request_time.map(|t| t.to_rfc3339()),
+        "last_fetch_ok": board.last_request_successful,
+        "last_fetch_error": board.last_fetch_error,
+        "status_error": board.status_error,
+        "rows": board.display_lines.as_ref().map_or(0, |lines| lines.len()),
+        "service_alerts": board.service_alerts.len(),
+        "theme": board.theme_name(),
+        "on": board.is_on(),
+        "blank": board.is_blank(),
+        "schedule_rules": board.schedule_rules(),
+        "brightness": brightness_status(board),
+        "page": page_status(board),
+        "message": message_status(board),
+    })
+}
+
+fn brightness_status(board: &DisplayBoard) -> Value {
+    json!({
+        "percent": board.effective_brightness(),
+        "schedule": board.brightness,
+        "ambient": board.ambient_brightness,
+        "override": board.brightness_override,
+    })
+}
+
+// Pages count from 1 here, the way they'd be talked about
+fn page_status(board: &DisplayBoard) -> Value {
+    let (page, pages) = board.shown_page();
+    json!({
+        "page": if pages == 0 { 0 } else { page + 1 },
+        "pages": pages,
+        "override": board.page_override.map(|page| page + 1),
+    })
+}
+
+fn message_status(board: &DisplayBoard) -> Value {
+    match board.message() {
+        Some(shown) => json!({
+            "text": shown.message.text,
+            "priority": shown.message.priority,
+            "source": shown.source.name(),
+            "secs_left": shown
+                .until
+                .map(|until| until.saturating_duration_since(Instant::now()).as_secs()),
+        }),
+        None => Value::Null,
+    }
+}
+
+// Serve the API until the server fails, requests are passed to the render loop
+// on api_tx and reloads asked for on reload_tx
+pub async fn serve(
+    addr: SocketAddr,
+    api_tx: mpsc::UnboundedSender<ApiRequest>,
+    reload_tx: mpsc::UnboundedSender<()>,
+) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let (api_tx, reload_tx) = (api_tx.clone(), reload_tx.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(request, api_tx.clone(), reload_tx.clone())
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!("HTTP API listening on http://{}", addr);
+    server.await
+}
+
+async fn handle(
+    request: Request<Body>,
+    api_tx: mpsc::UnboundedSender<ApiRequest>,
+    reload_tx: mpsc::UnboundedSender<()>,
+) -> Result<Response<Body>, Infallible> {
+    debug!("HTTP API {} {}", request.method(), request.uri());
+    let response = match route(request, &reload_tx).await {
+        Ok(Route::Board(command)) => ask_board(command, &api_tx).await,
+        Ok(Route::Answered(response)) | Err(response) => response,
+    };
+    Ok(response.into_response())
+}
+
+enum Route {
+    Board(ApiCommand),
+    // handled without the board
+    Answered(ApiResponse),
+}
+
+async fn route(request: Request<Body>, reload_tx: &mpsc::UnboundedSender<()>) -> Result<Route, ApiResponse> {
+    let query: HashMap<String, String> =
+        url::form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
+            .into_owned()
+            .collect();
+    let param = |name: &str| query.get(name).map(|value| value.trim().to_string());
+    let bad_request = |message: &str| ApiResponse::error(StatusCode::BAD_REQUEST, message);
+
+    let (method, path) = (request.method().clone(), request.uri().path().to_string());
+    let command = match (&method, path.as_str()) {
+        (&Method::GET, "/status") => ApiCommand::Status,
+        (&Method::GET, "/arrivals") => ApiCommand::Arrivals,
+        (&Method::GET, "/frame.png") => {
+            let scale = match param("scale") {
+                Some(scale) => scale
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|scale| (1..=MAX_FRAME_SCALE).contains(scale))
+                    .ok_or_else(|| bad_request("scale must be a number from 1 to 16"))?,
+                None => 1,
+            };
+            ApiCommand::Frame { scale }
+        }
+        (&Method::POST, "/brightness") => {
+            let percent = param("percent").ok_or_else(|| bad_request("percent is missing"))?;
+            ApiCommand::SetBrightness(parse_brightness(&percent).map_err(bad_request)?)
+        }
+        (&Method::POST, "/page") => {
+            let number = param("number").ok_or_else(|| bad_request("number is missing"))?;
+            ApiCommand::SetPage(parse_page(&number).map_err(bad_request)?)
+        }
+        (&Method::POST, "/power") => {
+            let on = param("on").ok_or_else(|| bad_request("on is missing"))?;
+            ApiCommand::SetPower(parse_power(&on).map_err(bad_request)?)
+        }
+        (&Method::POST, "/message") => {
+            let (secs, priority, color, scroll) =
+                (param("secs"), param("priority"), param("color"), param("scroll"));
+            // text in the query, or the whole body for longer messages
+            let text = match param("text") {
+                Some(text) => text,
+                None => read_body(request).await?,
+            };
+            let message = message_from_fields(
+                &text,
+                secs.as_deref(),
+                priority.as_deref(),
+                color.as_deref(),
+                scroll.as_deref(),
+            )
+            .map_err(bad_request)?;
+            ApiCommand::SetMessage(Some(message))
+        }
+        (&Method::DELETE, "/message") => ApiCommand::SetMessage(None),
+        (&Method::POST, "/theme") => {
+            ApiCommand::SetTheme(param("name").ok_or_else(|| bad_request("name is missing"))?)
+        }
+        // straight from the counters, so it answers even if the render loop is stuck
+        (&Method::GET, "/metrics") => {
+            return Ok(Route::Answered(ApiResponse::text(
+                "text/plain; version=0.0.4",
+                metrics().encode(),
+            )));
+        }
+        (&Method::POST, "/reload") => {
+            info!("Config reload asked for through the HTTP API");
+            let _ = reload_tx.send(());
+            return Ok(Route::Answered(
+                ApiResponse::json(&json!({ "reloading": true })).with_status(StatusCode::ACCEPTED),
+            ));
+        }
+        (_, "/status" | "/arrivals" | "/frame.png" | "/brightness" | "/page" | "/power" | "/message"
+        | "/theme" | "/reload" | "/metrics") => {
+            return Err(ApiResponse::error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"));
+        }
+        _ => return Err(ApiResponse::error(StatusCode::NOT_FOUND, "not found")),
+    };
+    Ok(Route::Board(command))
+}
+
+// Values taken by both the HTTP API and MQTT
+
+// Percent, or "auto" to go back to the schedule and auto brightness
+pub fn parse_brightness(value: &str) -> Result<Option<u8>, &'static str> {
+    if value.trim().eq_ignore_ascii_case("auto") {
+        return Ok(None);
+    }
+    match value.trim().parse::<u8>() {
+        Ok(percent) if percent <= 100 => Ok(Some(percent)),
+        _ => Err("brightness must be a percent from 0 to 100 or auto"),
+    }
+}
+
+// Page number from 1, or "auto" (or 0) to page on its own
+pub fn parse_page(value: &str) -> Result<Option<usize>, &'static str> {
+    if value.trim().eq_ignore_ascii_case("auto") {
+        return Ok(None);
+    }
+    match value.trim().parse::<usize>() {
+        Ok(0) => Ok(None),
+        Ok(number) => Ok(Some(number - 1)),
+        Err(_) => Err("page must be a page number from 1 or auto"),
+    }
+}
+
+pub fn parse_power(value: &str) -> Result<bool, &'static str> {
+    match value.trim().to_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err("expected on or off"),
+    }
+}
+
+async fn read_body(request: Request<Body>) -> Result<String, ApiResponse> {
+    let too_long = || ApiResponse::error(StatusCode::PAYLOAD_TOO_LARGE, "message is too long");
+    let mut body = request.into_body();
+    // a Content-Length over the limit is turned away before reading any of it,
+    // a body without one is cut off once it passes the limit
+    if body.size_hint().lower() > MAX_BODY_BYTES as u64 {
+        return Err(too_long());
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| ApiResponse::error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+        if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+            return Err(too_long());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let text = String::from_utf8(bytes)
+        .map_err(|_| ApiResponse::error(StatusCode::BAD_REQUEST, "message isn't UTF-8"))?;
+    Ok(text.trim().to_string())
+}
+
+// Pass a command to the render loop and wait for its answer
+pub async fn ask_board(command: ApiCommand, api_tx: &mpsc::UnboundedSender<ApiRequest>) -> ApiResponse {
+    let (reply, response) = oneshot::channel();
+    if api_tx.send(ApiRequest { command, reply }).is_err() {
+        return ApiResponse::error(StatusCode::SERVICE_UNAVAILABLE, "board isn't running");
+    }
+    match tokio::time::timeout(BOARD_REPLY_TIMEOUT, response).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(_)) => ApiResponse::error(StatusCode::SERVICE_UNAVAILABLE, "board isn't running"),
+        Err(_) => ApiResponse::error(StatusCode::SERVICE_UNAVAILABLE, "board isn't answering"),
+    }
+}
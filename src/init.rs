@@ -0,0 +1,423 @@
+use reqwest::Client;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::display_board::panel_grid_size;
+use crate::stop_monitor::{ApiError, DisplayRow, extract_monitored_vehicle_journeys, get_stop_monitor_responses};
+use crate::stop_search::{Stop, get_agency_stops, load_gtfs_stops, search_by_name};
+
+// Most search results offered at once
+const MAX_CHOICES: usize = 10;
+
+// Asks questions on a terminal. End of input counts as an empty answer so
+// piping answers in works and never loops forever.
+pub struct Prompter<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Prompter { input, output }
+    }
+
+    pub fn say(&mut self, text: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", text)
+    }
+
+    // Answer with surrounding whitespace trimmed, default when left blank
+    pub fn ask(&mut self, question: &str, default: &str) -> io::Result<String> {
+        if default.is_empty() {
+            write!(self.output, "{}: ", question)?;
+        } else {
+            write!(self.output, "{} [{}]: ", question, default)?;
+        }
+        self.output.flush()?;
+        let mut answer = String::new();
+        self.input.read_line(&mut answer)?;
+        let answer = answer.trim();
+        Ok(if answer.is_empty() { default } else { answer }.to_string())
+    }
+
+    pub fn ask_number<T: FromStr + ToString + PartialOrd + Copy>(
+        &mut self,
+        question: &str,
+        default: T,
+        min: T,
+        max: T,
+    ) -> io::Result<T> {
+        loop {
+            let answer = self.ask(question, &default.to_string())?;
+            match answer.parse::<T>() {
+                Ok(value) if value >= min && value <= max => return Ok(value),
+                _ => self.say(&format!(
+                    "  expected a number from {} to {}",
+                    min.to_string(),
+                    max.to_string()
+                ))?,
+            }
+        }
+    }
+
+    pub fn confirm(&mut self, question: &str, default: bool) -> io::Result<bool> {
+        loop {
+            let hint = if default { "Y/n" } else { "y/N" };
+            let answer = self.ask(question, hint)?;
+            match answer.to_lowercase().as_str() {
+                _ if answer == hint => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => self.say("  answer y or n")?,
+            }
+        }
+    }
+}
+
+// A stop picked for the board and the (line, direction) rows it feeds
+pub struct ChosenStop {
+    pub stop: Stop,
+    pub rows: BTreeSet<(String, Option<String>)>,
+}
+
+// Rapid and express variants that can share their parent's row, e.g. 14R -> 14
+// and 8AX -> 8. Only lines that start with a number and end in letters count,
+// so rail lines like N or KT are left alone.
+pub fn suggest_parent_lines(lines: &BTreeSet<String>) -> BTreeMap<String, String> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let base: String = line.chars().take_while(|c| c.is_ascii_digit()).collect();
+            let suffix = &line[base.len()..];
+            if base.is_empty() || suffix.is_empty() || !suffix.chars().all(|c| c.is_ascii_alphabetic()) {
+                return None;
+            }
+            Some((line.clone(), base))
+        })
+        .collect()
+}
+
+// Numbers in numeric order, then everything else
+fn line_sort_key(line: &str) -> (u32, String) {
+    let digits: String = line.chars().take_while(|c| c.is_ascii_digit()).collect();
+    (digits.parse().unwrap_or(u32::MAX), line.to_string())
+}
+
+// Position key and cell for each row, filled the same way the board places
+// rows without a position: down each column, then across. Rows that don't fit
+// are left out, the board pages through them.
+pub fn layout_rows(rows: &[DisplayRow], grid: (i32, i32)) -> Vec<(String, (i32, i32))> {
+    let mut rows = rows.to_vec();
+    rows.sort_by_key(|r| (line_sort_key(&r.line), r.direction.clone()));
+    let directions = |line: &str| rows.iter().filter(|r| r.line == line).count();
+    let cells = (0..grid.0).flat_map(|x| (0..grid.1).map(move |y| (x, y)));
+    rows.iter()
+        .zip(cells)
+        .map(|(row, cell)| {
+            // a line seen in one direction gets the plain key so the other
+            // direction lands next to it if it turns up later
+            let key = if row.direction.is_empty() || directions(&row.line) == 1 {
+                row.line.clone()
+            } else {
+                format!("{}:{}", row.line, row.direction)
+            };
+            (key, cell)
+        })
+        .collect()
+}
+
+pub struct PanelAnswers {
+    pub font_file: String,
+    pub rows: u32,
+    pub cols: u32,
+    pub chained: u32,
+}
+
+// The commented config file
+pub fn render_config(
+    agency: &str,
+    api_key_file: Option<&str>,
+    panel: &PanelAnswers,
+    stops: &[ChosenStop],
+    parent_lines: &BTreeMap<String, String>,
+    positions: &[(String, (i32, i32))],
+) -> String {
+    let mut yaml = String::new();
+    yaml.push_str("# transit_rust config, written by `transit_rust init`\n");
+    yaml.push_str("# Check changes with `transit_rust check-config <file>`\n\n");
+
+    yaml.push_str("# 511 operator id the stops belong to\n");
+    yaml.push_str(&format!("agency: {}\n", agency));
+    match api_key_file {
+        Some(path) => {
+            yaml.push_str("# File holding the 511 API key\n");
+            yaml.push_str(&format!("api_key_file: \"{}\"\n", path));
+        }
+        None => {
+            yaml.push_str("# The 511 API key comes from TRANSIT_TOKEN, or set a file holding it\n");
+            yaml.push_str("# api_key_file: /etc/transit_rust/api_key\n");
+        }
+    }
+
+    yaml.push_str("\n# Stop codes to show arrivals for, `transit_rust find-stops` looks them up\n");
+    yaml.push_str("stops_to_monitor:\n");
+    for chosen in stops {
+        yaml.push_str(&format!("  - \"{}\" # {}\n", chosen.stop.code, chosen.stop.name));
+    }
+
+    yaml.push_str("\n# Lines shown on another line's row, e.g. rapid lines with their local line\n");
+    if parent_lines.is_empty() {
+        yaml.push_str("rapid_line_to_parent_line_map: {}\n");
+    } else {
+        yaml.push_str("rapid_line_to_parent_line_map:\n");
+        for (line, parent) in parent_lines {
+            yaml.push_str(&format!("  \"{}\": \"{}\"\n", line, parent));
+        }
+    }
+
+    yaml.push_str("\ndisplay_board_config:\n");
+    yaml.push_str(&format!("  font_file: \"{}\"\n", panel.font_file));
+    yaml.push_str("  # height and width of one panel in LEDs, and how many are chained\n");
+    yaml.push_str(&format!("  rows: {}\n", panel.rows));
+    yaml.push_str(&format!("  cols: {}\n", panel.cols));
+    yaml.push_str(&format!("  chained: {}\n", panel.chained));
+    yaml.push_str("  # Cell each line is drawn in, x is the column and y the row. \"22:IB\" places\n");
+    yaml.push_str("  # one direction of a line, \"22\" both. Lines not listed fill the free cells.\n");
+    if positions.is_empty() {
+        yaml.push_str("  line_ref_to_display_position: {}\n");
+    } else {
+        yaml.push_str("  line_ref_to_display_position:\n");
+        for (key, (x, y)) in positions {
+            yaml.push_str(&format!("    \"{}\": {{ x: {}, y: {} }}\n", key, x, y));
+        }
+    }
+    yaml
+}
+
+// (line, direction) pairs arriving at a stop right now
+async fn live_rows(
+    client: &Client,
+    stop: &Stop,
+    agency: &str,
+    api_key: &str,
+) -> Result<BTreeSet<(String, Option<String>)>, ApiError> {
+    let responses =
+        get_stop_monitor_responses(client, &vec![stop.code.clone()], agency, api_key).await?;
+    let mut rows = BTreeSet::new();
+    for (_, stop_monitor_data) in responses {
+        for mvj in extract_monitored_vehicle_journeys(stop_monitor_data) {
+            let direction = Some(mvj.direction_ref().to_string()).filter(|d| !d.is_empty());
+            rows.insert((mvj.line_ref.clone(), direction));
+        }
+    }
+    Ok(rows)
+}
+
+pub struct InitOptions {
+    pub output: PathBuf,
+    pub gtfs: Option<PathBuf>,
+    pub force: bool,
+}
+
+pub async fn run_init<R: BufRead, W: Write>(
+    prompter: &mut Prompter<R, W>,
+    options: &InitOptions,
+) -> io::Result<()> {
+    if options.output.exists()
+        && !options.force
+        && !prompter.confirm(
+            &format!("{} already exists, overwrite it?", options.output.display()),
+            false,
+        )?
+    {
+        return Ok(());
+    }
+
+    let agency = prompter.ask("511 operator id", "SF")?;
+    let api_key_file = prompter.ask("File with your 511 API key (blank to use TRANSIT_TOKEN)", "")?;
+    let api_key = if api_key_file.is_empty() {
+        std::env::var("TRANSIT_TOKEN").ok()
+    } else {
+        match fs::read_to_string(&api_key_file) {
+            Ok(key) => Some(key.trim().to_string()),
+            Err(_) => {
+                let key = prompter.ask(&format!("API key to save in {}", api_key_file), "")?;
+                if !key.is_empty() {
+                    write_api_key(Path::new(&api_key_file), &key)?;
+                }
+                Some(key).filter(|k| !k.is_empty())
+            }
+        }
+    };
+
+    prompter.say("\nPanel")?;
+    let panel = PanelAnswers {
+        rows: prompter.ask_number("LED rows per panel", 32, 8, 64)?,
+        cols: prompter.ask_number("LED columns per panel", 64, 16, 256)?,
+        chained: prompter.ask_number("Panels chained together", 2, 1, 16)?,
+        font_file: prompter.ask("BDF font file", "./4x6.bdf")?,
+    };
+
+    let client = Client::new();
+    let all_stops = match (&options.gtfs, &api_key) {
+        (Some(path), _) => load_gtfs_stops(path)?,
+        (None, Some(api_key)) => {
+            prompter.say("Loading stops...")?;
+            get_agency_stops(&client, &agency, api_key)
+                .await
+                .map_err(io::Error::other)?
+        }
+        (None, None) => {
+            return Err(io::Error::other(
+                "an API key is needed to search stops, or pass --gtfs with a GTFS feed",
+            ));
+        }
+    };
+
+    prompter.say("\nStops, search by name and pick the ones to show")?;
+    let mut chosen: Vec<ChosenStop> = Vec::new();
+    loop {
+        let query = prompter.ask("Search for a stop (blank when done)", "")?;
+        if query.is_empty() {
+            if chosen.is_empty() {
+                prompter.say("  pick at least one stop")?;
+                // stdin closed, nothing more is coming
+                if prompter.input.fill_buf()?.is_empty() {
+                    return Err(io::Error::other("no stops picked"));
+                }
+                continue;
+            }
+            break;
+        }
+        let matches = search_by_name(&all_stops, &query);
+        if matches.is_empty() {
+            prompter.say("  no stops match")?;
+            continue;
+        }
+        for (i, stop) in matches.iter().take(MAX_CHOICES).enumerate() {
+            prompter.say(&format!("  {:>2}) {:<8} {}", i + 1, stop.code, stop.name))?;
+        }
+        if matches.len() > MAX_CHOICES {
+            prompter.say(&format!("  ... {} more, try more words", matches.len() - MAX_CHOICES))?;
+        }
+        let picks = prompter.ask("Numbers to add, e.g. 1,3 (blank for none)", "")?;
+        for pick in picks.split([',', ' ']).filter(|p| !p.is_empty()) {
+            let shown = &matches[..matches.len().min(MAX_CHOICES)];
+            let stop = match pick.parse::<usize>().ok().filter(|i| *i >= 1 && *i <= shown.len()) {
+                Some(i) => shown[i - 1].clone(),
+                None => {
+                    prompter.say(&format!("  skipping `{}`", pick))?;
+                    continue;
+                }
+            };
+            if chosen.iter().any(|c| c.stop.code == stop.code) {
+                continue;
+            }
+            let mut rows: BTreeSet<(String, Option<String>)> =
+                stop.lines.iter().map(|line| (line.clone(), None)).collect();
+            if let (None, Some(api_key)) = (&options.gtfs, &api_key) {
+                match live_rows(&client, &stop, &agency, api_key).await {
+                    Ok(live) => rows.extend(live),
+                    Err(e) => prompter.say(&format!("  unable to fetch lines: {}", e))?,
+                }
+            }
+            let lines: BTreeSet<&String> = rows.iter().map(|(line, _)| line).collect();
+            let lines: Vec<&str> = lines.into_iter().map(|l| l.as_str()).collect();
+            prompter.say(&format!(
+                "  {} {}: {}",
+                stop.code,
+                stop.name,
+                if lines.is_empty() { "no lines found".to_string() } else { lines.join(", ") }
+            ))?;
+            let extra = prompter.ask("  Other lines stopping here, e.g. 22,J (blank for none)", "")?;
+            for line in extra.split([',', ' ']).filter(|l| !l.is_empty()) {
+                rows.insert((line.to_string(), None));
+            }
+            chosen.push(ChosenStop { stop, rows });
+        }
+    }
+
+    let lines: BTreeSet<String> = chosen
+        .iter()
+        .flat_map(|c| c.rows.iter().map(|(line, _)| line.clone()))
+        .collect();
+    let mut parent_lines = BTreeMap::new();
+    for (line, parent) in suggest_parent_lines(&lines) {
+        if prompter.confirm(&format!("Show {} on the {} row?", line, parent), true)? {
+            parent_lines.insert(line, parent);
+        }
+    }
+
+    // one row per line and direction, as the board groups them
+    let mut display_rows: BTreeSet<DisplayRow> = BTreeSet::new();
+    for (line, direction) in chosen.iter().flat_map(|c| c.rows.iter()) {
+        let line = parent_lines.get(line).unwrap_or(line);
+        display_rows.insert(DisplayRow::new(line, direction.as_deref().unwrap_or("")));
+    }
+    // a line seen with a direction doesn't need its direction-less row too
+    let with_direction: BTreeSet<String> = display_rows
+        .iter()
+        .filter(|r| !r.direction.is_empty())
+        .map(|r| r.line.clone())
+        .collect();
+    display_rows.retain(|r| !r.direction.is_empty() || !with_direction.contains(&r.line));
+    let display_rows: Vec<DisplayRow> = display_rows.into_iter().collect();
+
+    let grid = panel_grid_size((panel.cols * panel.chained) as i32, panel.rows as i32);
+    let positions = layout_rows(&display_rows, grid);
+    prompter.say(&format!(
+        "\nLayout, {} columns of {} rows",
+        grid.0, grid.1
+    ))?;
+    for (key, (x, y)) in &positions {
+        prompter.say(&format!("  {:<8} x: {}, y: {}", key, x, y))?;
+    }
+    if display_rows.len() > positions.len() {
+        prompter.say(&format!(
+            "  {} more rows than cells, the board will page through them",
+            display_rows.len() - positions.len()
+        ))?;
+    }
+
+    let yaml = render_config(
+        &agency,
+        Some(api_key_file.as_str()).filter(|f| !f.is_empty()),
+        &panel,
+        &chosen,
+        &parent_lines,
+        &positions,
+    );
+    if let Some(dir) = options.output.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&options.output, yaml)?;
+    prompter.say(&format!("\nWrote {}", options.output.display()))?;
+
+    match Config::load(&options.output, &[]) {
+        Ok((_, warnings)) => {
+            for warning in warnings {
+                prompter.say(&format!("warning: {}", warning))?;
+            }
+        }
+        Err(e) => prompter.say(&format!("It needs some changes before the board will start, {}", e))?,
+    }
+    Ok(())
+}
+
+// Saved readable only by the owner, it's a secret
+fn write_api_key(path: &Path, key: &str) -> io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{}", key)
+}
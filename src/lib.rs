@@ -1,3 +1,26 @@
+pub mod arrivals;
+pub mod brightness;
+pub mod calendar;
 pub mod config;
+pub mod config_checker;
+pub mod config_watcher;
 pub mod display_board;
+pub mod font;
+pub mod frame;
+pub mod http_api;
+pub mod init;
+pub mod marquee;
+pub mod messages;
+pub mod metrics;
+pub mod mqtt;
+pub mod recording;
+pub mod schedule;
+pub mod service_alerts;
 pub mod stop_monitor;
+pub mod stop_search;
+pub mod systemd;
+pub mod table;
+#[cfg(test)]
+mod test_util;
+pub mod theme;
+pub mod weather;
@@ -1,98 +1,1033 @@
-use chrono::Local;
-use clap::Parser;
+use chrono::{Local, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
 use reqwest::Client;
-use std::collections::HashMap;
+use std::collections::BTreeSet;
 use std::io::{self, Write};
 use std::{thread, time};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use transit_rust::arrivals::{
+    DisplayLines, FetchSettings, arrivals, arrivals_csv, arrivals_table, drop_uncatchable,
+    fetch_display_lines, get_display_lines,
+};
+use transit_rust::brightness::{BrightnessController, light_source};
+use transit_rust::calendar::{Calendar, CalendarSlot};
+use transit_rust::config_checker::ConfigError;
+use transit_rust::config_watcher::watch_config;
 use transit_rust::display_board::DisplayBoard;
-use transit_rust::stop_monitor::{LineStop, MonitoredVehicleJourney, get_stops};
+use transit_rust::http_api::{self, RunInfo};
+use transit_rust::init::{InitOptions, Prompter, run_init};
+use transit_rust::messages::{self, Message, MessageInput};
+use transit_rust::metrics::metrics;
+use transit_rust::mqtt;
+use transit_rust::recording::{self, Snapshot};
+use transit_rust::schedule::ScheduleState;
+use transit_rust::service_alerts::{ServiceAlert, get_service_alerts};
+use transit_rust::stop_monitor::{ApiError, group_by_line_stop};
+use transit_rust::stop_search::{
+    Stop, add_live_lines, get_agency_stops, load_gtfs_stops, search_by_name, search_near,
+    stops_to_monitor_yaml,
+};
+use transit_rust::systemd::Systemd;
+use transit_rust::table::format_table;
+use transit_rust::weather::{Weather, WeatherReport};
 
-use log::debug;
-use rpi_led_matrix::{LedColor, LedFont, LedMatrix, LedMatrixOptions};
-use std::fs;
+use log::{debug, error, info, warn};
 use std::path::{Path, PathBuf};
-use yaml_rust2::{Yaml, YamlLoader};
 
-use transit_rust::config::Config;
+use transit_rust::config::{CalendarConfig, Config, ConfigOverride, WeatherConfig};
+
+// Longest a fetch under way is waited for when shutting down
+const FETCH_SHUTDOWN_GRACE: time::Duration = time::Duration::from_secs(10);
+// How often calendar files are read and the agenda worked out again
+const CALENDAR_TICK: time::Duration = time::Duration::from_secs(60);
 
 #[derive(Parser, Debug)]
+#[command(about = "Transit arrival times on an RGB LED matrix")]
 struct Args {
-    #[arg(short, long, default_value = "./etc/config.yml")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short, long, default_value = "./etc/config.yml", global = true)]
     config_path: PathBuf,
 
-    #[arg(short, long)]
-    run_color_test: bool,
+    /// Override a config key, e.g. --set display_board_config.rows=16. Applied
+    /// after the file and TRANSIT_* environment variables
+    #[arg(short = 's', long = "set", value_name = "KEY=VALUE", global = true, value_parser = ConfigOverride::parse_cli)]
+    set: Vec<ConfigOverride>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Drive the display, the default when no command is given
+    Run {
+        /// Append every API response to this file, for `replay`
+        #[arg(long, value_name = "FILE")]
+        record: Option<PathBuf>,
+    },
+    /// Validate a config file and exit, doesn't touch the display or the API
+    CheckConfig {
+        /// Defaults to --config-path
+        config_path: Option<PathBuf>,
+    },
+    /// Fetch arrivals once and print them, doesn't touch the display
+    Fetch {
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        /// Stop code to fetch instead of stops_to_monitor, can be repeated
+        #[arg(long = "stop", value_name = "CODE")]
+        stops: Vec<String>,
+        /// Append the responses to this file, for `replay`
+        #[arg(long, value_name = "FILE")]
+        record: Option<PathBuf>,
+    },
+    /// Draw one frame of the board to a PNG, doesn't touch the display
+    Render {
+        #[arg(short, long, default_value = "frame.png")]
+        output: PathBuf,
+        /// Size of each LED in the image, in pixels
+        #[arg(long, default_value_t = 8)]
+        scale: u32,
+        /// Draw the last snapshot of a recording instead of fetching
+        #[arg(long, value_name = "RECORDING")]
+        from: Option<PathBuf>,
+    },
+    /// Show a test pattern on the display
+    TestPattern {
+        #[arg(value_enum)]
+        name: TestPattern,
+        /// Seconds to hold the pattern before exiting
+        #[arg(long, default_value_t = 60)]
+        hold: u64,
+    },
+    /// Look up stop codes for stops_to_monitor by name or location
+    FindStops {
+        /// Words in the stop name, e.g. "church 24th"
+        query: Option<String>,
+        /// Stops within --radius of a point, e.g. --near 37.7515,-122.4276
+        #[arg(long, value_name = "LAT,LON", value_parser = parse_lat_lon, allow_hyphen_values = true)]
+        near: Option<(f64, f64)>,
+        /// Search radius for --near, in meters
+        #[arg(long, default_value_t = 300.0)]
+        radius: f64,
+        /// Read stops from a GTFS feed directory or stops.txt instead of the 511 API
+        #[arg(long, value_name = "PATH")]
+        gtfs: Option<PathBuf>,
+        /// 511 operator id, defaults to the config's agency
+        #[arg(long)]
+        agency: Option<String>,
+        /// Most stops to show
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// Skip looking up lines from live arrivals, one 511 request per stop
+        #[arg(long)]
+        no_lines: bool,
+        /// Print a stops_to_monitor snippet to paste into the config
+        #[arg(long)]
+        yaml: bool,
+    },
+    /// Set up a new config by answering a few questions, written to --config-path
+    Init {
+        /// Search stops in a GTFS feed directory or stops.txt instead of the 511 API
+        #[arg(long, value_name = "PATH")]
+        gtfs: Option<PathBuf>,
+        /// Overwrite an existing config without asking
+        #[arg(long)]
+        force: bool,
+    },
+    /// Play a recording made with --record back on the display
+    Replay {
+        recording: PathBuf,
+        /// Playback speed, 2 plays back twice as fast
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Start over from the beginning when the recording ends
+        #[arg(long = "loop")]
+        repeat: bool,
+    },
+}
 
-    #[arg(short, long)]
-    test_write: bool,
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
 
-    #[arg(short, long)]
-    x_test_text_colors: bool,
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum TestPattern {
+    /// Prompt for a color and show it
+    ColorPicker,
+    /// Step through colors, two seconds each
+    ColorSweep,
+    /// Text at a few positions, for checking font placement
+    Text,
+    /// Text and lines in the primary colors
+    TextColors,
 }
 
 #[tokio::main]
-async fn main() -> io::Result<()> {
+async fn main() {
     env_logger::init();
     debug!("Starting transit_rust");
 
     let args = Args::parse();
-    let config_string = fs::read_to_string(args.config_path)?;
-    let config_yaml = &YamlLoader::load_from_str(config_string.as_str()).unwrap()[0];
-    let config = Config::new(config_yaml);
+    // file, then environment, then command line
+    let mut overrides = ConfigOverride::from_env();
+    overrides.extend(args.set.iter().cloned());
+    let command = args.command.unwrap_or(Command::Run { record: None });
+    if let Command::CheckConfig { config_path } = &command {
+        check_config(config_path.as_ref().unwrap_or(&args.config_path), &overrides);
+    }
+    // used while setting up, so it can't count on there being a valid config
+    if let Command::FindStops {
+        query,
+        near,
+        radius,
+        gtfs,
+        agency,
+        limit,
+        no_lines,
+        yaml,
+    } = command
+    {
+        let config = Config::load(&args.config_path, &overrides).ok().map(|(c, _)| c);
+        let search = StopSearch {
+            query,
+            near,
+            radius,
+            limit,
+            live_lines: !no_lines,
+            yaml,
+        };
+        exit_on_error(find_stops(config.as_ref(), search, gtfs.as_deref(), agency).await);
+        return;
+    }
 
-    let client = Client::new();
+    if let Command::Init { gtfs, force } = command {
+        let options = InitOptions {
+            output: args.config_path.clone(),
+            gtfs,
+            force,
+        };
+        let mut prompter = Prompter::new(io::stdin().lock(), io::stdout());
+        exit_on_error(run_init(&mut prompter, &options).await);
+        return;
+    }
 
-    debug!("creating display board...");
+    let config = match Config::load(&args.config_path, &overrides) {
+        Ok((config, warnings)) => {
+            for warning in warnings {
+                warn!("config: {}", warning);
+            }
+            config
+        }
+        Err(e) => {
+            eprintln!("{}: {}", args.config_path.display(), e);
+            std::process::exit(1);
+        }
+    };
 
-    let font_path = Path::new(config.display_board_config().font_file());
-    debug!("font path: {:?}", font_path);
-    if !font_path.exists() {
-        panic!("font file doesn't exist");
-    }
-    let mut display_board = DisplayBoard::new(
-        config.display_board_config().rows(),
-        config.display_board_config().cols(),
-        config.display_board_config().chained(),
-        font_path,
-        config.display_board_config().line_ref_to_display_position(),
-    )
-    .unwrap();
+    let result = match command {
+        Command::Run { record } => run(&args.config_path, overrides, config, record).await,
+        Command::CheckConfig { .. } => unreachable!("handled above"),
+        Command::Fetch {
+            format,
+            stops,
+            record,
+        } => fetch(&config, format, stops, record).await,
+        Command::Render {
+            output,
+            scale,
+            from,
+        } => render(&config, &output, scale, from.as_deref()).await,
+        Command::TestPattern { name, hold } => test_pattern(&config, name, hold),
+        Command::FindStops { .. } | Command::Init { .. } => unreachable!("handled above"),
+        Command::Replay {
+            recording,
+            speed,
+            repeat,
+        } => replay(&config, &recording, speed, repeat).await,
+    };
+    exit_on_error(result);
+}
+
+fn exit_on_error(result: io::Result<()>) {
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn new_display_board(config: &Config) -> io::Result<DisplayBoard> {
+    debug!("creating display board...");
+    let mut display_board = DisplayBoard::new(config.display_board_config()).map_err(io::Error::other)?;
+    display_board.walk_minutes = config.walk_minutes().clone();
+    display_board.messages.set_events(config.messages().events().to_vec());
     debug!("Created display board");
+    Ok(display_board)
+}
 
-    if args.run_color_test {
-        run_color_test(&mut display_board);
-        return Ok(());
+async fn run(
+    config_path: &Path,
+    overrides: Vec<ConfigOverride>,
+    config: Config,
+    record: Option<PathBuf>,
+) -> io::Result<()> {
+    config.api_key().map_err(io::Error::other)?;
+    let client = Client::new();
+    let mut display_board = new_display_board(&config)?;
+
+    debug!("Stops to monitor: {:?}", config.stops_to_monitor());
+    // fetching stops while the board is blanked by the schedule or switched off
+    let (paused_tx, paused_rx) = watch::channel(config.schedule().state_at(Local::now()).blank);
+    let mut fetchers = spawn_fetchers(&client, &config, &record, &paused_rx, &mut display_board);
+    let mut schedule = config.schedule().clone();
+    let mut schedule_state: Option<ScheduleState> = None;
+    let mut auto_brightness = new_brightness_controller(&config);
+
+    // Reload the config when the file changes or on SIGHUP
+    let (reload_tx, reload_rx) = mpsc::unbounded_channel();
+    let _config_watcher = match watch_config(config_path, reload_tx.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            warn!("Unable to watch config file, reload with SIGHUP instead: {:?}", e);
+            None
+        }
+    };
+    tokio::spawn(forward_sighup(reload_tx.clone()));
+    // Status and control over HTTP and MQTT, requests are answered between
+    // frames
+    let (api_tx, mut api_rx) = mpsc::unbounded_channel();
+    let mqtt_config = config.mqtt().clone();
+    if mqtt_config.enabled() {
+        tokio::spawn(mqtt::run(mqtt_config.clone(), api_tx.clone()));
     }
-    if args.test_write {
-        display_board.test_write();
-        thread::sleep(time::Duration::from_secs(60));
+    let http_api_bind = config.http_api().enabled().then(|| config.http_api().bind().to_string());
+    if let Some(bind) = &http_api_bind {
+        let addr = bind.parse().expect("http_api.bind is checked when the config loads");
+        tokio::spawn(async move {
+            if let Err(e) = http_api::serve(addr, api_tx, reload_tx).await {
+                error!("HTTP API stopped: {}", e);
+            }
+        });
+    }
+    // Messages from the message file or FIFO
+    let (message_tx, mut message_rx) = mpsc::unbounded_channel();
+    let mut message_file = config.messages().file().map(Path::to_path_buf);
+    let mut message_watcher = spawn_message_watcher(&message_file, &message_tx);
+    let mut run_info = RunInfo {
+        started: time::Instant::now(),
+        config_path: config_path.to_path_buf(),
+        config_hash: config.hash().to_string(),
+    };
+    // SIGUSR1 switches to the next theme
+    let (theme_tx, mut theme_rx) = mpsc::unbounded_channel();
+    tokio::spawn(forward_sigusr1(theme_tx));
+    let (config_tx, mut config_rx) = mpsc::unbounded_channel();
+    tokio::spawn(run_config_reload_loop(
+        config_path.to_path_buf(),
+        overrides,
+        reload_rx,
+        config_tx,
+    ));
+
+    let mut systemd = Systemd::new();
+    let (shutdown_tx, mut shutdown_rx) = mpsc::unbounded_channel();
+    tokio::spawn(forward_shutdown_signals(shutdown_tx));
+
+    debug!("Starting render loop");
+    let mut frame_rate = config.display_board_config().frame_rate();
+    let mut frame_interval = new_frame_interval(frame_rate);
+    loop {
+        frame_interval.tick().await;
+        if let Ok(signal) = shutdown_rx.try_recv() {
+            info!("{} received, shutting down", signal);
+            break;
+        }
+        // applied between frames so the board never draws half of a config
+        while let Ok(reloaded) = config_rx.try_recv() {
+            match apply_reloaded_config(&mut display_board, reloaded) {
+                Ok(config) => {
+                    info!("Reloaded config");
+                    display_board.status_error = None;
+                    run_info.config_hash = config.hash().to_string();
+                    if config.http_api().enabled().then(|| config.http_api().bind()) != http_api_bind.as_deref() {
+                        warn!("http_api changes need a restart to take effect");
+                    }
+                    if config.mqtt() != &mqtt_config {
+                        warn!("mqtt changes need a restart to take effect");
+                    }
+                    display_board.walk_minutes = config.walk_minutes().clone();
+                    display_board.messages.set_events(config.messages().events().to_vec());
+                    if config.messages().file() != message_file.as_deref() {
+                        if let Some(watcher) = message_watcher.take() {
+                            watcher.abort();
+                        }
+                        display_board.messages.set_file(Vec::new());
+                        message_file = config.messages().file().map(Path::to_path_buf);
+                        message_watcher = spawn_message_watcher(&message_file, &message_tx);
+                    }
+                    // dropping the old fetchers stops them, anything they had in
+                    // flight for the old stops is thrown away with their channels
+                    fetchers = spawn_fetchers(&client, &config, &record, &paused_rx, &mut display_board);
+                    schedule = config.schedule().clone();
+                    // the reload reset anything the schedule had changed
+                    schedule_state = None;
+                    auto_brightness = match (new_brightness_controller(&config), &auto_brightness) {
+                        (Some(controller), Some(previous)) => Some(controller.continue_from(previous)),
+                        (controller, _) => controller,
+                    };
+                    if config.display_board_config().frame_rate() != frame_rate {
+                        frame_rate = config.display_board_config().frame_rate();
+                        frame_interval = new_frame_interval(frame_rate);
+                    }
+                }
+                Err(e) => {
+                    error!("Keeping the current config, reloaded config is invalid: {}", e);
+                    display_board.status_error = Some(format!("CONFIG ERROR {}", e));
+                }
+            }
+        }
+        let state = schedule.state_at(Local::now());
+        if schedule_state.as_ref() != Some(&state) {
+            if !state.active.is_empty() {
+                info!("Schedule rules in effect: {}", state.active.join(", "));
+            }
+            display_board.apply_schedule(&state);
+            schedule_state = Some(state);
+        }
+        display_board.ambient_brightness = match auto_brightness.as_mut() {
+            Some(controller) => controller.update(time::Instant::now()),
+            None => 100,
+        };
+        while let Ok(()) = theme_rx.try_recv() {
+            display_board.next_theme();
+            info!("Switched to theme {}", display_board.theme_name());
+        }
+        while let Ok(display_lines) = fetchers.update_rx.try_recv() {
+            update_display_board(&mut display_board, display_lines);
+        }
+        while let Ok(service_alerts) = fetchers.alerts_rx.try_recv() {
+            display_board.service_alerts = service_alerts;
+        }
+        while let Ok(agenda) = fetchers.calendar_rx.try_recv() {
+            display_board.show_agenda(agenda);
+        }
+        while let Ok(report) = fetchers.weather_rx.try_recv() {
+            display_board.weather_report = Some(report);
+        }
+        while let Ok(input) = message_rx.try_recv() {
+            match input {
+                MessageInput::Post(Some((message, duration))) => {
+                    info!("Showing message `{}`", message.text);
+                    display_board.set_message(message, duration);
+                }
+                MessageInput::Post(None) => display_board.clear_message(),
+                MessageInput::File(file_messages) => display_board.messages.set_file(file_messages),
+            }
+        }
+        while let Ok(request) = api_rx.try_recv() {
+            let response = http_api::respond(request.command, &mut display_board, &run_info);
+            // the client may have given up waiting
+            let _ = request.reply.send(response);
+        }
+        // blank for the schedule or switched off
+        let blank = display_board.is_blank();
+        if paused_tx.send_if_modified(|paused| std::mem::replace(paused, blank) != blank) {
+            metrics().board_blank.set(blank as i64);
+        }
+        let render_start = time::Instant::now();
+        display_board.write_times();
+        display_board.present();
+        metrics().frame_rendered(render_start.elapsed());
+        // only reached while the loop is running, a board stuck anywhere in
+        // here stops pinging the watchdog
+        systemd.set_status(&status_line(&display_board));
+        systemd.frame_drawn(time::Instant::now());
+    }
+
+    systemd.stopping();
+    let hold = display_board.show_shutdown_frame();
+    let hold_until = tokio::time::Instant::now() + hold;
+    fetchers.finish(FETCH_SHUTDOWN_GRACE).await;
+    if !hold.is_zero() {
+        info!("Holding the shutdown frame for {:?}", hold);
+        // a second signal cuts it short
+        tokio::select! {
+            _ = tokio::time::sleep_until(hold_until) => {}
+            _ = shutdown_rx.recv() => {}
+        }
+    }
+    display_board.release();
+    info!("Stopped");
+    Ok(())
+}
+
+// One line on how the board is doing, for `systemctl status`
+fn status_line(display_board: &DisplayBoard) -> String {
+    if display_board.is_blank() {
+        return "Blank for the schedule".to_string();
+    }
+    if let Some(error) = &display_board.status_error {
+        return error.clone();
+    }
+    let rows = display_board.display_lines.as_ref().map_or(0, |lines| lines.len());
+    match (display_board.last_successful_request_time, &display_board.last_fetch_error) {
+        (_, Some(error)) => format!("Fetching arrivals failed: {}", error),
+        (Some(updated), None) => format!("Showing {} rows, updated {}", rows, updated.format("%H:%M:%S")),
+        (None, None) => "Waiting for arrivals".to_string(),
+    }
+}
+
+async fn fetch(
+    config: &Config,
+    format: OutputFormat,
+    stops: Vec<String>,
+    record: Option<PathBuf>,
+) -> io::Result<()> {
+    config.api_key().map_err(io::Error::other)?;
+    let mut settings = FetchSettings::new(config);
+    if !stops.is_empty() {
+        settings.stops_to_monitor = stops;
+    }
+    settings.record = record;
+    let display_lines = fetch_display_lines(&Client::new(), &settings)
+        .await
+        .map_err(io::Error::other)?;
+    let arrivals = arrivals(&display_lines);
+    match format {
+        OutputFormat::Table => print!("{}", arrivals_table(&arrivals)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&arrivals)?),
+        OutputFormat::Csv => print!("{}", arrivals_csv(&arrivals)),
+    }
+    Ok(())
+}
+
+async fn render(config: &Config, output: &Path, scale: u32, from: Option<&Path>) -> io::Result<()> {
+    let mut display_board =
+        DisplayBoard::headless(config.display_board_config()).map_err(io::Error::other)?;
+    display_board.walk_minutes = config.walk_minutes().clone();
+    display_board.messages.set_events(config.messages().events().to_vec());
+    let settings = FetchSettings::new(config);
+    match from {
+        Some(path) => {
+            let snapshots = recording::load(path)?;
+            let snapshot = snapshots
+                .last()
+                .ok_or_else(|| io::Error::other(format!("{} is empty", path.display())))?;
+            show_snapshot(&mut display_board, snapshot, &settings);
+        }
+        None => {
+            config.api_key().map_err(io::Error::other)?;
+            let client = Client::new();
+            let display_lines = fetch_display_lines(&client, &settings)
+                .await
+                .map_err(io::Error::other)?;
+            update_display_board(&mut display_board, Ok(display_lines));
+            if config.service_alerts().enabled() {
+                display_board.show_alert_ticker = config.service_alerts().ticker();
+                match fetch_service_alerts(&client, &settings).await {
+                    Ok(service_alerts) => display_board.service_alerts = service_alerts,
+                    Err(e) => warn!("Failed to get service alerts: {:?}", e),
+                }
+            }
+        }
+    }
+    if config.calendar().enabled() {
+        let mut calendar = new_calendar(config.calendar());
+        calendar.refresh(&Client::new()).await;
+        display_board.show_agenda(calendar_agenda(&calendar, config.calendar()));
+    }
+    if let Some(weather_config) = config.weather() {
+        let mut weather = Weather::from_config(weather_config);
+        // the cached forecast is used until it's due again
+        if weather.next_fetch(Utc::now()).is_zero() {
+            weather.fetch(&Client::new()).await;
+        }
+        display_board.set_weather(Some(weather_config.clone()));
+        display_board.weather_report = weather.report().cloned();
+    }
+    display_board.write_times();
+    display_board.frame.write_png(output, scale)?;
+    println!("wrote {}", output.display());
+    Ok(())
+}
+
+fn test_pattern(config: &Config, name: TestPattern, hold: u64) -> io::Result<()> {
+    let mut display_board = new_display_board(config)?;
+    match name {
+        TestPattern::ColorPicker => run_color_test(&mut display_board),
+        TestPattern::ColorSweep => display_board.test_colors(),
+        TestPattern::Text => {
+            display_board.test_write();
+            thread::sleep(time::Duration::from_secs(hold));
+        }
+        TestPattern::TextColors => {
+            display_board.test_text_colors();
+            thread::sleep(time::Duration::from_secs(hold));
+        }
+    }
+    Ok(())
+}
+
+// What find-stops is looking for and how to print it
+struct StopSearch {
+    query: Option<String>,
+    near: Option<(f64, f64)>,
+    radius: f64,
+    limit: usize,
+    live_lines: bool,
+    yaml: bool,
+}
+
+fn parse_lat_lon(arg: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("expected LAT,LON e.g. 37.7515,-122.4276, got `{}`", arg);
+    let (lat, lon) = arg.split_once(',').ok_or_else(invalid)?;
+    let lat: f64 = lat.trim().parse().map_err(|_| invalid())?;
+    let lon: f64 = lon.trim().parse().map_err(|_| invalid())?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(invalid());
+    }
+    Ok((lat, lon))
+}
+
+async fn find_stops(
+    config: Option<&Config>,
+    search: StopSearch,
+    gtfs: Option<&Path>,
+    agency: Option<String>,
+) -> io::Result<()> {
+    if search.query.is_none() && search.near.is_none() {
+        return Err(io::Error::other("give a name to search for or --near LAT,LON"));
+    }
+    let client = Client::new();
+    let agency = agency
+        .or_else(|| config.map(|c| c.agency().to_string()))
+        .unwrap_or_else(|| "SF".to_string());
+    let api_key = config
+        .and_then(|c| c.api_key().ok())
+        .map(str::to_string)
+        .or_else(|| std::env::var("TRANSIT_TOKEN").ok());
+
+    let stops = match (gtfs, &api_key) {
+        (Some(path), _) => load_gtfs_stops(path)?,
+        (None, Some(api_key)) => get_agency_stops(&client, &agency, api_key)
+            .await
+            .map_err(io::Error::other)?,
+        (None, None) => {
+            return Err(io::Error::other(
+                "no 511 API key, fix the config (see check-config), set TRANSIT_TOKEN or use --gtfs",
+            ));
+        }
+    };
+
+    let mut found: Vec<(&Stop, Option<f64>)> = match search.near {
+        Some((latitude, longitude)) => search_near(&stops, latitude, longitude, search.radius)
+            .into_iter()
+            .map(|(stop, distance)| (stop, Some(distance)))
+            .collect(),
+        None => stops.iter().map(|stop| (stop, None)).collect(),
+    };
+    if let Some(query) = &search.query {
+        let named = search_by_name(&stops, query);
+        found.retain(|(stop, _)| named.iter().any(|n| std::ptr::eq(*n, *stop)));
+    }
+    if found.len() > search.limit {
+        eprintln!(
+            "{} stops match, showing the first {} (see --limit)",
+            found.len(),
+            search.limit
+        );
+        found.truncate(search.limit);
+    }
+
+    // the 511 stop list doesn't say what serves a stop, live arrivals do
+    let mut found: Vec<(Stop, Option<f64>)> =
+        found.into_iter().map(|(stop, d)| (stop.clone(), d)).collect();
+    if let (None, Some(api_key), true) = (gtfs, &api_key, search.live_lines) {
+        for (stop, _) in found.iter_mut() {
+            if let Err(e) = add_live_lines(&client, stop, &agency, api_key).await {
+                warn!("Unable to look up lines for stop {}: {:?}", stop.code, e);
+            }
+        }
+    }
+
+    if search.yaml {
+        let stops: Vec<&Stop> = found.iter().map(|(stop, _)| stop).collect();
+        print!("{}", stops_to_monitor_yaml(&stops));
         return Ok(());
     }
-    if args.x_test_text_colors {
-        display_board.test_text_colors();
-        thread::sleep(time::Duration::from_secs(60));
+    if found.is_empty() {
+        println!("no {} stops found", agency);
         return Ok(());
     }
+    let join = |set: &BTreeSet<String>| set.iter().cloned().collect::<Vec<_>>().join(", ");
+    let rows: Vec<Vec<String>> = found
+        .iter()
+        .map(|(stop, distance)| {
+            vec![
+                stop.code.clone(),
+                stop.name.clone(),
+                join(&stop.directions),
+                join(&stop.lines),
+                distance.map(|d| format!("{:.0}m", d)).unwrap_or_default(),
+            ]
+        })
+        .collect();
+    print!(
+        "{}",
+        format_table(&["CODE", "NAME", "DIRECTION", "LINES", "DISTANCE"], &rows)
+    );
+    Ok(())
+}
 
-    let stops_to_monitor = config.stops_to_monitor();
-    debug!("Stops to monitor: {:?}", stops_to_monitor);
-    debug!("Starting update loop");
+async fn replay(config: &Config, path: &Path, speed: f64, repeat: bool) -> io::Result<()> {
+    let snapshots = recording::load(path)?;
+    if snapshots.is_empty() {
+        return Err(io::Error::other(format!("{} is empty", path.display())));
+    }
+    let speed = if speed > 0.0 { speed } else { 1.0 };
+    let settings = FetchSettings::new(config);
+    let mut display_board = new_display_board(config)?;
+    let mut frame_interval = new_frame_interval(config.display_board_config().frame_rate());
     loop {
-        update_display_board(
-            &mut display_board,
-            &client,
-            config.rapid_line_to_parent_line_map(),
-            stops_to_monitor,
-        )
-        .await;
-        display_board.write_times();
-        if display_board.last_request_successful {
-            thread::sleep(time::Duration::from_secs(30));
-        } else {
-            thread::sleep(time::Duration::from_secs(2));
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            debug!("Replaying snapshot recorded at {}", snapshot.recorded_at);
+            show_snapshot(&mut display_board, snapshot, &settings);
+            // hold each snapshot for as long as it was live, the last for a
+            // normal fetch interval
+            let dwell = match snapshots.get(i + 1) {
+                Some(next) => (next.recorded_at - snapshot.recorded_at)
+                    .to_std()
+                    .unwrap_or_default(),
+                None => time::Duration::from_secs(30),
+            };
+            let until = tokio::time::Instant::now() + dwell.div_f64(speed);
+            while tokio::time::Instant::now() < until {
+                frame_interval.tick().await;
+                display_board.write_times();
+                display_board.present();
+            }
+        }
+        if !repeat {
+            return Ok(());
+        }
+    }
+}
+
+// Put a recorded snapshot on the board as if it had just been fetched
+fn show_snapshot(display_board: &mut DisplayBoard, snapshot: &Snapshot, settings: &FetchSettings) {
+    let mut stops = group_by_line_stop(snapshot.journeys(Utc::now()));
+    drop_uncatchable(&mut stops, &settings.min_lead_minutes);
+    update_display_board(
+        display_board,
+        Ok(get_display_lines(stops, &settings.rapid_line_to_parent_map, true)),
+    );
+    display_board.last_successful_request_time = Some(snapshot.recorded_at.with_timezone(&Local));
+}
+
+fn spawn_message_watcher(
+    path: &Option<PathBuf>,
+    message_tx: &mpsc::UnboundedSender<MessageInput>,
+) -> Option<JoinHandle<()>> {
+    let path = path.clone()?;
+    debug!("Watching {} for messages", path.display());
+    Some(tokio::spawn(messages::watch_file(path, message_tx.clone())))
+}
+
+fn new_brightness_controller(config: &Config) -> Option<BrightnessController> {
+    let auto_brightness = config.display_board_config().auto_brightness()?;
+    Some(BrightnessController::new(
+        auto_brightness,
+        light_source(auto_brightness.source()),
+    ))
+}
+
+fn new_frame_interval(frame_rate: u32) -> tokio::time::Interval {
+    let mut frame_interval =
+        tokio::time::interval(time::Duration::from_secs_f64(1.0 / frame_rate.max(1) as f64));
+    frame_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    frame_interval
+}
+
+// Background tasks that feed the board, stopped when dropped
+struct Fetchers {
+    update_rx: mpsc::UnboundedReceiver<Result<DisplayLines, String>>,
+    alerts_rx: mpsc::UnboundedReceiver<Vec<ServiceAlert>>,
+    calendar_rx: mpsc::UnboundedReceiver<Option<(CalendarSlot, Message)>>,
+    weather_rx: mpsc::UnboundedReceiver<WeatherReport>,
+    tasks: Vec<JoinHandle<()>>,
+    // tells the tasks to stop once they're between fetches
+    stopping: watch::Sender<bool>,
+}
+
+impl Fetchers {
+    // Stop once any fetch under way is done, so a recording isn't left with
+    // half a line. Waits at most `grace`, whatever they fetch is dropped.
+    async fn finish(mut self, grace: time::Duration) {
+        self.stopping.send_replace(true);
+        let deadline = tokio::time::Instant::now() + grace;
+        for task in self.tasks.iter_mut() {
+            if tokio::time::timeout_at(deadline, task).await.is_err() {
+                warn!("Fetch still running after {:?}, stopping it", grace);
+                // dropping aborts the rest
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for Fetchers {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+// Fetching runs on its own task so the board keeps drawing (and scrolling)
+// while requests are in flight
+fn spawn_fetchers(
+    client: &Client,
+    config: &Config,
+    record: &Option<PathBuf>,
+    paused: &watch::Receiver<bool>,
+    display_board: &mut DisplayBoard,
+) -> Fetchers {
+    let mut settings = FetchSettings::new(config);
+    settings.record = record.clone();
+    let (stopping, stopping_rx) = watch::channel(false);
+    let (update_tx, update_rx) = mpsc::unbounded_channel();
+    let mut tasks = vec![tokio::spawn(run_fetch_loop(
+        client.clone(),
+        settings.clone(),
+        paused.clone(),
+        stopping_rx.clone(),
+        update_tx,
+    ))];
+
+    let (alerts_tx, alerts_rx) = mpsc::unbounded_channel();
+    if config.service_alerts().enabled() {
+        display_board.show_alert_ticker = config.service_alerts().ticker();
+        tasks.push(tokio::spawn(run_service_alerts_loop(
+            client.clone(),
+            settings,
+            time::Duration::from_secs(config.service_alerts().refresh_secs()),
+            paused.clone(),
+            stopping_rx.clone(),
+            alerts_tx,
+        )));
+    } else {
+        display_board.show_alert_ticker = false;
+        display_board.service_alerts.clear();
+    }
+
+    let (calendar_tx, calendar_rx) = mpsc::unbounded_channel();
+    if config.calendar().enabled() {
+        tasks.push(tokio::spawn(run_calendar_loop(
+            client.clone(),
+            config.calendar().clone(),
+            stopping_rx.clone(),
+            calendar_tx,
+        )));
+    } else {
+        display_board.show_agenda(None);
+    }
+
+    let (weather_tx, weather_rx) = mpsc::unbounded_channel();
+    display_board.set_weather(config.weather().cloned());
+    if let Some(weather) = config.weather() {
+        tasks.push(tokio::spawn(run_weather_loop(
+            client.clone(),
+            weather.clone(),
+            paused.clone(),
+            stopping_rx,
+            weather_tx,
+        )));
+    }
+    Fetchers {
+        update_rx,
+        alerts_rx,
+        calendar_rx,
+        weather_rx,
+        tasks,
+        stopping,
+    }
+}
+
+async fn forward_sighup(reload_tx: mpsc::UnboundedSender<()>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Unable to listen for SIGHUP: {:?}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        debug!("SIGHUP received, reloading config");
+        if reload_tx.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+// SIGTERM (systemctl stop) and SIGINT (Ctrl-C) end the render loop, which
+// then shuts the board down
+async fn forward_shutdown_signals(shutdown_tx: mpsc::UnboundedSender<&'static str>) {
+    let (mut terminate, mut interrupt) =
+        match (signal(SignalKind::terminate()), signal(SignalKind::interrupt())) {
+            (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Unable to listen for SIGTERM and SIGINT: {:?}", e);
+                return;
+            }
+        };
+    loop {
+        let name = tokio::select! {
+            _ = terminate.recv() => "SIGTERM",
+            _ = interrupt.recv() => "SIGINT",
+        };
+        if shutdown_tx.send(name).is_err() {
+            return;
+        }
+    }
+}
+
+async fn forward_sigusr1(theme_tx: mpsc::UnboundedSender<()>) {
+    let mut user_defined = match signal(SignalKind::user_defined1()) {
+        Ok(user_defined) => user_defined,
+        Err(e) => {
+            warn!("Unable to listen for SIGUSR1: {:?}", e);
+            return;
+        }
+    };
+    while user_defined.recv().await.is_some() {
+        if theme_tx.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+// Loads and validates the config each time a reload is asked for, the render
+// loop only ever sees a complete config or the reason it was rejected
+async fn run_config_reload_loop(
+    config_path: PathBuf,
+    overrides: Vec<ConfigOverride>,
+    mut reload_rx: mpsc::UnboundedReceiver<()>,
+    config_tx: mpsc::UnboundedSender<Result<Config, ConfigError>>,
+) {
+    while reload_rx.recv().await.is_some() {
+        // saving a file fires several events, let the writes settle and
+        // load once for all of them
+        tokio::time::sleep(time::Duration::from_millis(250)).await;
+        while reload_rx.try_recv().is_ok() {}
+
+        let reloaded = match Config::load(&config_path, &overrides) {
+            Ok((config, warnings)) => {
+                for warning in warnings {
+                    warn!("config: {}", warning);
+                }
+                Ok(config)
+            }
+            Err(e) => Err(e),
+        };
+        if config_tx.send(reloaded).is_err() {
+            return;
+        }
+    }
+}
+
+// Hold off while the board is blanked, there's no point using up API quota.
+// Returns right away once it's unpaused so the board wakes with fresh times,
+// false if the board is shutting down instead.
+// Check a reloaded config and put it on the board. Anything that can reject
+// it runs before the board changes so a bad config leaves the board alone.
+fn apply_reloaded_config(
+    display_board: &mut DisplayBoard,
+    reloaded: Result<Config, ConfigError>,
+) -> Result<Config, String> {
+    let config = reloaded.map_err(|e| {
+        e.errors.first().map_or_else(|| "invalid config".to_string(), |issue| issue.to_string())
+    })?;
+    config.api_key()?;
+    display_board
+        .apply_config(config.display_board_config())
+        .map_err(|e| e.to_string())?;
+    Ok(config)
+}
+
+async fn wait_while_paused(
+    paused: &mut watch::Receiver<bool>,
+    stopping: &mut watch::Receiver<bool>,
+) -> bool {
+    if *paused.borrow() {
+        debug!("Board is blank, pausing fetches");
+    }
+    tokio::select! {
+        biased;
+        _ = stopping.wait_for(|stopping| *stopping) => false,
+        // only fails once the render loop has gone away
+        _ = paused.wait_for(|paused| !paused) => true,
+    }
+}
+
+// Wait until the next fetch, false if the board starts shutting down first
+async fn sleep_unless_stopping(duration: time::Duration, stopping: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => true,
+        _ = stopping.wait_for(|stopping| *stopping) => false,
+    }
+}
+
+async fn run_fetch_loop(
+    client: Client,
+    settings: FetchSettings,
+    mut paused: watch::Receiver<bool>,
+    mut stopping: watch::Receiver<bool>,
+    update_tx: mpsc::UnboundedSender<Result<DisplayLines, String>>,
+) {
+    loop {
+        if !wait_while_paused(&mut paused, &mut stopping).await {
+            return;
+        }
+        let display_lines = fetch_display_lines(&client, &settings).await.map_err(|e| {
+            debug!("Failed to get display lines: {:?}", e);
+            // the url has the api key in it
+            e.without_url().to_string()
+        });
+        let successful = display_lines.is_ok();
+        if successful {
+            metrics().fetch_succeeded();
+        }
+        if update_tx.send(display_lines).is_err() {
+            // render loop has gone away
+            return;
+        }
+        let delay = if successful { 30 } else { 2 };
+        if !sleep_unless_stopping(time::Duration::from_secs(delay), &mut stopping).await {
+            return;
+        }
+    }
+}
+
+// Print every problem with the config and exit, non zero if it can't be used
+fn check_config(config_path: &Path, overrides: &[ConfigOverride]) -> ! {
+    match Config::load(config_path, overrides) {
+        Ok((_, warnings)) => {
+            for warning in &warnings {
+                println!("warning: {}", warning);
+            }
+            println!("{}: OK", config_path.display());
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprint!("{}: {}", config_path.display(), e);
+            std::process::exit(1);
         }
     }
-    Ok(())
 }
 
 fn run_color_test(display_board: &mut DisplayBoard) {
@@ -122,7 +1057,7 @@ fn read_i32_input(prompt: &str) -> i32 {
 
         match input.trim().parse::<i32>() {
             Ok(num) => {
-                if num >= 0 && num <= 255 {
+                if (0..=255).contains(&num) {
                     return num;
                 } else {
                     println!("Value must be between 0 and 255. Please try again.");
@@ -133,60 +1068,159 @@ fn read_i32_input(prompt: &str) -> i32 {
     }
 }
 
-async fn update_display_board(
-    display_board: &mut DisplayBoard,
+// Alerts for our lines or stops, with rapid lines also flagging their parent
+async fn fetch_service_alerts(
     client: &Client,
-    rapid_line_to_parent_map: &HashMap<String, String>,
-    stops_to_monitor: &Vec<String>,
+    settings: &FetchSettings,
+) -> Result<Vec<ServiceAlert>, ApiError> {
+    let mut service_alerts = get_service_alerts(client, &settings.agency, &settings.api_key).await?;
+    // keep alerts for a line, the board matches those against its
+    // rows, or for one of our stops
+    service_alerts
+        .retain(|a| !a.lines.is_empty() || a.affects_any_stop(&settings.stops_to_monitor));
+    for alert in service_alerts.iter_mut() {
+        alert.add_parent_lines(&settings.rapid_line_to_parent_map);
+    }
+    Ok(service_alerts)
+}
+
+// Alerts change slowly, so they're fetched on their own (much longer) interval
+async fn run_service_alerts_loop(
+    client: Client,
+    settings: FetchSettings,
+    refresh: time::Duration,
+    mut paused: watch::Receiver<bool>,
+    mut stopping: watch::Receiver<bool>,
+    alerts_tx: mpsc::UnboundedSender<Vec<ServiceAlert>>,
 ) {
-    if let Ok(stops) = get_stops(client, stops_to_monitor).await {
-        if let Ok(display_lines) = get_display_lines(stops, rapid_line_to_parent_map, true) {
-            debug!("Received lines to display");
-            (*display_board).display_lines = Some(display_lines);
-            (*display_board).last_successful_request_time = Some(Local::now());
-            (*display_board).last_request_successful = true;
-        } else {
-            debug!("Failed to get display lines");
-            (*display_board).last_request_successful = false;
+    loop {
+        if !wait_while_paused(&mut paused, &mut stopping).await {
+            return;
+        }
+        match fetch_service_alerts(&client, &settings).await {
+            Ok(service_alerts) => {
+                debug!("Received {} service alerts", service_alerts.len());
+                if alerts_tx.send(service_alerts).is_err() {
+                    return;
+                }
+            }
+            Err(e) => debug!("Failed to get service alerts: {:?}", e),
+        }
+        if !sleep_unless_stopping(refresh, &mut stopping).await {
+            return;
         }
-    } else {
-        (*display_board).last_request_successful = false;
     }
 }
 
-fn get_display_lines(
-    stops: HashMap<LineStop, Vec<MonitoredVehicleJourney>>,
-    rapid_line_to_parent_map: &HashMap<String, String>,
-    use_line_to_parent_map: bool,
-) -> Result<HashMap<String, Vec<MonitoredVehicleJourney>>, reqwest::Error> {
-    const DEFAULT_TIME_TO_ARRIVAL: i64 = 999;
-    let mut display: HashMap<String, Vec<MonitoredVehicleJourney>> = HashMap::new();
-
-    for (line_stop, value) in stops.into_iter() {
-        let parent_line = if use_line_to_parent_map {
-            match rapid_line_to_parent_map.get(line_stop.line_ref.as_str()) {
-                Some(parent_line) => parent_line.clone(),
-                None => line_stop.screen_display(),
+// Files are read again every CALENDAR_TICK, which also moves the agenda on as
+// events end and days change. URLs are only fetched every refresh_mins.
+async fn run_calendar_loop(
+    client: Client,
+    config: CalendarConfig,
+    mut stopping: watch::Receiver<bool>,
+    calendar_tx: mpsc::UnboundedSender<Option<(CalendarSlot, Message)>>,
+) {
+    let mut calendar = new_calendar(&config);
+    // only the text changes, the slot, priority and color come from the config
+    let mut last_text = None;
+    loop {
+        calendar.refresh(&client).await;
+        let agenda = calendar_agenda(&calendar, &config);
+        let text = agenda.as_ref().map(|(_, message)| message.text.clone());
+        if last_text.as_ref() != Some(&text) {
+            debug!("Calendar agenda: {:?}", text);
+            if calendar_tx.send(agenda).is_err() {
+                return;
             }
-        } else {
-            line_stop.screen_display()
-        };
+            last_text = Some(text);
+        }
+        if !sleep_unless_stopping(CALENDAR_TICK, &mut stopping).await {
+            return;
+        }
+    }
+}
 
-        // Add new time to arrivals or create a new entry in display lines
-        for mvj in value {
-            if mvj.time_to_arrival().is_some() {
-                display
-                    .entry(parent_line.clone())
-                    .or_insert_with(Vec::new)
-                    .push(mvj);
+// The weather is fetched every refresh_mins whatever the transit polling is
+// doing, and a forecast cached by an earlier run is shown until then
+async fn run_weather_loop(
+    client: Client,
+    config: WeatherConfig,
+    mut paused: watch::Receiver<bool>,
+    mut stopping: watch::Receiver<bool>,
+    weather_tx: mpsc::UnboundedSender<WeatherReport>,
+) {
+    let mut weather = Weather::from_config(&config);
+    if let Some(report) = weather.report() {
+        if weather_tx.send(report.clone()).is_err() {
+            return;
+        }
+    }
+    loop {
+        if !sleep_unless_stopping(weather.next_fetch(Utc::now()), &mut stopping).await {
+            return;
+        }
+        if !wait_while_paused(&mut paused, &mut stopping).await {
+            return;
+        }
+        if let Some(report) = weather.fetch(&client).await {
+            if weather_tx.send(report.clone()).is_err() {
+                return;
             }
         }
     }
+}
+
+fn new_calendar(config: &CalendarConfig) -> Calendar {
+    Calendar::new(
+        config.sources().to_vec(),
+        time::Duration::from_secs(config.refresh_mins() * 60),
+    )
+}
+
+// Today's and upcoming events as a message for where the config puts them
+fn calendar_agenda(calendar: &Calendar, config: &CalendarConfig) -> Option<(CalendarSlot, Message)> {
+    let text = calendar.agenda(Local::now().naive_local(), config.lookahead_days())?;
+    let mut message = Message::new(&text);
+    message.priority = config.priority();
+    message.color = config.color();
+    Some((config.slot(), message))
+}
 
-    // Sort the values by time to arrival
-    for value in display.values_mut() {
-        value.sort_by_key(|a| a.time_to_arrival().unwrap_or(DEFAULT_TIME_TO_ARRIVAL));
+fn update_display_board(display_board: &mut DisplayBoard, display_lines: Result<DisplayLines, String>) {
+    match display_lines {
+        Ok(display_lines) => {
+            display_board.display_lines = Some(display_lines);
+            display_board.last_successful_request_time = Some(Local::now());
+            display_board.last_request_successful = true;
+            display_board.last_fetch_error = None;
+        }
+        Err(e) => {
+            display_board.last_request_successful = false;
+            display_board.last_fetch_error = Some(e);
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(display)
+    #[test]
+    fn keyless_reload_leaves_the_board_alone() {
+        let with_key = "stops_to_monitor: [\"15553\"]\napi_key: key\n";
+        let (config, _) = Config::parse(with_key, &[]).unwrap();
+        let mut display_board = DisplayBoard::headless(config.display_board_config()).unwrap();
+
+        // TRANSIT_TOKEN would stand in for the missing key, no other test reads it
+        std::env::remove_var("TRANSIT_TOKEN");
+        let keyless = "stops_to_monitor: [\"15553\"]\n\
+            display_board_config:\n  theme: night_red\n  marquee_speed: 99\n  page_dwell_secs: 99\n";
+        let reloaded = Config::parse(keyless, &[]).map(|(config, _)| config);
+        let error = apply_reloaded_config(&mut display_board, reloaded).err().unwrap();
+
+        assert!(error.starts_with("no 511 API key"), "{}", error);
+        assert_eq!(display_board.theme_name(), config.display_board_config().theme());
+        assert_eq!(display_board.marquee_speed, config.display_board_config().marquee_speed());
+        assert_eq!(display_board.page_dwell.as_secs(), config.display_board_config().page_dwell_secs());
+    }
 }
@@ -0,0 +1,133 @@
+use std::time::Instant;
+
+use crate::font::BdfFont;
+use crate::frame::{FrameBuffer, Rect};
+use rpi_led_matrix::LedColor;
+
+// Blank pixels between the end of the text and the start of its next pass
+const MARQUEE_GAP_COLS: i32 = 12;
+
+// Text that scrolls right to left through a fixed region of the board.
+// Position is worked out from the time since the text was set, so the scroll
+// speed is the same whatever rate frames are drawn at.
+pub struct Marquee {
+    text: String,
+    bounds: Rect,
+    // pixels per second
+    speed: f32,
+    started: Instant,
+}
+
+impl Marquee {
+    pub fn new(text: &str, bounds: Rect, speed: f32) -> Self {
+        Marquee {
+            text: text.to_string(),
+            bounds,
+            speed,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    // Restarts the scroll only when the text actually changes
+    pub fn set_text(&mut self, text: &str) {
+        if self.text != text {
+            self.text = text.to_string();
+            self.started = Instant::now();
+        }
+    }
+
+    pub fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    // Scroll offset in pixels at the given time, 0 when the text fits
+    pub fn offset(&self, font: &BdfFont, now: Instant) -> i32 {
+        let text_width = font.text_width(&self.text);
+        if text_width <= self.bounds.width || self.speed <= 0.0 {
+            return 0;
+        }
+        let elapsed = now.saturating_duration_since(self.started).as_secs_f64();
+        let cycle = text_width + MARQUEE_GAP_COLS;
+        // down to a single pass before scaling, so text that has been up for
+        // days scrolls as smoothly as new text
+        let speed = self.speed as f64;
+        ((elapsed % (cycle as f64 / speed)) * speed) as i32 % cycle
+    }
+
+    pub fn draw(&self, frame: &mut FrameBuffer, font: &BdfFont, color: &LedColor, now: Instant) {
+        // bottom pixel row of the text, text is vertically aligned to the top of bounds
+        let y = self.bounds.y + font.height() - 1;
+        let offset = self.offset(font, now);
+        let x = self.bounds.x - offset;
+        frame.draw_text_clipped(font, &self.text, x, y, color, &self.bounds);
+        if offset > 0 {
+            // second copy follows the first so the loop is seamless
+            let next_x = x + font.text_width(&self.text) + MARQUEE_GAP_COLS;
+            frame.draw_text_clipped(font, &self.text, next_x, y, color, &self.bounds);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn font() -> BdfFont {
+        BdfFont::parse(include_str!("../4x6.bdf")).unwrap()
+    }
+
+    #[test]
+    fn text_that_fits_does_not_scroll() {
+        let marquee = Marquee::new("HI", Rect::new(0, 0, 64, 6), 10.0);
+        assert_eq!(marquee.offset(&font(), marquee.started + Duration::from_secs(5)), 0);
+    }
+
+    #[test]
+    fn scrolls_at_speed_and_wraps() {
+        let font = font();
+        // 20 characters of 4 pixels, so a cycle is 80 + 12 pixels
+        let marquee = Marquee::new("ABCDEFGHIJKLMNOPQRST", Rect::new(0, 0, 32, 6), 10.0);
+        let at = |secs: f64| marquee.offset(&font, marquee.started + Duration::from_secs_f64(secs));
+        assert_eq!(at(0.0), 0);
+        assert_eq!(at(2.5), 25);
+        assert_eq!(at(9.25), 0);
+        assert_eq!(at(9.35), 1);
+    }
+
+    #[test]
+    fn stays_exact_after_running_for_weeks() {
+        let font = font();
+        let marquee = Marquee::new("ABCDEFGHIJKLMNOPQRST", Rect::new(0, 0, 32, 6), 10.0);
+        // 30 days is a whole number of 9.2s cycles plus 1.2s, which an f32
+        // of the elapsed seconds can only tell to the nearest quarter second
+        let month = Duration::from_secs(30 * 24 * 3600);
+        for (extra_ms, expected) in [(50, 12), (150, 13), (2750, 39), (8050, 0)] {
+            let now = marquee.started + month + Duration::from_millis(extra_ms);
+            assert_eq!(marquee.offset(&font, now), expected, "{}ms past 30 days", extra_ms);
+        }
+    }
+
+    #[test]
+    fn fast_scroll_does_not_freeze() {
+        let font = font();
+        let marquee = Marquee::new("ABCDEFGHIJKLMNOPQRST", Rect::new(0, 0, 32, 6), 1_000_000.0);
+        let year = Duration::from_secs(365 * 24 * 3600);
+        let first = marquee.offset(&font, marquee.started + year);
+        let later = marquee.offset(&font, marquee.started + year + Duration::from_micros(20));
+        assert_ne!(first, later);
+        assert!((0..92).contains(&later));
+    }
+}
@@ -0,0 +1,351 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, Weekday};
+use log::{debug, info, warn};
+use rpi_led_matrix::LedColor;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::unix::pipe;
+use tokio::sync::mpsc;
+
+use crate::theme;
+
+// How often the message file is checked for changes
+const FILE_POLL: Duration = Duration::from_secs(2);
+
+// Text put up on the bottom row in place of the service alerts
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub text: String,
+    // the highest priority of the messages due is shown
+    pub priority: i32,
+    // the theme's text color when None
+    pub color: Option<LedColor>,
+    // scrolls when it's too wide for the board, otherwise it's cut off
+    pub scroll: bool,
+}
+
+impl Message {
+    pub fn new(text: &str) -> Self {
+        Message {
+            text: text.to_string(),
+            priority: 0,
+            color: None,
+            scroll: true,
+        }
+    }
+}
+
+// Day a message from the config is shown on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventDate {
+    // every year, e.g. a birthday
+    Yearly { month: u32, day: u32 },
+    On(NaiveDate),
+}
+
+// "02-02" for every year or "2026-12-24" for one day
+pub fn parse_date(date: &str) -> Option<EventDate> {
+    let date = date.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return Some(EventDate::On(date));
+    }
+    let (month, day) = date.split_once('-')?;
+    let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+    // 2000 is a leap year, so 02-29 is allowed
+    NaiveDate::from_ymd_opt(2000, month, day)?;
+    Some(EventDate::Yearly { month, day })
+}
+
+// A message from the config, shown on its date and days between from and to.
+// Unlike schedule rules the times don't run past midnight.
+#[derive(Clone, Debug)]
+pub struct MessageEvent {
+    pub message: Message,
+    // any day when None
+    pub date: Option<EventDate>,
+    // every day when empty
+    pub days: HashSet<Weekday>,
+    pub from: Option<NaiveTime>,
+    pub to: Option<NaiveTime>,
+}
+
+impl MessageEvent {
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        let today = now.date_naive();
+        let on_date = match self.date {
+            None => true,
+            Some(EventDate::Yearly { month, day }) => today.month() == month && today.day() == day,
+            Some(EventDate::On(date)) => today == date,
+        };
+        on_date
+            && (self.days.is_empty() || self.days.contains(&today.weekday()))
+            && self.from.is_none_or(|from| now.time() >= from)
+            && self.to.is_none_or(|to| now.time() < to)
+    }
+}
+
+// Where the message being shown came from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageSource {
+    // the HTTP API, MQTT or the message FIFO
+    Posted,
+    File,
+    Calendar,
+    Config,
+}
+
+impl MessageSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageSource::Posted => "posted",
+            MessageSource::File => "file",
+            MessageSource::Calendar => "calendar",
+            MessageSource::Config => "config",
+        }
+    }
+}
+
+pub struct ShownMessage<'a> {
+    pub message: &'a Message,
+    pub source: MessageSource,
+    // None when it's shown until cleared or its time is up
+    pub until: Option<Instant>,
+}
+
+// Every message the board knows about, only one is shown at a time
+#[derive(Default)]
+pub struct Messages {
+    events: Vec<MessageEvent>,
+    file: Vec<Message>,
+    // today's and upcoming events, when the calendar goes on the bottom row
+    calendar: Option<Message>,
+    // the last one posted replaces the one before, until the instant it
+    // expires if it has one
+    posted: Option<(Message, Option<Instant>)>,
+}
+
+impl Messages {
+    pub fn set_events(&mut self, events: Vec<MessageEvent>) {
+        self.events = events;
+    }
+
+    pub fn set_file(&mut self, messages: Vec<Message>) {
+        self.file = messages;
+    }
+
+    pub fn set_calendar(&mut self, agenda: Option<Message>) {
+        self.calendar = agenda;
+    }
+
+    pub fn post(&mut self, message: Message, duration: Option<Duration>) {
+        self.posted = Some((message, duration.map(|d| Instant::now() + d)));
+    }
+
+    pub fn clear_posted(&mut self) {
+        self.posted = None;
+    }
+
+    // Highest priority message due, on a tie a posted message wins over the
+    // file, the file over the calendar and the calendar over the config
+    pub fn current(&self, now: DateTime<Local>, instant: Instant) -> Option<ShownMessage<'_>> {
+        let posted = self
+            .posted
+            .iter()
+            .filter(|(_, until)| until.is_none_or(|until| until > instant))
+            .map(|(message, until)| ShownMessage {
+                message,
+                source: MessageSource::Posted,
+                until: *until,
+            });
+        let file = self.file.iter().map(|message| ShownMessage {
+            message,
+            source: MessageSource::File,
+            until: None,
+        });
+        let calendar = self.calendar.iter().map(|message| ShownMessage {
+            message,
+            source: MessageSource::Calendar,
+            until: None,
+        });
+        let events = self
+            .events
+            .iter()
+            .filter(|event| event.is_active(now))
+            .map(|event| ShownMessage {
+                message: &event.message,
+                source: MessageSource::Config,
+                until: None,
+            });
+        posted.chain(file).chain(calendar).chain(events).fold(None, |best, shown| match best {
+            Some(best) if best.message.priority >= shown.message.priority => Some(best),
+            _ => Some(shown),
+        })
+    }
+}
+
+// A message and how long to show it, from its text and the optional settings
+// that come with it in an HTTP request, an MQTT payload or a line of the
+// message file
+pub fn message_from_fields(
+    text: &str,
+    secs: Option<&str>,
+    priority: Option<&str>,
+    color: Option<&str>,
+    scroll: Option<&str>,
+) -> Result<(Message, Option<Duration>), &'static str> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("text is missing");
+    }
+    let mut message = Message::new(text);
+    if let Some(priority) = priority {
+        message.priority = priority
+            .trim()
+            .parse()
+            .map_err(|_| "priority must be a whole number")?;
+    }
+    if let Some(color) = color {
+        message.color =
+            Some(theme::parse_color(color).ok_or("color must be \"#rrggbb\" or a name like orange")?);
+    }
+    if let Some(scroll) = scroll {
+        message.scroll = match scroll.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => true,
+            "false" | "no" | "0" => false,
+            _ => return Err("scroll must be true or false"),
+        };
+    }
+    let duration = match secs {
+        Some(secs) => Some(Duration::from_secs(
+            secs.trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or("secs must be a number of seconds")?,
+        )),
+        None => None,
+    };
+    Ok((message, duration))
+}
+
+// Plain text, or JSON with the same fields as the HTTP API:
+// {"text": "Trash day", "secs": 3600, "priority": 5, "color": "green", "scroll": false}
+// Empty clears the message.
+pub fn parse_message(payload: &str) -> Result<Option<(Message, Option<Duration>)>, &'static str> {
+    let payload = payload.trim();
+    if payload.is_empty() {
+        return Ok(None);
+    }
+    let fields = match serde_json::from_str::<Value>(payload) {
+        Ok(Value::Object(fields)) => fields,
+        _ => return message_from_fields(payload, None, None, None, None).map(Some),
+    };
+    // numbers and booleans are read the same as their text
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .filter(|value| !value.is_null())
+            .map(|value| value.as_str().map(String::from).unwrap_or_else(|| value.to_string()))
+    };
+    let text = field("text").unwrap_or_default();
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    message_from_fields(
+        &text,
+        field("secs").as_deref(),
+        field("priority").as_deref(),
+        field("color").as_deref(),
+        field("scroll").as_deref(),
+    )
+    .map(Some)
+}
+
+// What the message file has for the render loop
+pub enum MessageInput {
+    // a line written to the FIFO, None clears
+    Post(Option<(Message, Option<Duration>)>),
+    // everything in a plain file, replacing what it had before
+    File(Vec<Message>),
+}
+
+// Follows the message file. A FIFO is read a line at a time, each line posted
+// like a message from the HTTP API. A plain file is read whole whenever it
+// changes, one message per line, and its messages stay up until they're taken
+// out of it or it's deleted.
+pub async fn watch_file(path: PathBuf, input_tx: mpsc::UnboundedSender<MessageInput>) {
+    let mut modified: Option<SystemTime> = None;
+    loop {
+        match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => {
+                if let Err(e) = read_fifo(&path, &input_tx).await {
+                    warn!("Unable to read message FIFO {}: {}", path.display(), e);
+                }
+            }
+            Ok(metadata) => {
+                let changed = metadata.modified().ok();
+                if modified.is_none() || changed != modified {
+                    modified = changed;
+                    let messages = read_file(&path);
+                    debug!("Read {} messages from {}", messages.len(), path.display());
+                    if input_tx.send(MessageInput::File(messages)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                if modified.take().is_some() && input_tx.send(MessageInput::File(Vec::new())).is_err() {
+                    return;
+                }
+            }
+            Err(e) => warn!("Unable to read message file {}: {}", path.display(), e),
+        }
+        if input_tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(FILE_POLL).await;
+    }
+}
+
+// Opened for writing as well as reading so it never sees end of file when a
+// writer closes it, and opening doesn't wait for one
+async fn read_fifo(path: &PathBuf, input_tx: &mpsc::UnboundedSender<MessageInput>) -> std::io::Result<()> {
+    let receiver = pipe::OpenOptions::new().read_write(true).open_receiver(path)?;
+    info!("Reading messages from FIFO {}", path.display());
+    let mut lines = BufReader::new(receiver).lines();
+    while let Some(line) = lines.next_line().await? {
+        match parse_message(&line) {
+            Ok(message) => {
+                if input_tx.send(MessageInput::Post(message)).is_err() {
+                    return Ok(());
+                }
+            }
+            Err(e) => warn!("Ignoring message `{}`: {}", line, e),
+        }
+    }
+    Ok(())
+}
+
+fn read_file(path: &PathBuf) -> Vec<Message> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Unable to read message file {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut messages = Vec::new();
+    for line in content.lines() {
+        match parse_message(line) {
+            // secs don't mean anything here, the file says how long
+            Ok(Some((message, _))) => messages.push(message),
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring message `{}` in {}: {}", line, path.display(), e),
+        }
+    }
+    messages
+}
@@ -0,0 +1,215 @@
+use chrono::Utc;
+use log::warn;
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, HistogramTimer, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::stop_monitor::ApiError;
+
+// 511 allows 60 requests an hour per API key unless a higher limit is asked for
+const API_REQUESTS_PER_HOUR: usize = 60;
+const QUOTA_WINDOW: Duration = Duration::from_secs(3600);
+
+// Counters and timings for Prometheus, served at /metrics by the HTTP API.
+// Shared by the whole process since the API requests are made from
+// several tasks and commands.
+pub struct Metrics {
+    registry: Registry,
+    started: Instant,
+    pub fetch_attempts: IntCounterVec,
+    pub fetch_successes: IntCounterVec,
+    pub fetch_failures: IntCounterVec,
+    api_request_duration: HistogramVec,
+    api_requests_last_hour: IntGauge,
+    api_quota_used: Gauge,
+    pub arrivals_parsed: IntCounter,
+    pub malformed_visits_skipped: IntCounter,
+    render_duration: Histogram,
+    frame_rate: Gauge,
+    last_fetch_success: Gauge,
+    staleness: Gauge,
+    pub board_blank: IntGauge,
+    // when each API request in the last hour was made
+    api_requests: Mutex<VecDeque<Instant>>,
+    // start of the current second and the frames drawn in it
+    frames: Mutex<(Instant, u32)>,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let counter_vec = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounter::new(name, help).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let gauge = |name: &str, help: &str| {
+            let gauge = Gauge::new(name, help).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+        let int_gauge = |name: &str, help: &str| {
+            let gauge = IntGauge::new(name, help).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+
+        let api_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "transit_api_request_duration_seconds",
+                "Time taken by 511 API requests, by endpoint",
+            )
+            .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 30.0]),
+            &["endpoint"],
+        )
+        .unwrap();
+        registry.register(Box::new(api_request_duration.clone())).unwrap();
+        let render_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "transit_render_duration_seconds",
+                "Time taken to draw a frame and copy it to the matrix",
+            )
+            .buckets(vec![0.0005, 0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1]),
+        )
+        .unwrap();
+        registry.register(Box::new(render_duration.clone())).unwrap();
+
+        Metrics {
+            fetch_attempts: counter_vec(
+                "transit_fetch_attempts_total",
+                "Arrival fetches started, by stop",
+                &["stop"],
+            ),
+            fetch_successes: counter_vec(
+                "transit_fetch_successes_total",
+                "Arrival fetches that got a response, by stop",
+                &["stop"],
+            ),
+            fetch_failures: counter_vec(
+                "transit_fetch_failures_total",
+                "Arrival fetches that failed, by stop and kind of error",
+                &["stop", "kind"],
+            ),
+            api_request_duration,
+            api_requests_last_hour: int_gauge(
+                "transit_api_requests_last_hour",
+                "511 API requests made in the last hour",
+            ),
+            api_quota_used: gauge(
+                "transit_api_quota_used_ratio",
+                "Estimated share of the hourly 511 API quota used",
+            ),
+            arrivals_parsed: counter(
+                "transit_arrivals_parsed_total",
+                "Arrivals read from StopMonitoring responses",
+            ),
+            malformed_visits_skipped: counter(
+                "transit_malformed_visits_skipped_total",
+                "Stop visits skipped because they were missing fields",
+            ),
+            render_duration,
+            frame_rate: gauge("transit_frame_rate", "Frames drawn per second"),
+            last_fetch_success: gauge(
+                "transit_last_fetch_success_timestamp_seconds",
+                "Unix time the board last got arrivals",
+            ),
+            staleness: gauge(
+                "transit_staleness_seconds",
+                "Seconds since the board last got arrivals, or since it started if it hasn't",
+            ),
+            board_blank: int_gauge(
+                "transit_board_blank",
+                "1 while the board is blank or switched off and fetching paused",
+            ),
+            registry,
+            started: Instant::now(),
+            api_requests: Mutex::new(VecDeque::new()),
+            frames: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    // Count a 511 API request against the quota and time it until the timer is
+    // dropped
+    pub fn api_request(&self, endpoint: &str) -> HistogramTimer {
+        let mut requests = self.api_requests.lock().unwrap();
+        requests.push_back(Instant::now());
+        drop(requests);
+        self.update_quota();
+        self.api_request_duration.with_label_values(&[endpoint]).start_timer()
+    }
+
+    fn update_quota(&self) {
+        let mut requests = self.api_requests.lock().unwrap();
+        while requests.front().is_some_and(|made| made.elapsed() > QUOTA_WINDOW) {
+            requests.pop_front();
+        }
+        self.api_requests_last_hour.set(requests.len() as i64);
+        self.api_quota_used.set(requests.len() as f64 / API_REQUESTS_PER_HOUR as f64);
+    }
+
+    // Short name for what went wrong with a request
+    pub fn error_kind(error: &ApiError) -> String {
+        let error = match error {
+            ApiError::Request(error) => error,
+            ApiError::Json(_) => return "json".to_string(),
+        };
+        if error.is_timeout() {
+            "timeout".to_string()
+        } else if error.is_connect() {
+            "connect".to_string()
+        } else if let Some(status) = error.status() {
+            format!("http_{}", status.as_u16())
+        } else if error.is_decode() || error.is_body() {
+            "body".to_string()
+        } else {
+            "request".to_string()
+        }
+    }
+
+    pub fn fetch_succeeded(&self) {
+        self.last_fetch_success.set(Utc::now().timestamp() as f64);
+    }
+
+    pub fn frame_rendered(&self, took: Duration) {
+        self.render_duration.observe(took.as_secs_f64());
+        let mut frames = self.frames.lock().unwrap();
+        frames.1 += 1;
+        let elapsed = frames.0.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.frame_rate.set(frames.1 as f64 / elapsed.as_secs_f64());
+            *frames = (Instant::now(), 0);
+        }
+    }
+
+    // Everything in the Prometheus text format
+    pub fn encode(&self) -> String {
+        self.update_quota();
+        // worked out now rather than by the render loop, so it keeps growing
+        // even if the board has stopped drawing
+        let last_success = self.last_fetch_success.get();
+        self.staleness.set(if last_success > 0.0 {
+            Utc::now().timestamp() as f64 - last_success
+        } else {
+            self.started.elapsed().as_secs() as f64
+        });
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            warn!("Unable to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
@@ -0,0 +1,475 @@
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::MqttConfig;
+use crate::http_api::{
+    ApiCommand, ApiRequest, ask_board, parse_brightness, parse_page, parse_power,
+};
+use crate::messages::parse_message;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+enum BrokerEvent {
+    Connected,
+    Disconnected,
+    // the part of the topic after set/, and the payload
+    Command(String, String),
+}
+
+// Publishes arrivals by line and the board's health to the broker, and passes
+// commands from the set/ topics to the render loop the same way the HTTP API
+// does. Everything published is retained, and only sent when it changes.
+// Without a clean disconnect the broker marks the board offline through the
+// last will.
+pub async fn run(config: MqttConfig, api_tx: mpsc::UnboundedSender<ApiRequest>) {
+    let mut options = MqttOptions::new(config.client_id(), config.host(), config.port());
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some(username) = config.username() {
+        options.set_credentials(username, config.password().unwrap_or_default());
+    }
+    let mut publisher = Publisher::new(config);
+    options.set_last_will(LastWill::new(
+        publisher.topic("availability"),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    let (client, eventloop) = AsyncClient::new(options, 64);
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    tokio::spawn(poll_broker(eventloop, publisher.topic("set/"), event_tx));
+
+    let mut interval = tokio::time::interval(Duration::from_secs(publisher.config.publish_secs()));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut connected = false;
+    loop {
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Some(BrokerEvent::Connected) => {
+                    connected = true;
+                    publisher.connected(&client).await;
+                }
+                Some(BrokerEvent::Disconnected) => connected = false,
+                Some(BrokerEvent::Command(name, payload)) => {
+                    run_command(&name, &payload, &api_tx).await;
+                }
+                None => return,
+            },
+            _ = interval.tick() => {}
+        }
+        // nothing is queued while disconnected, it'd be stale by the time it
+        // went out
+        if connected {
+            publisher.publish_board(&client, &api_tx).await;
+        }
+    }
+}
+
+// Drives the connection, rumqttc reconnects on the next poll after an error
+async fn poll_broker(
+    mut eventloop: EventLoop,
+    command_prefix: String,
+    event_tx: mpsc::UnboundedSender<BrokerEvent>,
+) {
+    // only the first failure of an outage is worth a warning
+    let mut warned = false;
+    loop {
+        let event = match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to MQTT broker");
+                warned = false;
+                BrokerEvent::Connected
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let name = match command_name(&publish.topic, &command_prefix) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                let payload = String::from_utf8_lossy(&publish.payload).trim().to_string();
+                BrokerEvent::Command(name, payload)
+            }
+            Ok(_) => continue,
+            Err(e) => {
+                if warned {
+                    debug!("Still unable to reach MQTT broker: {}", e);
+                } else {
+                    warn!("Unable to reach MQTT broker, retrying every {:?}: {}", RECONNECT_DELAY, e);
+                    warned = true;
+                }
+                if event_tx.send(BrokerEvent::Disconnected).is_err() {
+                    return;
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        if event_tx.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+// Command name from a topic under the set/ prefix, e.g. "power"
+fn command_name<'a>(topic: &'a str, command_prefix: &str) -> Option<&'a str> {
+    topic
+        .strip_prefix(command_prefix)
+        .filter(|name| !name.is_empty() && !name.contains('/'))
+}
+
+fn parse_command(name: &str, payload: &str) -> Result<ApiCommand, &'static str> {
+    match name {
+        "power" => parse_power(payload).map(ApiCommand::SetPower),
+        "brightness" => parse_brightness(payload).map(ApiCommand::SetBrightness),
+        "page" => parse_page(payload).map(ApiCommand::SetPage),
+        "message" => parse_message(payload).map(ApiCommand::SetMessage),
+        _ => Err("unknown command"),
+    }
+}
+
+async fn run_command(name: &str, payload: &str, api_tx: &mpsc::UnboundedSender<ApiRequest>) {
+    let command = match parse_command(name, payload) {
+        Ok(command) => command,
+        Err(e) => {
+            warn!("Ignoring MQTT command {} `{}`: {}", name, payload, e);
+            return;
+        }
+    };
+    debug!("MQTT command {} `{}`", name, payload);
+    let response = ask_board(command, api_tx).await;
+    if !response.is_ok() {
+        warn!(
+            "MQTT command {} failed: {}",
+            name,
+            String::from_utf8_lossy(response.body())
+        );
+    }
+}
+
+// Topic levels and Home Assistant ids only get letters, digits, - and _
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+struct Publisher {
+    config: MqttConfig,
+    // last payload sent on each topic since connecting
+    published: HashMap<String, String>,
+    // lines seen since starting, kept so a line with nothing coming shows as
+    // unknown rather than disappearing
+    lines: BTreeMap<String, String>,
+}
+
+impl Publisher {
+    fn new(config: MqttConfig) -> Self {
+        Publisher {
+            config,
+            published: HashMap::new(),
+            lines: BTreeMap::new(),
+        }
+    }
+
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.config.topic_prefix(), name)
+    }
+
+    async fn publish(&mut self, client: &AsyncClient, topic: String, payload: String) {
+        if self.published.get(&topic) == Some(&payload) {
+            return;
+        }
+        if let Err(e) = client.publish(&topic, QoS::AtLeastOnce, true, payload.clone()).await {
+            warn!("Unable to publish to {}: {}", topic, e);
+            return;
+        }
+        self.published.insert(topic, payload);
+    }
+
+    // The broker may have restarted and lost everything retained, so it all
+    // goes out again
+    async fn connected(&mut self, client: &AsyncClient) {
+        self.published.clear();
+        let commands = self.topic("set/+");
+        if let Err(e) = client.subscribe(&commands, QoS::AtLeastOnce).await {
+            warn!("Unable to subscribe to {}: {}", commands, e);
+        }
+        self.publish(client, self.topic("availability"), "online".to_string()).await;
+        if self.config.discovery() {
+            for (topic, payload) in self.discovery_configs() {
+                self.publish(client, topic, payload.to_string()).await;
+            }
+            let lines: Vec<String> = self.lines.keys().cloned().collect();
+            for line in lines {
+                self.publish_line_discovery(client, &line).await;
+            }
+        }
+    }
+
+    async fn publish_board(&mut self, client: &AsyncClient, api_tx: &mpsc::UnboundedSender<ApiRequest>) {
+        // the board has stopped answering when it's shutting down
+        let status = ask_board(ApiCommand::Status, api_tx).await;
+        if !status.is_ok() {
+            return;
+        }
+        if let Ok(status) = serde_json::from_slice::<Value>(status.body()) {
+            self.publish_status(client, &status).await;
+        }
+        let arrivals = ask_board(ApiCommand::Arrivals, api_tx).await;
+        if !arrivals.is_ok() {
+            return;
+        }
+        if let Ok(Value::Array(arrivals)) = serde_json::from_slice::<Value>(arrivals.body()) {
+            self.publish_arrivals(client, arrivals).await;
+        }
+    }
+
+    async fn publish_status(&mut self, client: &AsyncClient, status: &Value) {
+        // uptime and the like are left out so this only changes when the
+        // board does
+        let health = json!({
+            "last_fetch": status["last_fetch"],
+            "last_fetch_ok": status["last_fetch_ok"],
+            "last_fetch_error": status["last_fetch_error"],
+            "status_error": status["status_error"],
+            "rows": status["rows"],
+            "service_alerts": status["service_alerts"],
+            "theme": status["theme"],
+            "blank": status["blank"],
+        });
+        self.publish(client, self.topic("health"), health.to_string()).await;
+        let power = if status["on"].as_bool().unwrap_or(true) { "ON" } else { "OFF" };
+        self.publish(client, self.topic("state/power"), power.to_string()).await;
+        let brightness = status["brightness"]["percent"].to_string();
+        self.publish(client, self.topic("state/brightness"), brightness).await;
+        let message = status["message"]["text"].as_str().unwrap_or_default().to_string();
+        self.publish(client, self.topic("state/message"), message).await;
+        // 0 while paging on its own
+        let page = status["page"]["override"].as_u64().unwrap_or(0).to_string();
+        self.publish(client, self.topic("state/page"), page).await;
+    }
+
+    async fn publish_arrivals(&mut self, client: &AsyncClient, arrivals: Vec<Value>) {
+        let mut by_line: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for arrival in arrivals {
+            if let Some(line) = arrival["line"].as_str() {
+                by_line.entry(line.to_string()).or_default().push(arrival);
+            }
+        }
+        for line in by_line.keys() {
+            if !self.lines.contains_key(line) {
+                debug!("Publishing arrivals for new line {}", line);
+                self.lines.insert(line.clone(), slug(line));
+                if self.config.discovery() {
+                    self.publish_line_discovery(client, line).await;
+                }
+            }
+        }
+        let lines: Vec<(String, String)> = self.lines.clone().into_iter().collect();
+        for (line, line_slug) in lines {
+            let mut arrivals = by_line.remove(&line).unwrap_or_default();
+            arrivals.sort_by_key(|arrival| arrival["minutes"].as_i64());
+            let payload = json!({
+                "line": line,
+                "next_minutes": arrivals.first().map(|arrival| arrival["minutes"].clone()),
+                "arrivals": arrivals,
+            });
+            self.publish(client, self.topic(&format!("arrivals/{}", line_slug)), payload.to_string())
+                .await;
+        }
+    }
+
+    fn device(&self) -> Value {
+        json!({
+            "identifiers": [self.config.client_id()],
+            "name": self.config.client_id(),
+            "model": "LED matrix transit board",
+            "manufacturer": "transit_rust",
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        })
+    }
+
+    // Home Assistant config for an entity, with what they all share filled in
+    fn discovery_config(&self, component: &str, id: &str, name: &str, mut config: Value) -> (String, Value) {
+        let node = slug(self.config.client_id());
+        config["name"] = json!(name);
+        config["unique_id"] = json!(format!("{}_{}", node, id));
+        config["availability_topic"] = json!(self.topic("availability"));
+        config["device"] = self.device();
+        let topic = format!("{}/{}/{}/{}/config", self.config.discovery_prefix(), component, node, id);
+        (topic, config)
+    }
+
+    fn discovery_configs(&self) -> Vec<(String, Value)> {
+        vec![
+            self.discovery_config(
+                "light",
+                "board",
+                "Board",
+                json!({
+                    "command_topic": self.topic("set/power"),
+                    "state_topic": self.topic("state/power"),
+                    "brightness_command_topic": self.topic("set/brightness"),
+                    "brightness_state_topic": self.topic("state/brightness"),
+                    "brightness_scale": 100,
+                    "payload_on": "ON",
+                    "payload_off": "OFF",
+                }),
+            ),
+            self.discovery_config(
+                "text",
+                "message",
+                "Message",
+                json!({
+                    "command_topic": self.topic("set/message"),
+                    "state_topic": self.topic("state/message"),
+                    "max": 255,
+                    "icon": "mdi:message-text",
+                }),
+            ),
+            self.discovery_config(
+                "number",
+                "page",
+                "Page",
+                json!({
+                    "command_topic": self.topic("set/page"),
+                    "state_topic": self.topic("state/page"),
+                    "min": 0,
+                    "max": 20,
+                    "step": 1,
+                    "mode": "box",
+                    "icon": "mdi:book-open-page-variant",
+                }),
+            ),
+            self.discovery_config(
+                "binary_sensor",
+                "problem",
+                "Fetch problem",
+                json!({
+                    "state_topic": self.topic("health"),
+                    "value_template": "{{ 'OFF' if value_json.last_fetch_ok else 'ON' }}",
+                    "device_class": "problem",
+                    "json_attributes_topic": self.topic("health"),
+                }),
+            ),
+            self.discovery_config(
+                "sensor",
+                "last_fetch",
+                "Last update",
+                json!({
+                    "state_topic": self.topic("health"),
+                    "value_template": "{{ value_json.last_fetch }}",
+                    "device_class": "timestamp",
+                }),
+            ),
+        ]
+    }
+
+    // A sensor for the next arrival on a line, added as lines turn up
+    fn line_discovery_config(&self, line: &str) -> (String, Value) {
+        let line_topic = self.topic(&format!("arrivals/{}", slug(line)));
+        self.discovery_config(
+            "sensor",
+            &format!("line_{}", slug(line)),
+            &format!("{} arrival", line),
+            json!({
+                "state_topic": line_topic,
+                "value_template": "{{ value_json.next_minutes }}",
+                "unit_of_measurement": "min",
+                "json_attributes_topic": line_topic,
+                "icon": "mdi:bus-clock",
+            }),
+        )
+    }
+
+    async fn publish_line_discovery(&mut self, client: &AsyncClient, line: &str) {
+        let (topic, config) = self.line_discovery_config(line);
+        self.publish(client, topic, config.to_string()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_checker::ConfigChecker;
+    use yaml_rust2::YamlLoader;
+
+    fn publisher(source: &str) -> Publisher {
+        let config = &YamlLoader::load_from_str(source).unwrap()[0];
+        Publisher::new(MqttConfig::new(config, &mut ConfigChecker::new(source)))
+    }
+
+    #[test]
+    fn command_names_come_from_one_level_under_set() {
+        let prefix = publisher("client_id: hallway\n").topic("set/");
+        assert_eq!(prefix, "transit_rust/set/");
+        assert_eq!(command_name("transit_rust/set/power", &prefix), Some("power"));
+        assert_eq!(command_name("transit_rust/set/", &prefix), None);
+        assert_eq!(command_name("transit_rust/set/power/extra", &prefix), None);
+        assert_eq!(command_name("transit_rust/state/power", &prefix), None);
+        assert_eq!(command_name("other/set/power", &prefix), None);
+    }
+
+    #[test]
+    fn commands_parse_like_the_http_api() {
+        assert!(matches!(parse_command("power", "ON"), Ok(ApiCommand::SetPower(true))));
+        assert!(matches!(parse_command("power", "off"), Ok(ApiCommand::SetPower(false))));
+        assert!(matches!(parse_command("brightness", "40"), Ok(ApiCommand::SetBrightness(Some(40)))));
+        assert!(matches!(parse_command("brightness", "auto"), Ok(ApiCommand::SetBrightness(None))));
+        // pages count from 1 on the wire and 0 inside
+        assert!(matches!(parse_command("page", "2"), Ok(ApiCommand::SetPage(Some(1)))));
+        assert!(matches!(parse_command("page", "0"), Ok(ApiCommand::SetPage(None))));
+        assert!(matches!(parse_command("message", ""), Ok(ApiCommand::SetMessage(None))));
+        assert!(matches!(parse_command("message", "Back at 5"), Ok(ApiCommand::SetMessage(Some(_)))));
+        assert!(parse_command("power", "dim").is_err());
+        assert!(parse_command("brightness", "150").is_err());
+        assert!(parse_command("theme", "night_red").is_err());
+    }
+
+    #[test]
+    fn discovery_configs_share_device_and_availability() {
+        let publisher = publisher("client_id: hall way\ntopic_prefix: home/board\n");
+        let configs = publisher.discovery_configs();
+        let topics: Vec<&str> = configs.iter().map(|(topic, _)| topic.as_str()).collect();
+        assert_eq!(
+            topics,
+            [
+                "homeassistant/light/hall_way/board/config",
+                "homeassistant/text/hall_way/message/config",
+                "homeassistant/number/hall_way/page/config",
+                "homeassistant/binary_sensor/hall_way/problem/config",
+                "homeassistant/sensor/hall_way/last_fetch/config",
+            ]
+        );
+        for (topic, config) in &configs {
+            assert_eq!(config["availability_topic"], "home/board/availability", "{}", topic);
+            assert_eq!(config["device"]["identifiers"], json!(["hall way"]), "{}", topic);
+            assert!(config["unique_id"].as_str().unwrap().starts_with("hall_way_"), "{}", topic);
+            assert!(config["name"].is_string(), "{}", topic);
+        }
+        let (_, light) = &configs[0];
+        assert_eq!(light["command_topic"], "home/board/set/power");
+        assert_eq!(light["state_topic"], "home/board/state/power");
+        assert_eq!(light["brightness_command_topic"], "home/board/set/brightness");
+        assert_eq!(light["brightness_scale"], 100);
+    }
+
+    #[test]
+    fn line_sensors_read_their_arrivals_topic() {
+        let publisher = publisher("discovery_prefix: ha\n");
+        let (topic, config) = publisher.line_discovery_config("14R");
+        assert_eq!(topic, "ha/sensor/transit_rust/line_14R/config");
+        assert_eq!(config["unique_id"], "transit_rust_line_14R");
+        assert_eq!(config["name"], "14R arrival");
+        assert_eq!(config["state_topic"], "transit_rust/arrivals/14R");
+        assert_eq!(config["json_attributes_topic"], "transit_rust/arrivals/14R");
+        assert_eq!(config["value_template"], "{{ value_json.next_minutes }}");
+        // topic levels can't have a / or a space in them
+        let (topic, _) = publisher.line_discovery_config("T Third/Mission");
+        assert_eq!(topic, "ha/sensor/transit_rust/line_T_Third_Mission/config");
+    }
+}
@@ -0,0 +1,91 @@
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::stop_monitor::{MonitoredVehicleJourney, extract_monitored_vehicle_journeys};
+
+// Recordings are raw StopMonitoring responses stored one per line as JSON:
+//   {"recorded_at": "2024-05-01T08:00:00Z", "stop": "15551", "response": {...}}
+// Responses from the same fetch share recorded_at and make up one snapshot.
+
+// Everything fetched at one point in time
+pub struct Snapshot {
+    pub recorded_at: DateTime<Utc>,
+    pub responses: Vec<(String, Value)>,
+}
+
+impl Snapshot {
+    // Arrivals moved forward by however long ago the snapshot was recorded, so
+    // the board shows the same times it showed back then
+    pub fn journeys(&self, now: DateTime<Utc>) -> Vec<MonitoredVehicleJourney> {
+        let shift = now - self.recorded_at;
+        let mut journeys: Vec<MonitoredVehicleJourney> = self
+            .responses
+            .iter()
+            .flat_map(|(_, response)| extract_monitored_vehicle_journeys(response.clone()))
+            .collect();
+        for mvj in journeys.iter_mut() {
+            mvj.shift_arrival(shift);
+        }
+        journeys
+    }
+}
+
+// Append the responses of one fetch to a recording
+pub fn append(
+    path: &Path,
+    recorded_at: DateTime<Utc>,
+    responses: &[(String, Value)],
+) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for (stop, response) in responses {
+        let record = json!({
+            "recorded_at": recorded_at.to_rfc3339(),
+            "stop": stop,
+            "response": response,
+        });
+        writeln!(file, "{}", record)?;
+    }
+    Ok(())
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<Snapshot>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}, line {}: {}", path.display(), i + 1, message),
+            )
+        };
+        let record: Value = serde_json::from_str(&line).map_err(|e| invalid(&e.to_string()))?;
+        let recorded_at = record["recorded_at"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .ok_or_else(|| invalid("missing or invalid recorded_at"))?
+            .with_timezone(&Utc);
+        let stop = record["stop"].as_str().unwrap_or("").to_string();
+        let response = record["response"].clone();
+        if !response.is_object() {
+            return Err(invalid("missing response"));
+        }
+
+        match snapshots.last_mut() {
+            Some(snapshot) if snapshot.recorded_at == recorded_at => {
+                snapshot.responses.push((stop, response))
+            }
+            _ => snapshots.push(Snapshot {
+                recorded_at,
+                responses: vec![(stop, response)],
+            }),
+        }
+    }
+    Ok(snapshots)
+}
@@ -0,0 +1,217 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Weekday};
+use std::collections::{HashMap, HashSet};
+
+// A time-of-day rule. Between `from` and `to` on the listed days the board
+// takes on whatever the rule sets, later rules winning over earlier ones.
+#[derive(Clone, Debug)]
+pub struct ScheduleRule {
+    pub name: String,
+    // days the rule starts on, every day when empty
+    pub days: HashSet<Weekday>,
+    // all day when both are None. A range ending before it starts runs past
+    // midnight into the next day.
+    pub from: Option<NaiveTime>,
+    pub to: Option<NaiveTime>,
+    // percent
+    pub brightness: Option<u8>,
+    pub blank: Option<bool>,
+    // only rows for these lines, "22" or "22:IB"
+    pub lines: Option<HashSet<String>>,
+    pub theme: Option<String>,
+    pub line_ref_to_display_position: Option<HashMap<String, (i32, i32)>>,
+}
+
+// What the rules in effect at some moment ask for
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduleState {
+    pub brightness: u8,
+    pub blank: bool,
+    pub lines: Option<HashSet<String>>,
+    pub theme: Option<String>,
+    pub line_ref_to_display_position: Option<HashMap<String, (i32, i32)>>,
+    // names of the rules in effect
+    pub active: Vec<String>,
+}
+
+impl Default for ScheduleState {
+    fn default() -> Self {
+        ScheduleState {
+            brightness: 100,
+            blank: false,
+            lines: None,
+            theme: None,
+            line_ref_to_display_position: None,
+            active: Vec::new(),
+        }
+    }
+}
+
+// "mon" .. "sun", or "weekdays", "weekends" and "daily" for several at once
+pub fn parse_days(day: &str) -> Option<Vec<Weekday>> {
+    use Weekday::*;
+    let days = match day.trim().to_lowercase().as_str() {
+        "daily" | "everyday" => vec![Mon, Tue, Wed, Thu, Fri, Sat, Sun],
+        "weekdays" => vec![Mon, Tue, Wed, Thu, Fri],
+        "weekends" => vec![Sat, Sun],
+        day => vec![day.parse::<Weekday>().ok()?],
+    };
+    Some(days)
+}
+
+// "06:30" or "6:30"
+pub fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+}
+
+impl ScheduleRule {
+    fn runs_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        let time = now.time();
+        let today = now.weekday();
+        let yesterday = (now - Duration::days(1)).weekday();
+        match (self.from, self.to) {
+            (None, None) => self.runs_on(today),
+            (Some(from), None) => self.runs_on(today) && time >= from,
+            (None, Some(to)) => self.runs_on(today) && time < to,
+            (Some(from), Some(to)) if from <= to => self.runs_on(today) && time >= from && time < to,
+            // past midnight, the early hours belong to the day it started
+            (Some(from), Some(to)) => {
+                (self.runs_on(today) && time >= from) || (self.runs_on(yesterday) && time < to)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    pub rules: Vec<ScheduleRule>,
+}
+
+impl Schedule {
+    pub fn state_at(&self, now: DateTime<Local>) -> ScheduleState {
+        let mut state = ScheduleState::default();
+        for rule in self.rules.iter().filter(|r| r.is_active(now)) {
+            if let Some(brightness) = rule.brightness {
+                state.brightness = brightness;
+            }
+            if let Some(blank) = rule.blank {
+                state.blank = blank;
+            }
+            if rule.lines.is_some() {
+                state.lines = rule.lines.clone();
+            }
+            if rule.theme.is_some() {
+                state.theme = rule.theme.clone();
+            }
+            if rule.line_ref_to_display_position.is_some() {
+                state.line_ref_to_display_position = rule.line_ref_to_display_position.clone();
+            }
+            state.active.push(rule.name.clone());
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn rule(days: &[Weekday], from: Option<&str>, to: Option<&str>) -> ScheduleRule {
+        ScheduleRule {
+            name: "test".to_string(),
+            days: days.iter().copied().collect(),
+            from: from.and_then(parse_time),
+            to: to.and_then(parse_time),
+            brightness: None,
+            blank: Some(true),
+            lines: None,
+            theme: None,
+            line_ref_to_display_position: None,
+        }
+    }
+
+    // 2026-10-16 is a Friday
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, day, hour, minute, 0).single().unwrap()
+    }
+
+    #[test]
+    fn overnight_range_runs_past_midnight() {
+        let night = rule(&[], Some("22:00"), Some("06:00"));
+        for (now, active) in [
+            (at(16, 21, 59), false),
+            (at(16, 22, 0), true),
+            (at(16, 23, 59), true),
+            (at(17, 0, 0), true),
+            (at(17, 5, 59), true),
+            (at(17, 6, 0), false),
+            (at(17, 12, 0), false),
+        ] {
+            assert_eq!(night.is_active(now), active, "at {}", now);
+        }
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        let from_only = rule(&[], Some("18:00"), None);
+        let to_only = rule(&[], None, Some("09:00"));
+        let all_day = rule(&[], None, None);
+        for (now, from_active, to_active) in [
+            (at(16, 0, 0), false, true),
+            (at(16, 8, 59), false, true),
+            (at(16, 9, 0), false, false),
+            (at(16, 17, 59), false, false),
+            (at(16, 18, 0), true, false),
+            (at(16, 23, 59), true, false),
+        ] {
+            assert_eq!(from_only.is_active(now), from_active, "from 18:00 at {}", now);
+            assert_eq!(to_only.is_active(now), to_active, "to 09:00 at {}", now);
+            assert!(all_day.is_active(now));
+        }
+    }
+
+    #[test]
+    fn overnight_range_belongs_to_the_day_it_starts() {
+        let friday_night = rule(&[Weekday::Fri], Some("22:00"), Some("06:00"));
+        for (now, active) in [
+            // Thursday night running into Friday morning
+            (at(16, 5, 59), false),
+            (at(16, 23, 0), true),
+            // Saturday morning is still Friday night
+            (at(17, 5, 59), true),
+            (at(17, 6, 0), false),
+            (at(17, 23, 0), false),
+        ] {
+            assert_eq!(friday_night.is_active(now), active, "at {}", now);
+        }
+    }
+
+    #[test]
+    fn day_only_rules_follow_the_calendar_day() {
+        let weekends = rule(&parse_days("weekends").unwrap(), None, None);
+        assert!(!weekends.is_active(at(16, 23, 59)));
+        assert!(weekends.is_active(at(17, 0, 0)));
+        assert!(weekends.is_active(at(18, 23, 59)));
+        assert!(!weekends.is_active(at(19, 0, 0)));
+    }
+
+    #[test]
+    fn later_rules_win() {
+        let mut dim = rule(&[], Some("20:00"), Some("07:00"));
+        dim.blank = None;
+        dim.brightness = Some(30);
+        let mut off = rule(&[], Some("23:00"), Some("06:00"));
+        off.name = "off".to_string();
+        let schedule = Schedule { rules: vec![dim, off] };
+        let state = schedule.state_at(at(16, 23, 30));
+        assert_eq!(state.brightness, 30);
+        assert!(state.blank);
+        assert_eq!(state.active, vec!["test", "off"]);
+        assert!(!schedule.state_at(at(17, 6, 30)).blank);
+        assert_eq!(schedule.state_at(at(17, 12, 0)), ScheduleState::default());
+    }
+}
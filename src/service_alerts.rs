@@ -0,0 +1,163 @@
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::metrics::metrics;
+use crate::stop_monitor::ApiError;
+
+// A window an alert applies to, either end can be open
+#[derive(Debug, Clone)]
+pub struct ActivePeriod {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+impl ActivePeriod {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| start <= now) && self.end.is_none_or(|end| now < end)
+    }
+}
+
+// A GTFS-RT alert, trimmed down to what the board needs
+#[derive(Debug, Clone)]
+pub struct ServiceAlert {
+    pub id: String,
+    pub header_text: String,
+    // lines (route ids) and stops the alert is about
+    pub lines: Vec<String>,
+    pub stops: Vec<String>,
+    active_periods: Vec<ActivePeriod>,
+}
+
+impl ServiceAlert {
+    // Alerts without any active period are in effect until removed from the feed
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.active_periods.is_empty() || self.active_periods.iter().any(|p| p.contains(now))
+    }
+
+    pub fn affects_line(&self, line: &str) -> bool {
+        self.lines.iter().any(|l| l == line)
+    }
+
+    pub fn affects_any_stop(&self, stops: &[String]) -> bool {
+        self.stops.iter().any(|s| stops.contains(s))
+    }
+
+    // Also flag the parent line of any rapid line the alert mentions, since
+    // that's the row the rapid line is shown on
+    pub fn add_parent_lines(&mut self, rapid_line_to_parent_map: &HashMap<String, String>) {
+        let parents: Vec<String> = self
+            .lines
+            .iter()
+            .filter_map(|line| rapid_line_to_parent_map.get(line))
+            .filter(|parent| !self.lines.contains(parent))
+            .cloned()
+            .collect();
+        self.lines.extend(parents);
+    }
+}
+
+pub async fn get_service_alerts(
+    client: &Client,
+    agency: &str,
+    api_key: &str,
+) -> Result<Vec<ServiceAlert>, ApiError> {
+    let url: String = "https://api.511.org/transit/servicealerts?api_key=".to_owned()
+        + api_key
+        + "&agency="
+        + agency
+        + "&format=json";
+    let _timer = metrics().api_request("service_alerts");
+    let response = client.get(url).send().await?;
+
+    let response_body = response.error_for_status()?.text().await?;
+    // a non-JSON body (an error page, say) fails the fetch so the last alerts stay up
+    let data: Value = serde_json::from_str(response_body.as_str())?;
+    Ok(extract_service_alerts(&data))
+}
+
+// 511 serves GTFS-RT as PascalCase JSON ("Entities", "ActivePeriods"), other
+// feeds use the protobuf field names ("entity", "active_period")
+fn field<'a>(value: &'a Value, names: &[&str]) -> &'a Value {
+    names
+        .iter()
+        .map(|name| &value[*name])
+        .find(|v| !v.is_null())
+        .unwrap_or(&Value::Null)
+}
+
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let seconds = match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }?;
+    // 0 means the period is open on that end
+    if seconds == 0 {
+        return None;
+    }
+    Utc.timestamp_opt(seconds, 0).single()
+}
+
+fn translated_text(value: &Value) -> Option<String> {
+    let translations = field(value, &["Translations", "translation"]).as_array()?;
+    let translation = translations
+        .iter()
+        .find(|t| {
+            matches!(
+                field(t, &["Language", "language"]).as_str(),
+                None | Some("en") | Some("")
+            )
+        })
+        .or_else(|| translations.first())?;
+    field(translation, &["Text", "text"])
+        .as_str()
+        .map(|t| t.trim().to_string())
+}
+
+pub fn extract_service_alerts(data: &Value) -> Vec<ServiceAlert> {
+    let mut alerts = Vec::new();
+    let entities = match field(data, &["Entities", "entity"]).as_array() {
+        Some(entities) => entities,
+        None => return alerts,
+    };
+    for entity in entities {
+        let alert = field(entity, &["Alert", "alert"]);
+        let header_text = match translated_text(field(alert, &["HeaderText", "header_text"])) {
+            Some(text) if !text.is_empty() => text,
+            _ => continue,
+        };
+        let mut lines = Vec::new();
+        let mut stops = Vec::new();
+        for informed in field(alert, &["InformedEntities", "informed_entity"])
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if let Some(route) = field(informed, &["RouteId", "route_id"]).as_str() {
+                lines.push(route.to_string());
+            }
+            if let Some(stop) = field(informed, &["StopId", "stop_id"]).as_str() {
+                stops.push(stop.to_string());
+            }
+        }
+        let active_periods = field(alert, &["ActivePeriods", "active_period"])
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|period| ActivePeriod {
+                start: timestamp(field(period, &["Start", "start"])),
+                end: timestamp(field(period, &["End", "end"])),
+            })
+            .collect();
+        alerts.push(ServiceAlert {
+            id: field(entity, &["Id", "id"]).as_str().unwrap_or("").to_string(),
+            header_text,
+            lines,
+            stops,
+            active_periods,
+        });
+    }
+    alerts
+}
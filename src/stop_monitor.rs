@@ -2,18 +2,63 @@ use chrono;
 use chrono::DateTime;
 use reqwest;
 use reqwest::Client;
+use log::{debug, warn};
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::env;
+use std::fmt;
+
+use crate::metrics::{Metrics, metrics};
+
+// A 511 request that failed, or came back with something other than JSON
+#[derive(Debug)]
+pub enum ApiError {
+    Request(reqwest::Error),
+    Json(serde_json::Error),
+}
+
+impl ApiError {
+    // The request url has the api key in it
+    pub fn without_url(self) -> Self {
+        match self {
+            ApiError::Request(e) => ApiError::Request(e.without_url()),
+            json => json,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::Request(e) => write!(f, "{}", e),
+            ApiError::Json(e) => write!(f, "response isn't JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        ApiError::Request(e)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> Self {
+        ApiError::Json(e)
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub struct MonitoredVehicleJourney {
     pub line_ref: String,
+    direction_ref: String,
     line_name: String,
     origin_name: String,
     destination_name: String,
     vehicle_location: Location,
+    occupancy: Occupancy,
     monitored_call: MonitoredCall,
 }
 
@@ -26,11 +71,86 @@ impl MonitoredVehicleJourney {
             Ok(value) => Some(value.signed_duration_since(now).num_minutes()),
             Err(_) => None,
         };
-        return output;
+        output
+    }
+
+    // Move the expected arrival, used to replay recorded arrivals as if they
+    // were live
+    pub fn shift_arrival(&mut self, by: chrono::Duration) {
+        if let Ok(arrival_time) =
+            DateTime::parse_from_rfc3339(&self.monitored_call.expected_arrival_time)
+        {
+            self.monitored_call.expected_arrival_time = (arrival_time + by).to_rfc3339();
+        }
     }
 
     pub fn has_location(&self) -> bool {
-        return !self.vehicle_location.is_empty();
+        !self.vehicle_location.is_empty()
+    }
+
+    pub fn direction_ref(&self) -> &str {
+        &self.direction_ref
+    }
+
+    pub fn destination_name(&self) -> &str {
+        &self.destination_name
+    }
+
+    // Headsign as shown on the vehicle, often shorter than destination_name
+    pub fn destination_display(&self) -> &str {
+        &self.monitored_call.destination_display
+    }
+
+    pub fn stop_name(&self) -> &str {
+        &self.monitored_call.stop_name
+    }
+
+    pub fn stop_point_ref(&self) -> &str {
+        &self.monitored_call.stop_point_ref
+    }
+
+    pub fn expected_arrival_time(&self) -> Option<DateTime<chrono::FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.monitored_call.expected_arrival_time).ok()
+    }
+
+    pub fn occupancy(&self) -> Occupancy {
+        self.occupancy
+    }
+}
+
+// How full the vehicle is, collapsed to the levels the board can show
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occupancy {
+    Unknown,
+    SeatsAvailable,
+    StandingAvailable,
+    Full,
+}
+
+impl Occupancy {
+    // Accepts SIRI Occupancy (seatsAvailable, standingAvailable, full) and
+    // GTFS-RT OccupancyStatus (MANY_SEATS_AVAILABLE, CRUSHED_STANDING_ROOM_ONLY, ...)
+    pub fn parse(value: &str) -> Self {
+        match value.to_ascii_lowercase().replace('_', "").as_str() {
+            "seatsavailable" | "empty" | "manyseatsavailable" | "fewseatsavailable" => {
+                Occupancy::SeatsAvailable
+            }
+            "standingavailable" | "standingroomonly" | "crushedstandingroomonly" => {
+                Occupancy::StandingAvailable
+            }
+            "full" | "notacceptingpassengers" => Occupancy::Full,
+            _ => Occupancy::Unknown,
+        }
+    }
+
+    // 0 when unknown, up to 3 when full
+    pub fn level(&self) -> i32 {
+        match self {
+            Occupancy::Unknown => 0,
+            Occupancy::SeatsAvailable => 1,
+            Occupancy::StandingAvailable => 2,
+            Occupancy::Full => 3,
+        }
     }
 }
 
@@ -42,7 +162,7 @@ pub struct Location {
 
 impl Location {
     pub fn is_empty(&self) -> bool {
-        return self.longitude.is_empty() || self.latitude.is_empty();
+        self.longitude.is_empty() || self.latitude.is_empty()
     }
 }
 
@@ -57,6 +177,7 @@ pub struct MonitoredCall {
 #[derive(Deserialize, Debug, Hash, PartialEq, Eq)]
 pub struct LineStop {
     pub line_ref: String,
+    pub direction_ref: String,
     line_name: String,
     origin_name: String,
     pub destination_name: String,
@@ -65,93 +186,179 @@ pub struct LineStop {
 
 impl LineStop {
     pub fn screen_display(&self) -> String {
-        return self.line_ref.clone() + " - " + &self.destination_name;
+        self.line_ref.clone() + " - " + &self.destination_name
+    }
+
+    // Direction used to split a line into rows, falls back to the destination
+    // for feeds that don't publish a DirectionRef
+    pub fn direction(&self) -> String {
+        if self.direction_ref.is_empty() {
+            self.destination_name.clone()
+        } else {
+            self.direction_ref.clone()
+        }
+    }
+}
+
+// A single row on the board: a (parent) line heading in one direction
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DisplayRow {
+    pub line: String,
+    pub direction: String,
+}
+
+impl DisplayRow {
+    pub fn new(line: &str, direction: &str) -> Self {
+        DisplayRow {
+            line: line.to_string(),
+            direction: direction.to_string(),
+        }
+    }
+
+    // Keys tried, in order, when looking up a row's position on the board
+    pub fn position_keys(&self) -> [String; 2] {
+        [format!("{}:{}", self.line, self.direction), self.line.clone()]
     }
 }
 
 pub async fn get_stops(
     client: &Client,
     stops_to_monitor: &Vec<String>,
-) -> Result<HashMap<LineStop, Vec<MonitoredVehicleJourney>>, reqwest::Error> {
-    let mut hm: HashMap<LineStop, Vec<MonitoredVehicleJourney>> = HashMap::new();
+    agency: &str,
+    api_key: &str,
+) -> Result<HashMap<LineStop, Vec<MonitoredVehicleJourney>>, ApiError> {
+    let responses = get_stop_monitor_responses(client, stops_to_monitor, agency, api_key).await?;
+    let monitored_vehicle_journeys = responses
+        .into_iter()
+        .flat_map(|(_, stop_monitor_data)| extract_monitored_vehicle_journeys(stop_monitor_data))
+        .collect();
+    Ok(group_by_line_stop(monitored_vehicle_journeys))
+}
+
+// Raw StopMonitoring response for each stop, keyed by stop code
+pub async fn get_stop_monitor_responses(
+    client: &Client,
+    stops_to_monitor: &Vec<String>,
+    agency: &str,
+    api_key: &str,
+) -> Result<Vec<(String, Value)>, ApiError> {
+    let mut responses = Vec::new();
     for stop_id in stops_to_monitor {
-        let stop_monitor_data: Value = get_stop_monitor_request(client, stop_id.as_str()).await?;
-        let monitored_vehicle_journeys = extract_monitored_vehicle_journeys(stop_monitor_data);
-        for mvj in monitored_vehicle_journeys {
-            let line = LineStop {
-                line_ref: mvj.line_ref.clone(),
-                line_name: mvj.line_name.clone(),
-                origin_name: mvj.origin_name.clone(),
-                destination_name: mvj.destination_name.clone(),
-                stop_name: mvj.monitored_call.stop_name.clone(),
+        metrics().fetch_attempts.with_label_values(&[stop_id]).inc();
+        let stop_monitor_data: Value =
+            match get_stop_monitor_request(client, stop_id.as_str(), agency, api_key).await {
+                Ok(stop_monitor_data) => stop_monitor_data,
+                Err(e) => {
+                    metrics()
+                        .fetch_failures
+                        .with_label_values(&[stop_id, &Metrics::error_kind(&e)])
+                        .inc();
+                    return Err(e);
+                }
             };
-            if let Some(x) = hm.get_mut(&line) {
-                x.push(mvj);
-            } else {
-                hm.insert(line, vec![mvj]);
-            }
+        metrics().fetch_successes.with_label_values(&[stop_id]).inc();
+        responses.push((stop_id.clone(), stop_monitor_data));
+    }
+    Ok(responses)
+}
+
+pub fn group_by_line_stop(
+    monitored_vehicle_journeys: Vec<MonitoredVehicleJourney>,
+) -> HashMap<LineStop, Vec<MonitoredVehicleJourney>> {
+    let mut hm: HashMap<LineStop, Vec<MonitoredVehicleJourney>> = HashMap::new();
+    for mvj in monitored_vehicle_journeys {
+        let line = LineStop {
+            line_ref: mvj.line_ref.clone(),
+            direction_ref: mvj.direction_ref.clone(),
+            line_name: mvj.line_name.clone(),
+            origin_name: mvj.origin_name.clone(),
+            destination_name: mvj.destination_name.clone(),
+            stop_name: mvj.monitored_call.stop_name.clone(),
+        };
+        if let Some(x) = hm.get_mut(&line) {
+            x.push(mvj);
+        } else {
+            hm.insert(line, vec![mvj]);
         }
     }
-    return Ok(hm);
+    hm
 }
 
-fn extract_monitored_vehicle_journeys(stop_monitor_data: Value) -> Vec<MonitoredVehicleJourney> {
+pub fn extract_monitored_vehicle_journeys(stop_monitor_data: Value) -> Vec<MonitoredVehicleJourney> {
     let mut monitored_vehicle_journeys: Vec<MonitoredVehicleJourney> = Vec::new();
-    for elem in stop_monitor_data["ServiceDelivery"]["StopMonitoringDelivery"]["MonitoredStopVisit"]
+    let visits = match stop_monitor_data["ServiceDelivery"]["StopMonitoringDelivery"]["MonitoredStopVisit"]
         .as_array()
-        .unwrap()
     {
-        let mvj = elem["MonitoredVehicleJourney"].as_object().unwrap();
-        let expected_arrival =
-            mvj["MonitoredCall"].as_object().unwrap()["ExpectedArrivalTime"].as_str();
-
-        if let Some(ea) = expected_arrival {
-            monitored_vehicle_journeys.push(MonitoredVehicleJourney {
-                line_ref: mvj["LineRef"].as_str().unwrap().to_string(),
-                line_name: mvj["PublishedLineName"].as_str().unwrap().to_string(),
-                origin_name: mvj["OriginName"].as_str().unwrap().to_string(),
-                destination_name: mvj["DestinationName"].as_str().unwrap().to_string(),
-                vehicle_location: Location {
-                    latitude: mvj["VehicleLocation"].as_object().unwrap()["Latitude"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                    longitude: mvj["VehicleLocation"].as_object().unwrap()["Longitude"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                },
-                monitored_call: MonitoredCall {
-                    stop_point_ref: mvj["MonitoredCall"].as_object().unwrap()["StopPointRef"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                    destination_display:
-                        mvj["MonitoredCall"].as_object().unwrap()["DestinationDisplay"]
-                            .as_str()
-                            .unwrap()
-                            .to_string(),
-                    expected_arrival_time: ea.to_string(),
-                    stop_name: mvj["MonitoredCall"].as_object().unwrap()["StopPointName"]
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                },
-            });
+        Some(visits) => visits,
+        None => {
+            warn!("StopMonitoring response has no MonitoredStopVisit list");
+            return monitored_vehicle_journeys;
+        }
+    };
+    for elem in visits {
+        let mvj = &elem["MonitoredVehicleJourney"];
+        // visits without a prediction aren't shown
+        let expected_arrival = match mvj["MonitoredCall"]["ExpectedArrivalTime"].as_str() {
+            Some(ea) => ea,
+            None => continue,
+        };
+        match parse_visit(mvj, expected_arrival) {
+            Some(mvj) => monitored_vehicle_journeys.push(mvj),
+            None => {
+                // one bad visit shouldn't cost the rest of the board
+                debug!("Skipping malformed stop visit: {}", elem);
+                metrics().malformed_visits_skipped.inc();
+            }
         }
     }
-    return monitored_vehicle_journeys;
+    metrics().arrivals_parsed.inc_by(monitored_vehicle_journeys.len() as u64);
+    monitored_vehicle_journeys
+}
+
+// None when a field the board needs is missing
+fn parse_visit(mvj: &Value, expected_arrival: &str) -> Option<MonitoredVehicleJourney> {
+    let string = |value: &Value| value.as_str().map(|s| s.to_string());
+    let monitored_call = &mvj["MonitoredCall"];
+    Some(MonitoredVehicleJourney {
+        line_ref: string(&mvj["LineRef"])?,
+        direction_ref: string(&mvj["DirectionRef"]).unwrap_or_default(),
+        line_name: string(&mvj["PublishedLineName"])?,
+        origin_name: string(&mvj["OriginName"])?,
+        destination_name: string(&mvj["DestinationName"])?,
+        vehicle_location: Location {
+            latitude: string(&mvj["VehicleLocation"]["Latitude"])?,
+            longitude: string(&mvj["VehicleLocation"]["Longitude"])?,
+        },
+        // feeds built from GTFS-RT carry OccupancyStatus instead
+        occupancy: Occupancy::parse(
+            mvj["Occupancy"].as_str().or_else(|| mvj["OccupancyStatus"].as_str()).unwrap_or(""),
+        ),
+        monitored_call: MonitoredCall {
+            stop_point_ref: string(&monitored_call["StopPointRef"])?,
+            destination_display: string(&monitored_call["DestinationDisplay"])?,
+            expected_arrival_time: expected_arrival.to_string(),
+            stop_name: string(&monitored_call["StopPointName"])?,
+        },
+    })
 }
 
-async fn get_stop_monitor_request(client: &Client, stop_id: &str) -> Result<Value, reqwest::Error> {
-    let token = env::var("TRANSIT_TOKEN").unwrap();
+async fn get_stop_monitor_request(
+    client: &Client,
+    stop_id: &str,
+    agency: &str,
+    api_key: &str,
+) -> Result<Value, ApiError> {
     let url: String = "https://api.511.org/transit/StopMonitoring?api_key=".to_owned()
-        + &token
-        + "&agency=SF&stopCode="
+        + api_key
+        + "&agency="
+        + agency
+        + "&stopCode="
         + stop_id;
+    let _timer = metrics().api_request("stop_monitoring");
     let response = client.get(url).send().await?;
 
     let response_body = response.error_for_status()?.text().await?;
-    let data: Value = serde_json::from_str(response_body.as_str()).unwrap();
-    return Ok(data);
+    // an error page instead of JSON counts as a failed fetch like any other
+    let data: Value = serde_json::from_str(response_body.as_str())?;
+    Ok(data)
 }
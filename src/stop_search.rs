@@ -0,0 +1,271 @@
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::Path;
+
+use crate::metrics::metrics;
+use crate::stop_monitor::{ApiError, extract_monitored_vehicle_journeys, get_stop_monitor_responses};
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// A stop from the agency's list of stops or a GTFS feed
+#[derive(Debug, Clone)]
+pub struct Stop {
+    // stop code used in stops_to_monitor
+    pub code: String,
+    pub name: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // directions (DirectionRef or trip headsign) and lines serving the stop,
+    // empty until looked up
+    pub directions: BTreeSet<String>,
+    pub lines: BTreeSet<String>,
+}
+
+pub async fn get_agency_stops(
+    client: &Client,
+    agency: &str,
+    api_key: &str,
+) -> Result<Vec<Stop>, ApiError> {
+    let url: String = "https://api.511.org/transit/stops?api_key=".to_owned()
+        + api_key
+        + "&operator_id="
+        + agency
+        + "&format=json";
+    let _timer = metrics().api_request("stops");
+    let response = client.get(url).send().await?;
+
+    let response_body = response.error_for_status()?.text().await?;
+    let data: Value = serde_json::from_str(response_body.as_str())?;
+    Ok(extract_stops(&data))
+}
+
+fn coordinate(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn extract_stops(data: &Value) -> Vec<Stop> {
+    data["Contents"]["dataObjects"]["ScheduledStopPoint"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|stop| {
+            Some(Stop {
+                code: stop["id"].as_str()?.to_string(),
+                name: stop["Name"].as_str().unwrap_or("").to_string(),
+                latitude: coordinate(&stop["Location"]["Latitude"]),
+                longitude: coordinate(&stop["Location"]["Longitude"]),
+                directions: BTreeSet::new(),
+                lines: BTreeSet::new(),
+            })
+        })
+        .collect()
+}
+
+// Fill in the lines and directions serving a stop from its live arrivals.
+// Costs one request, so only worth doing for a handful of stops.
+pub async fn add_live_lines(
+    client: &Client,
+    stop: &mut Stop,
+    agency: &str,
+    api_key: &str,
+) -> Result<(), ApiError> {
+    let responses =
+        get_stop_monitor_responses(client, &vec![stop.code.clone()], agency, api_key).await?;
+    for (_, stop_monitor_data) in responses {
+        for mvj in extract_monitored_vehicle_journeys(stop_monitor_data) {
+            if !mvj.direction_ref().is_empty() {
+                stop.directions.insert(mvj.direction_ref().to_string());
+            }
+            stop.lines.insert(mvj.line_ref.clone());
+        }
+    }
+    Ok(())
+}
+
+fn gtfs_reader(path: &Path) -> io::Result<csv::Reader<std::fs::File>> {
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| io::Error::other(format!("{}: {}", path.display(), e)))
+}
+
+// Index of each named column, GTFS files can order (and omit) columns freely
+fn gtfs_columns<const N: usize>(
+    reader: &mut csv::Reader<std::fs::File>,
+    names: [&str; N],
+) -> io::Result<[Option<usize>; N]> {
+    let headers = reader.headers().map_err(io::Error::other)?.clone();
+    // some feeds start with a byte order mark
+    let position = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.trim_start_matches('\u{feff}').trim() == name)
+    };
+    Ok(names.map(position))
+}
+
+fn field(record: &csv::StringRecord, column: Option<usize>) -> &str {
+    column.and_then(|c| record.get(c)).unwrap_or("").trim()
+}
+
+// Stops from a GTFS feed, either stops.txt itself or the feed directory.
+// Given the directory, lines and headsigns come from routes.txt, trips.txt
+// and stop_times.txt when they're there.
+pub fn load_gtfs_stops(path: &Path) -> io::Result<Vec<Stop>> {
+    let (stops_path, feed_dir) = if path.is_dir() {
+        (path.join("stops.txt"), Some(path))
+    } else {
+        (path.to_path_buf(), None)
+    };
+
+    let mut reader = gtfs_reader(&stops_path)?;
+    let [id, code, name, lat, lon, location_type] = gtfs_columns(
+        &mut reader,
+        ["stop_id", "stop_code", "stop_name", "stop_lat", "stop_lon", "location_type"],
+    )?;
+    let mut stops = Vec::new();
+    let mut stop_ids = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(io::Error::other)?;
+        // stations and entrances can't be monitored, only their platforms/stops
+        if !matches!(field(&record, location_type), "" | "0") {
+            continue;
+        }
+        let stop_code = match field(&record, code) {
+            "" => field(&record, id),
+            stop_code => stop_code,
+        };
+        stop_ids.insert(field(&record, id).to_string(), stops.len());
+        stops.push(Stop {
+            code: stop_code.to_string(),
+            name: field(&record, name).to_string(),
+            latitude: field(&record, lat).parse().ok(),
+            longitude: field(&record, lon).parse().ok(),
+            directions: BTreeSet::new(),
+            lines: BTreeSet::new(),
+        });
+    }
+
+    if let Some(dir) = feed_dir {
+        if ["routes.txt", "trips.txt", "stop_times.txt"]
+            .iter()
+            .all(|f| dir.join(f).exists())
+        {
+            add_gtfs_lines(dir, &mut stops, &stop_ids)?;
+        }
+    }
+    Ok(stops)
+}
+
+fn add_gtfs_lines(dir: &Path, stops: &mut [Stop], stop_ids: &HashMap<String, usize>) -> io::Result<()> {
+    let mut reader = gtfs_reader(&dir.join("routes.txt"))?;
+    let [route_id, short_name, long_name] =
+        gtfs_columns(&mut reader, ["route_id", "route_short_name", "route_long_name"])?;
+    let mut routes = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(io::Error::other)?;
+        let line = match field(&record, short_name) {
+            "" => field(&record, long_name),
+            line => line,
+        };
+        routes.insert(field(&record, route_id).to_string(), line.to_string());
+    }
+
+    let mut reader = gtfs_reader(&dir.join("trips.txt"))?;
+    let [trip_id, trip_route, headsign] =
+        gtfs_columns(&mut reader, ["trip_id", "route_id", "trip_headsign"])?;
+    let mut trips = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(io::Error::other)?;
+        let line = routes
+            .get(field(&record, trip_route))
+            .cloned()
+            .unwrap_or_else(|| field(&record, trip_route).to_string());
+        trips.insert(
+            field(&record, trip_id).to_string(),
+            (line, field(&record, headsign).to_string()),
+        );
+    }
+
+    let mut reader = gtfs_reader(&dir.join("stop_times.txt"))?;
+    let [time_trip, time_stop] = gtfs_columns(&mut reader, ["trip_id", "stop_id"])?;
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record).map_err(io::Error::other)? {
+        let (stop, trip) = match (
+            stop_ids.get(field(&record, time_stop)),
+            trips.get(field(&record, time_trip)),
+        ) {
+            (Some(stop), Some(trip)) => (*stop, trip),
+            _ => continue,
+        };
+        let (line, headsign) = trip;
+        stops[stop].lines.insert(line.clone());
+        if !headsign.is_empty() {
+            stops[stop].directions.insert(headsign.clone());
+        }
+    }
+    Ok(())
+}
+
+// Stops whose name contains every word of the query, ignoring case and
+// punctuation, so "church 24th" finds "24th St & Church St"
+pub fn search_by_name<'a>(stops: &'a [Stop], query: &str) -> Vec<&'a Stop> {
+    let normalize = |s: &str| -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect()
+    };
+    let query = normalize(query);
+    stops
+        .iter()
+        .filter(|stop| {
+            let name = normalize(&stop.name);
+            query.iter().all(|q| name.iter().any(|w| w.starts_with(q.as_str())))
+        })
+        .collect()
+}
+
+// Great circle distance in meters
+pub fn distance_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+// Stops within radius_m of a point, closest first, with their distance
+pub fn search_near(stops: &[Stop], latitude: f64, longitude: f64, radius_m: f64) -> Vec<(&Stop, f64)> {
+    let mut found: Vec<(&Stop, f64)> = stops
+        .iter()
+        .filter_map(|stop| {
+            let distance = distance_m(latitude, longitude, stop.latitude?, stop.longitude?);
+            (distance <= radius_m).then_some((stop, distance))
+        })
+        .collect();
+    found.sort_by(|a, b| a.1.total_cmp(&b.1));
+    found
+}
+
+// Ready to paste into the config, with each stop's name as a comment
+pub fn stops_to_monitor_yaml(stops: &[&Stop]) -> String {
+    let mut yaml = String::from("stops_to_monitor:\n");
+    for stop in stops {
+        let mut comment = stop.name.clone();
+        if !stop.lines.is_empty() {
+            comment.push_str(&format!(
+                " ({})",
+                stop.lines.iter().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        yaml.push_str(&format!("  - \"{}\" # {}\n", stop.code, comment));
+    }
+    yaml
+}
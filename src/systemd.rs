@@ -0,0 +1,74 @@
+use log::{debug, info, warn};
+use sd_notify::NotifyState;
+use std::time::{Duration, Instant};
+
+// Keeps systemd up to date on the board: ready once the first frame is on the
+// matrix, a status line for `systemctl status`, and watchdog pings from the
+// render loop so a board stuck in a sleep or waiting on the matrix gets
+// restarted. Does nothing unless started by a Type=notify unit.
+pub struct Systemd {
+    // how often to ping, half of WatchdogSec, None without a watchdog
+    watchdog: Option<Duration>,
+    last_ping: Option<Instant>,
+    ready: bool,
+    status: String,
+}
+
+impl Systemd {
+    pub fn new() -> Self {
+        let mut usec = 0;
+        let watchdog = sd_notify::watchdog_enabled(false, &mut usec)
+            .then(|| Duration::from_micros(usec) / 2);
+        if let Some(interval) = watchdog {
+            info!("systemd watchdog enabled, pinging every {:?}", interval);
+        }
+        Systemd {
+            watchdog,
+            last_ping: None,
+            ready: false,
+            status: String::new(),
+        }
+    }
+
+    fn notify(&self, state: &[NotifyState]) {
+        if let Err(e) = sd_notify::notify(false, state) {
+            warn!("Unable to notify systemd: {}", e);
+        }
+    }
+
+    // Call after each frame is drawn. The first one tells systemd the board
+    // is up, after that they keep the watchdog fed.
+    pub fn frame_drawn(&mut self, now: Instant) {
+        if !self.ready {
+            debug!("Telling systemd the board is ready");
+            self.notify(&[NotifyState::Ready]);
+            self.ready = true;
+        }
+        let interval = match self.watchdog {
+            Some(interval) => interval,
+            None => return,
+        };
+        if self.last_ping.is_none_or(|last| now.duration_since(last) >= interval) {
+            self.notify(&[NotifyState::Watchdog]);
+            self.last_ping = Some(now);
+        }
+    }
+
+    // Shown by `systemctl status`, only sent when it changes
+    pub fn set_status(&mut self, status: &str) {
+        if status != self.status {
+            self.status = status.to_string();
+            self.notify(&[NotifyState::Status(status)]);
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.notify(&[NotifyState::Stopping]);
+    }
+}
+
+impl Default for Systemd {
+    fn default() -> Self {
+        Systemd::new()
+    }
+}
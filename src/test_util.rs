@@ -0,0 +1,28 @@
+// Helpers shared by the unit tests
+
+use std::fs;
+use std::path::PathBuf;
+
+// A fresh directory under the system temp dir, removed with everything in it
+// when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    // `name` keeps tests running at the same time out of each other's files
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("transit_rust_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn join(&self, file: &str) -> PathBuf {
+        self.0.join(file)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,269 @@
+use rpi_led_matrix::LedColor;
+use std::collections::HashMap;
+
+// Colors for every part of the board. Themes come from the presets below or the
+// `themes` section of the config, which starts from one of them.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    // "Now" clock in the header
+    pub clock: LedColor,
+    // "As of" update time in the header
+    pub updated: LedColor,
+    pub line_name: LedColor,
+    // time to arrival, and for stops with a walk time the arrivals there's no
+    // longer time to walk to and the ones to leave for now
+    pub tta: LedColor,
+    pub tta_missed: LedColor,
+    pub tta_leave_now: LedColor,
+    // test patterns and anything without a color of its own
+    pub text: LedColor,
+    // dot next to a time when the vehicle has no location
+    pub no_location: LedColor,
+    pub occupancy_seats: LedColor,
+    pub occupancy_standing: LedColor,
+    pub occupancy_full: LedColor,
+    pub destination: LedColor,
+    pub alert: LedColor,
+    pub status_error: LedColor,
+    pub page_indicator: LedColor,
+    pub page_indicator_dim: LedColor,
+    // weather panel's temperature and chance of rain, and the parts of its icon
+    pub weather: LedColor,
+    pub weather_sun: LedColor,
+    pub weather_cloud: LedColor,
+    pub weather_rain: LedColor,
+    // line name color for particular lines, e.g. Muni Metro's line colors
+    pub line_colors: HashMap<String, LedColor>,
+}
+
+// Keys of the `colors` section of a theme
+pub const COLOR_KEYS: [&str; 20] = [
+    "clock",
+    "updated",
+    "line_name",
+    "tta",
+    "tta_missed",
+    "tta_leave_now",
+    "text",
+    "no_location",
+    "occupancy_seats",
+    "occupancy_standing",
+    "occupancy_full",
+    "destination",
+    "alert",
+    "status_error",
+    "page_indicator",
+    "page_indicator_dim",
+    "weather",
+    "weather_sun",
+    "weather_cloud",
+    "weather_rain",
+];
+
+pub const DEFAULT_THEME: &str = "default";
+pub const PRESETS: [&str; 4] = [DEFAULT_THEME, "high_contrast", "muni", "night_red"];
+
+const fn rgb(red: u8, green: u8, blue: u8) -> LedColor {
+    LedColor { red, green, blue }
+}
+
+const NAMED_COLORS: [(&str, LedColor); 16] = [
+    ("black", rgb(0, 0, 0)),
+    ("white", rgb(255, 255, 255)),
+    ("gray", rgb(128, 128, 128)),
+    ("grey", rgb(128, 128, 128)),
+    ("red", rgb(255, 0, 0)),
+    ("green", rgb(0, 255, 0)),
+    ("blue", rgb(0, 0, 255)),
+    ("yellow", rgb(255, 255, 0)),
+    ("cyan", rgb(0, 255, 255)),
+    ("magenta", rgb(255, 0, 255)),
+    ("orange", rgb(255, 140, 0)),
+    ("amber", rgb(255, 191, 0)),
+    ("purple", rgb(128, 0, 255)),
+    ("pink", rgb(255, 105, 180)),
+    ("teal", rgb(0, 200, 120)),
+    ("navy", rgb(0, 0, 150)),
+];
+
+// "#ff8800", "ff8800", "#f80" or a name like "orange"
+pub fn parse_color(color: &str) -> Option<LedColor> {
+    let color = color.trim().to_lowercase();
+    if let Some((_, named)) = NAMED_COLORS.iter().find(|(name, _)| *name == color) {
+        return Some(*named);
+    }
+    let hex = color.strip_prefix('#').unwrap_or(&color);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize, width: usize| {
+        let value = u8::from_str_radix(&hex[i * width..(i + 1) * width], 16).ok()?;
+        // #f80 is short for #ff8800
+        Some(if width == 1 { value * 17 } else { value })
+    };
+    let width = match hex.len() {
+        3 => 1,
+        6 => 2,
+        _ => return None,
+    };
+    Some(rgb(channel(0, width)?, channel(1, width)?, channel(2, width)?))
+}
+
+impl Theme {
+    // Built in theme by name
+    pub fn preset(name: &str) -> Option<Theme> {
+        let theme = match name {
+            DEFAULT_THEME => Theme::default_colors(),
+            // brightest colors that are easy to tell apart, for sunny windows
+            "high_contrast" => Theme {
+                clock: rgb(255, 255, 255),
+                updated: rgb(255, 255, 255),
+                line_name: rgb(255, 255, 0),
+                tta: rgb(255, 255, 255),
+                tta_missed: rgb(255, 0, 0),
+                tta_leave_now: rgb(0, 255, 0),
+                text: rgb(255, 255, 255),
+                no_location: rgb(255, 0, 0),
+                occupancy_seats: rgb(0, 255, 0),
+                occupancy_standing: rgb(255, 255, 0),
+                occupancy_full: rgb(255, 0, 0),
+                destination: rgb(0, 255, 255),
+                alert: rgb(255, 0, 255),
+                status_error: rgb(255, 0, 0),
+                page_indicator: rgb(255, 255, 255),
+                page_indicator_dim: rgb(60, 60, 60),
+                weather: rgb(255, 255, 255),
+                weather_sun: rgb(255, 255, 0),
+                weather_cloud: rgb(255, 255, 255),
+                weather_rain: rgb(0, 255, 255),
+                line_colors: HashMap::new(),
+            },
+            // amber like the signs at Muni stops, Metro lines in their own colors
+            "muni" => Theme {
+                clock: rgb(255, 191, 0),
+                updated: rgb(180, 120, 0),
+                line_name: rgb(255, 191, 0),
+                tta: rgb(255, 191, 0),
+                text: rgb(255, 191, 0),
+                no_location: rgb(200, 16, 46),
+                destination: rgb(255, 150, 0),
+                alert: rgb(255, 255, 255),
+                weather: rgb(255, 191, 0),
+                line_colors: HashMap::from(
+                    [
+                        ("E", rgb(102, 102, 102)),
+                        ("F", rgb(240, 230, 140)),
+                        ("J", rgb(250, 166, 52)),
+                        ("K", rgb(86, 155, 190)),
+                        ("L", rgb(146, 39, 143)),
+                        ("M", rgb(0, 136, 81)),
+                        ("N", rgb(0, 91, 149)),
+                        ("S", rgb(255, 204, 0)),
+                        ("T", rgb(191, 43, 69)),
+                    ]
+                    .map(|(line, color)| (line.to_string(), color)),
+                ),
+                ..Theme::default_colors()
+            },
+            // dim reds only, easy on the eyes in a dark room
+            "night_red" => Theme {
+                clock: rgb(120, 0, 0),
+                updated: rgb(60, 0, 0),
+                line_name: rgb(160, 10, 0),
+                tta: rgb(200, 20, 0),
+                tta_missed: rgb(50, 0, 0),
+                tta_leave_now: rgb(255, 60, 0),
+                text: rgb(120, 0, 0),
+                no_location: rgb(255, 0, 0),
+                occupancy_seats: rgb(60, 0, 0),
+                occupancy_standing: rgb(120, 0, 0),
+                occupancy_full: rgb(200, 0, 0),
+                destination: rgb(100, 10, 0),
+                alert: rgb(255, 40, 0),
+                status_error: rgb(255, 0, 0),
+                page_indicator: rgb(150, 0, 0),
+                page_indicator_dim: rgb(30, 0, 0),
+                weather: rgb(120, 0, 0),
+                weather_sun: rgb(160, 10, 0),
+                weather_cloud: rgb(60, 0, 0),
+                weather_rain: rgb(200, 20, 0),
+                line_colors: HashMap::new(),
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    // The colors the board has always used
+    fn default_colors() -> Theme {
+        Theme {
+            clock: rgb(255, 255, 255),
+            updated: rgb(0, 0, 150),
+            line_name: rgb(255, 255, 20),
+            tta: rgb(255, 140, 0),
+            tta_missed: rgb(255, 0, 0),
+            tta_leave_now: rgb(0, 200, 0),
+            text: rgb(0, 127, 255),
+            no_location: rgb(255, 0, 0),
+            occupancy_seats: rgb(0, 200, 0),
+            occupancy_standing: rgb(255, 140, 0),
+            occupancy_full: rgb(255, 0, 0),
+            destination: rgb(0, 200, 120),
+            alert: rgb(255, 200, 0),
+            status_error: rgb(255, 0, 0),
+            page_indicator: rgb(255, 255, 255),
+            page_indicator_dim: rgb(40, 40, 40),
+            weather: rgb(255, 255, 255),
+            weather_sun: rgb(255, 200, 0),
+            weather_cloud: rgb(150, 150, 150),
+            weather_rain: rgb(0, 127, 255),
+            line_colors: HashMap::new(),
+        }
+    }
+
+    // Color by its key in COLOR_KEYS
+    pub fn color_mut(&mut self, key: &str) -> Option<&mut LedColor> {
+        let color = match key {
+            "clock" => &mut self.clock,
+            "updated" => &mut self.updated,
+            "line_name" => &mut self.line_name,
+            "tta" => &mut self.tta,
+            "tta_missed" => &mut self.tta_missed,
+            "tta_leave_now" => &mut self.tta_leave_now,
+            "text" => &mut self.text,
+            "no_location" => &mut self.no_location,
+            "occupancy_seats" => &mut self.occupancy_seats,
+            "occupancy_standing" => &mut self.occupancy_standing,
+            "occupancy_full" => &mut self.occupancy_full,
+            "destination" => &mut self.destination,
+            "alert" => &mut self.alert,
+            "status_error" => &mut self.status_error,
+            "page_indicator" => &mut self.page_indicator,
+            "page_indicator_dim" => &mut self.page_indicator_dim,
+            "weather" => &mut self.weather,
+            "weather_sun" => &mut self.weather_sun,
+            "weather_cloud" => &mut self.weather_cloud,
+            "weather_rain" => &mut self.weather_rain,
+            _ => return None,
+        };
+        Some(color)
+    }
+
+    pub fn line_name_color(&self, line: &str) -> LedColor {
+        self.line_colors.get(line).copied().unwrap_or(self.line_name)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::default_colors()
+    }
+}
+
+// The presets, for configs without a themes section
+pub fn preset_themes() -> HashMap<String, Theme> {
+    PRESETS
+        .iter()
+        .filter_map(|name| Some((name.to_string(), Theme::preset(name)?)))
+        .collect()
+}
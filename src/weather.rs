@@ -0,0 +1,687 @@
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::{WeatherConfig, WeatherProviderConfig};
+
+pub const OPEN_METEO_URL: &str = "https://api.open-meteo.com/v1/forecast";
+pub const NWS_URL: &str = "https://api.weather.gov";
+// api.weather.gov turns away requests without one
+const USER_AGENT: &str = "transit_rust (github.com/APuzyk/transit-led-display)";
+// Longest wait before trying again after a failed fetch
+const RETRY: Duration = Duration::from_secs(300);
+
+// Picks the icon
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Conditions {
+    Clear,
+    PartlyCloudy,
+    Cloudy,
+    Fog,
+    Rain,
+    Snow,
+    Storm,
+}
+
+// The weather right now, the same from every provider
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Forecast {
+    // degrees Celsius, shown in the configured unit
+    pub temperature: f64,
+    pub conditions: Conditions,
+    // highest percent chance of rain over the next few hours
+    #[serde(default)]
+    pub rain_chance: Option<u8>,
+    // a clear sky shows the moon rather than the sun
+    #[serde(default)]
+    pub night: bool,
+}
+
+// A forecast and when it was fetched
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeatherReport {
+    pub forecast: Forecast,
+    pub fetched: DateTime<Utc>,
+}
+
+impl WeatherReport {
+    // Too old to go by, the panel is left off rather than show it
+    pub fn is_stale(&self, now: DateTime<Utc>, stale_mins: u64) -> bool {
+        now.signed_duration_since(self.fetched).num_minutes() >= stale_mins as i64
+    }
+}
+
+// As the report is kept in the cache file
+#[derive(Serialize, Deserialize)]
+struct CachedReport {
+    // unix seconds
+    fetched: i64,
+    #[serde(flatten)]
+    forecast: Forecast,
+}
+
+pub type ForecastFuture<'a> = Pin<Box<dyn Future<Output = Result<Forecast, String>> + Send + 'a>>;
+
+// Somewhere to get the weather from
+pub trait ForecastProvider: Send + Sync {
+    fn forecast<'a>(&'a self, client: &'a Client) -> ForecastFuture<'a>;
+}
+
+// open-meteo.com, anywhere in the world and no key needed
+pub struct OpenMeteo {
+    url: String,
+    latitude: f64,
+    longitude: f64,
+    rain_hours: u32,
+}
+
+impl OpenMeteo {
+    pub fn new(url: &str, latitude: f64, longitude: f64, rain_hours: u32) -> Self {
+        OpenMeteo {
+            url: url.to_string(),
+            latitude,
+            longitude,
+            rain_hours,
+        }
+    }
+}
+
+impl ForecastProvider for OpenMeteo {
+    fn forecast<'a>(&'a self, client: &'a Client) -> ForecastFuture<'a> {
+        Box::pin(async move {
+            let query = [
+                ("latitude", self.latitude.to_string()),
+                ("longitude", self.longitude.to_string()),
+                ("current", "temperature_2m,weather_code,is_day".to_string()),
+                ("hourly", "precipitation_probability".to_string()),
+                ("forecast_hours", self.rain_hours.to_string()),
+                ("timezone", "UTC".to_string()),
+            ];
+            let response = get_json(client.get(&self.url).query(&query)).await?;
+            open_meteo_forecast(&response)
+        })
+    }
+}
+
+// The hourly chances only cover the next rain_hours, that's all that's asked for
+fn open_meteo_forecast(response: &Value) -> Result<Forecast, String> {
+    let current = &response["current"];
+    let temperature = current["temperature_2m"]
+        .as_f64()
+        .ok_or("response has no current temperature_2m")?;
+    let code = current["weather_code"].as_u64().ok_or("response has no current weather_code")?;
+    Ok(Forecast {
+        temperature,
+        conditions: wmo_conditions(code),
+        rain_chance: highest_chance(
+            response["hourly"]["precipitation_probability"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(Value::as_f64),
+        ),
+        night: current["is_day"].as_u64() == Some(0),
+    })
+}
+
+// WMO weather interpretation codes, as Open-Meteo gives them
+fn wmo_conditions(code: u64) -> Conditions {
+    match code {
+        0 | 1 => Conditions::Clear,
+        2 => Conditions::PartlyCloudy,
+        45 | 48 => Conditions::Fog,
+        51..=67 | 80..=82 => Conditions::Rain,
+        71..=77 | 85 | 86 => Conditions::Snow,
+        95..=99 => Conditions::Storm,
+        _ => Conditions::Cloudy,
+    }
+}
+
+// weather.gov, the US National Weather Service, only covers the US
+pub struct Nws {
+    url: String,
+    latitude: f64,
+    longitude: f64,
+    rain_hours: u32,
+    // hourly forecast URL for the location, looked up on the first fetch
+    forecast_url: Mutex<Option<String>>,
+}
+
+impl Nws {
+    pub fn new(url: &str, latitude: f64, longitude: f64, rain_hours: u32) -> Self {
+        Nws {
+            url: url.trim_end_matches('/').to_string(),
+            latitude,
+            longitude,
+            rain_hours,
+            forecast_url: Mutex::new(None),
+        }
+    }
+
+    async fn forecast_url(&self, client: &Client) -> Result<String, String> {
+        if let Some(url) = self.forecast_url.lock().unwrap().clone() {
+            return Ok(url);
+        }
+        // more than 4 decimal places gets a redirect
+        let points = format!("{}/points/{:.4},{:.4}", self.url, self.latitude, self.longitude);
+        let response = get_json(nws_request(client, &points)).await?;
+        let url = response["properties"]["forecastHourly"]
+            .as_str()
+            .ok_or("location has no hourly forecast")?
+            .to_string();
+        debug!("Hourly forecast for {},{} is at {}", self.latitude, self.longitude, url);
+        *self.forecast_url.lock().unwrap() = Some(url.clone());
+        Ok(url)
+    }
+}
+
+impl ForecastProvider for Nws {
+    fn forecast<'a>(&'a self, client: &'a Client) -> ForecastFuture<'a> {
+        Box::pin(async move {
+            let url = self.forecast_url(client).await?;
+            let response = get_json(nws_request(client, &url)).await?;
+            nws_forecast(&response, self.rain_hours)
+        })
+    }
+}
+
+// From an hourly forecast, the first period is the current hour
+fn nws_forecast(response: &Value, rain_hours: u32) -> Result<Forecast, String> {
+    let periods = response["properties"]["periods"]
+        .as_array()
+        .filter(|periods| !periods.is_empty())
+        .ok_or("response has no forecast periods")?;
+    let now = &periods[0];
+    let temperature = now["temperature"].as_f64().ok_or("forecast has no temperature")?;
+    let temperature = match now["temperatureUnit"].as_str() {
+        Some("C") => temperature,
+        _ => (temperature - 32.0) * 5.0 / 9.0,
+    };
+    Ok(Forecast {
+        temperature,
+        conditions: nws_conditions(now["shortForecast"].as_str().unwrap_or_default()),
+        rain_chance: highest_chance(
+            periods
+                .iter()
+                .take(rain_hours as usize)
+                .map(|period| period["probabilityOfPrecipitation"]["value"].as_f64()),
+        ),
+        night: now["isDaytime"].as_bool() == Some(false),
+    })
+}
+
+fn nws_request(client: &Client, url: &str) -> reqwest::RequestBuilder {
+    client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .header(reqwest::header::ACCEPT, "application/geo+json")
+}
+
+// From the short forecast text, like "Chance Light Rain" or "Mostly Sunny"
+fn nws_conditions(forecast: &str) -> Conditions {
+    let forecast = forecast.to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|word| forecast.contains(word));
+    if has(&["thunder"]) {
+        Conditions::Storm
+    } else if has(&["snow", "sleet", "flurries", "blizzard", "ice"]) {
+        Conditions::Snow
+    } else if has(&["rain", "showers", "drizzle"]) {
+        Conditions::Rain
+    } else if has(&["fog", "haze", "smoke"]) {
+        Conditions::Fog
+    } else if has(&["mostly cloudy", "overcast"]) || forecast.trim() == "cloudy" {
+        Conditions::Cloudy
+    } else if has(&["partly", "cloud"]) {
+        Conditions::PartlyCloudy
+    } else {
+        Conditions::Clear
+    }
+}
+
+// A JSON file, for trying the panel out or for a script to keep up to date.
+// Either the same fields as a Forecast:
+// {"temperature": 12.5, "conditions": "rain", "rain_chance": 80}
+// or a response saved from Open-Meteo or an NWS hourly forecast.
+pub struct ForecastFile {
+    path: PathBuf,
+    rain_hours: u32,
+}
+
+impl ForecastFile {
+    pub fn new(path: PathBuf, rain_hours: u32) -> Self {
+        ForecastFile { path, rain_hours }
+    }
+
+    fn read(&self) -> Result<Forecast, String> {
+        let contents = fs::read_to_string(&self.path).map_err(|e| e.to_string())?;
+        let value: Value = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        if value["current"].is_object() {
+            open_meteo_forecast(&value)
+        } else if value["properties"]["periods"].is_array() {
+            nws_forecast(&value, self.rain_hours)
+        } else {
+            serde_json::from_value(value).map_err(|e| e.to_string())
+        }
+    }
+}
+
+impl ForecastProvider for ForecastFile {
+    fn forecast<'a>(&'a self, _client: &'a Client) -> ForecastFuture<'a> {
+        Box::pin(async move { self.read().map_err(|e| format!("{}: {}", self.path.display(), e)) })
+    }
+}
+
+pub fn forecast_provider(config: &WeatherProviderConfig) -> Box<dyn ForecastProvider> {
+    match config {
+        WeatherProviderConfig::OpenMeteo {
+            url,
+            latitude,
+            longitude,
+            rain_hours,
+        } => Box::new(OpenMeteo::new(url, *latitude, *longitude, *rain_hours)),
+        WeatherProviderConfig::Nws {
+            url,
+            latitude,
+            longitude,
+            rain_hours,
+        } => Box::new(Nws::new(url, *latitude, *longitude, *rain_hours)),
+        WeatherProviderConfig::File { path, rain_hours } => {
+            Box::new(ForecastFile::new(path.clone(), *rain_hours))
+        }
+    }
+}
+
+async fn get_json(request: reqwest::RequestBuilder) -> Result<Value, String> {
+    let response = request.send().await.and_then(|response| response.error_for_status());
+    let response = response.map_err(|e| e.without_url().to_string())?;
+    response.json().await.map_err(|e| e.without_url().to_string())
+}
+
+// Percent chances, some of which may be missing
+fn highest_chance(chances: impl Iterator<Item = Option<f64>>) -> Option<u8> {
+    chances.flatten().map(|chance| chance.clamp(0.0, 100.0).round() as u8).max()
+}
+
+// The last forecast, fetched from the provider every refresh and kept in the
+// cache file so restarts and reloads don't fetch it again before it's due
+pub struct Weather {
+    provider: Box<dyn ForecastProvider>,
+    refresh: Duration,
+    cache: PathBuf,
+    report: Option<WeatherReport>,
+    failed_at: Option<DateTime<Utc>>,
+}
+
+impl Weather {
+    pub fn new(provider: Box<dyn ForecastProvider>, refresh: Duration, cache: PathBuf) -> Self {
+        let report = read_cache(&cache);
+        Weather {
+            provider,
+            refresh,
+            cache,
+            report,
+            failed_at: None,
+        }
+    }
+
+    pub fn from_config(config: &WeatherConfig) -> Self {
+        Weather::new(
+            forecast_provider(config.provider()),
+            Duration::from_secs(config.refresh_mins() * 60),
+            config.cache().to_path_buf(),
+        )
+    }
+
+    pub fn report(&self) -> Option<&WeatherReport> {
+        self.report.as_ref()
+    }
+
+    // How long until the next fetch, zero when one is due
+    pub fn next_fetch(&self, now: DateTime<Utc>) -> Duration {
+        let since = |at: DateTime<Utc>| (now - at).to_std().unwrap_or_default();
+        let mut wait = match &self.report {
+            Some(report) => self.refresh.saturating_sub(since(report.fetched)),
+            None => Duration::ZERO,
+        };
+        // a provider that's down is tried again sooner than every refresh
+        if let Some(failed_at) = self.failed_at {
+            wait = wait.max(self.refresh.min(RETRY).saturating_sub(since(failed_at)));
+        }
+        wait
+    }
+
+    // Fetch from the provider, keeping the last report when that fails
+    pub async fn fetch(&mut self, client: &Client) -> Option<&WeatherReport> {
+        match self.provider.forecast(client).await {
+            Ok(forecast) => {
+                debug!("Fetched weather: {:?}", forecast);
+                let report = WeatherReport {
+                    forecast,
+                    fetched: Utc::now(),
+                };
+                if let Err(e) = write_cache(&self.cache, &report) {
+                    warn!("Unable to cache the weather in {}: {}", self.cache.display(), e);
+                }
+                self.report = Some(report);
+                self.failed_at = None;
+            }
+            Err(e) => {
+                warn!("Unable to get the weather: {}", e);
+                self.failed_at = Some(Utc::now());
+            }
+        }
+        self.report.as_ref()
+    }
+}
+
+fn read_cache(cache: &Path) -> Option<WeatherReport> {
+    let contents = fs::read_to_string(cache).ok()?;
+    match serde_json::from_str::<CachedReport>(&contents) {
+        Ok(cached) => Some(WeatherReport {
+            forecast: cached.forecast,
+            fetched: DateTime::from_timestamp(cached.fetched, 0)?,
+        }),
+        Err(e) => {
+            warn!("Ignoring weather cache {}: {}", cache.display(), e);
+            None
+        }
+    }
+}
+
+fn write_cache(cache: &Path, report: &WeatherReport) -> std::io::Result<()> {
+    if let Some(dir) = cache.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let cached = CachedReport {
+        fetched: report.fetched.timestamp(),
+        forecast: report.forecast.clone(),
+    };
+    fs::write(cache, serde_json::to_string(&cached)?)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    // Whole degrees, like "54°"
+    pub fn format(&self, celsius: f64) -> String {
+        let degrees = match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        };
+        format!("{}°", degrees.round() as i64)
+    }
+}
+
+pub const ICON_SIZE: i32 = 7;
+
+// Part of an icon, each drawn in its own theme color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IconPixel {
+    // sun, moon and lightning
+    Sun,
+    Cloud,
+    // rain and snow
+    Rain,
+}
+
+// Pixel art for the conditions: S sun or moon, C cloud, R rain
+fn icon(conditions: Conditions, night: bool) -> [&'static str; ICON_SIZE as usize] {
+    match (conditions, night) {
+        (Conditions::Clear, false) => [
+            "   S   ",
+            " S   S ",
+            "  SSS  ",
+            "S SSS S",
+            "  SSS  ",
+            " S   S ",
+            "   S   ",
+        ],
+        (Conditions::Clear, true) => [
+            "  SSS  ",
+            " SS    ",
+            "SS     ",
+            "SS     ",
+            "SS    S",
+            " SSSSS ",
+            "  SSS  ",
+        ],
+        (Conditions::PartlyCloudy, true) => [
+            "    SS ",
+            "   S   ",
+            "  CS  S",
+            " CCCSS ",
+            "CCCCCCC",
+            "CCCCCCC",
+            "       ",
+        ],
+        (Conditions::PartlyCloudy, false) => [
+            "    S S",
+            "   SSS ",
+            "  CCSSS",
+            " CCCCS ",
+            "CCCCCCC",
+            "CCCCCCC",
+            "       ",
+        ],
+        (Conditions::Cloudy, _) => [
+            "       ",
+            "   CC  ",
+            " CCCCC ",
+            "CCCCCCC",
+            "CCCCCCC",
+            " CCCCC ",
+            "       ",
+        ],
+        (Conditions::Fog, _) => [
+            "       ",
+            "CCCCCC ",
+            "       ",
+            " CCCCCC",
+            "       ",
+            "CCCCCC ",
+            "       ",
+        ],
+        (Conditions::Rain, _) => [
+            "  CCC  ",
+            " CCCCC ",
+            "CCCCCCC",
+            "       ",
+            "R  R  R",
+            " R  R  ",
+            "R  R  R",
+        ],
+        (Conditions::Snow, _) => [
+            "  CCC  ",
+            " CCCCC ",
+            "CCCCCCC",
+            "       ",
+            "R R R R",
+            "       ",
+            " R R R ",
+        ],
+        (Conditions::Storm, _) => [
+            "  CCC  ",
+            " CCCCC ",
+            "CCCCCCC",
+            "   S   ",
+            "  SS   ",
+            "   SS  ",
+            "   S   ",
+        ],
+    }
+}
+
+// Pixels of an icon that are lit, as (x, y, part)
+pub fn icon_pixels(conditions: Conditions, night: bool) -> Vec<(i32, i32, IconPixel)> {
+    let mut pixels = Vec::new();
+    for (y, row) in icon(conditions, night).iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let pixel = match c {
+                'S' => IconPixel::Sun,
+                'C' => IconPixel::Cloud,
+                'R' => IconPixel::Rain,
+                _ => continue,
+            };
+            pixels.push((x as i32, y as i32, pixel));
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/weather").join(name)
+    }
+
+    fn forecast(temperature: f64) -> Forecast {
+        Forecast {
+            temperature,
+            conditions: Conditions::Cloudy,
+            rain_chance: None,
+            night: false,
+        }
+    }
+
+    #[test]
+    fn reads_a_saved_open_meteo_response() {
+        let forecast = ForecastFile::new(testdata("open_meteo.json"), 3).read().unwrap();
+        assert_eq!(
+            forecast,
+            Forecast {
+                temperature: 16.4,
+                conditions: Conditions::Rain,
+                rain_chance: Some(70),
+                night: false,
+            }
+        );
+    }
+
+    #[test]
+    fn reads_a_saved_nws_hourly_forecast() {
+        let forecast = ForecastFile::new(testdata("nws_hourly.json"), 3).read().unwrap();
+        assert_eq!(
+            forecast,
+            Forecast {
+                temperature: 15.0,
+                conditions: Conditions::Clear,
+                rain_chance: Some(10),
+                night: true,
+            }
+        );
+        // the chance of rain looks as far ahead as it's asked to
+        let further = ForecastFile::new(testdata("nws_hourly.json"), 5).read().unwrap();
+        assert_eq!(further.rain_chance, Some(60));
+    }
+
+    #[tokio::test]
+    async fn reads_a_forecast_file_and_names_it_in_errors() {
+        let dir = TempDir::new("forecast_file");
+        let path = dir.join("forecast.json");
+        fs::write(&path, r#"{"temperature": 12.5, "conditions": "partly_cloudy"}"#).unwrap();
+        let file = ForecastFile::new(path.clone(), 3);
+        let read = file.forecast(&Client::new()).await.unwrap();
+        assert_eq!(read.conditions, Conditions::PartlyCloudy);
+        assert_eq!((read.rain_chance, read.night), (None, false));
+        fs::write(&path, r#"{"temperature": 12.5, "conditions": "sleet"}"#).unwrap();
+        let error = file.forecast(&Client::new()).await.unwrap_err();
+        assert!(error.starts_with(&path.display().to_string()), "{}", error);
+    }
+
+    // Hands out the given results in order
+    struct Scripted(Mutex<Vec<Result<Forecast, String>>>);
+
+    impl ForecastProvider for Scripted {
+        fn forecast<'a>(&'a self, _client: &'a Client) -> ForecastFuture<'a> {
+            let result = self.0.lock().unwrap().remove(0);
+            Box::pin(async move { result })
+        }
+    }
+
+    const REFRESH: Duration = Duration::from_secs(15 * 60);
+
+    fn weather(cache: &Path, results: Vec<Result<Forecast, String>>) -> Weather {
+        Weather::new(Box::new(Scripted(Mutex::new(results))), REFRESH, cache.to_path_buf())
+    }
+
+    #[tokio::test]
+    async fn fetches_again_once_the_refresh_is_up() {
+        let dir = TempDir::new("weather_refresh");
+        let cache = dir.join("weather.json");
+        let mut weather = weather(&cache, vec![Ok(forecast(20.0))]);
+        assert!(weather.report().is_none());
+        assert_eq!(weather.next_fetch(Utc::now()), Duration::ZERO);
+        let fetched = weather.fetch(&Client::new()).await.unwrap().fetched;
+        assert_eq!(weather.next_fetch(fetched), REFRESH);
+        assert_eq!(weather.next_fetch(fetched + chrono::Duration::minutes(10)), Duration::from_secs(300));
+        assert_eq!(weather.next_fetch(fetched + chrono::Duration::minutes(20)), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_last_forecast_and_retries_sooner_after_a_failure() {
+        let dir = TempDir::new("weather_retry");
+        let cache = dir.join("weather.json");
+        let an_hour_ago = Utc::now() - chrono::Duration::hours(1);
+        let cached = WeatherReport {
+            forecast: forecast(20.0),
+            fetched: DateTime::from_timestamp(an_hour_ago.timestamp(), 0).unwrap(),
+        };
+        write_cache(&cache, &cached).unwrap();
+        let mut weather = weather(&cache, vec![Err("down".to_string())]);
+        assert_eq!(weather.next_fetch(Utc::now()), Duration::ZERO);
+        assert_eq!(weather.fetch(&Client::new()).await, Some(&cached));
+        // RETRY after the failure rather than a whole refresh
+        let wait = weather.next_fetch(Utc::now());
+        assert!(wait <= RETRY && wait > RETRY - Duration::from_secs(5), "{:?}", wait);
+    }
+
+    #[tokio::test]
+    async fn picks_up_the_cached_forecast_after_a_restart() {
+        let dir = TempDir::new("weather_restart");
+        let cache = dir.join("weather.json");
+        let fetched = weather(&cache, vec![Ok(forecast(8.5))]).fetch(&Client::new()).await.unwrap().fetched;
+
+        let restarted = weather(&cache, Vec::new());
+        let report = restarted.report().unwrap();
+        assert_eq!(report.forecast, forecast(8.5));
+        // the cache keeps whole seconds
+        assert_eq!(report.fetched.timestamp(), fetched.timestamp());
+        // not due again until a refresh after the cached one was fetched
+        let wait = restarted.next_fetch(report.fetched + chrono::Duration::minutes(5));
+        assert_eq!(wait, Duration::from_secs(10 * 60));
+
+        fs::write(&cache, "not json").unwrap();
+        let corrupt = weather(&cache, Vec::new());
+        assert!(corrupt.report().is_none());
+        assert_eq!(corrupt.next_fetch(Utc::now()), Duration::ZERO);
+    }
+
+    #[test]
+    fn reports_go_stale_after_stale_mins() {
+        let fetched = Utc::now();
+        let report = WeatherReport {
+            forecast: forecast(12.0),
+            fetched,
+        };
+        assert!(!report.is_stale(fetched, 120));
+        assert!(!report.is_stale(fetched + chrono::Duration::minutes(119), 120));
+        assert!(report.is_stale(fetched + chrono::Duration::minutes(120), 120));
+        assert!(report.is_stale(fetched + chrono::Duration::days(2), 120));
+    }
+}